@@ -0,0 +1,49 @@
+//! Measures SPSC throughput between two threads, of single items and of bytes in bulk.
+//!
+//! Run with `cargo run --release --example throughput`, the numbers only mean something with
+//! the two threads on separate cores.
+
+extern crate ring_buffer;
+
+use std::thread;
+use std::time::Instant;
+
+use ring_buffer::RingBuffer;
+
+const ITEMS: u64 = 10_000_000;
+const BYTES: usize = 1 << 30;
+const BLOCK: usize = 4096;
+
+fn main() {
+    let (mut p, mut c) = RingBuffer::new(1024).split();
+    let start = Instant::now();
+    let producer = thread::spawn(move || {
+        for i in 0..ITEMS {
+            p.push(i).unwrap();
+        }
+    });
+    let mut sum = 0;
+    for _ in 0..ITEMS {
+        sum += c.pop().unwrap();
+    }
+    producer.join().unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(sum, ITEMS * (ITEMS - 1) / 2);
+    println!("{:.1} Mitems/s", ITEMS as f64 / elapsed.as_secs_f64() / 1e6);
+
+    let (mut p, mut c) = RingBuffer::<u8>::new(1 << 16).split();
+    let start = Instant::now();
+    let producer = thread::spawn(move || {
+        let block = [1; BLOCK];
+        for _ in 0..BYTES / BLOCK {
+            p.write(&block).unwrap();
+        }
+    });
+    let mut block = [0; BLOCK];
+    for _ in 0..BYTES / BLOCK {
+        c.read_exact_into(&mut block).unwrap();
+    }
+    producer.join().unwrap();
+    let elapsed = start.elapsed();
+    println!("{:.2} GB/s", BYTES as f64 / elapsed.as_secs_f64() / 1e9);
+}
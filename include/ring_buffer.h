@@ -0,0 +1,33 @@
+/* C API of the ring-buffer crate, built with its `ffi` feature. */
+
+#ifndef RING_BUFFER_H
+#define RING_BUFFER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Lockfree SPSC byte ring buffer, one thread may push while another pops. */
+typedef struct RbHandle RbHandle;
+
+/* Creates a buffer holding at least `capacity` bytes, rounded up to a power of two. Returns
+ * NULL if `capacity` is zero or too large, or the storage can't be allocated. */
+RbHandle *rb_create(size_t capacity);
+
+/* Pushes as many of the `len` bytes at `data` as fit, returns the number pushed. */
+size_t rb_push_bytes(RbHandle *rb, const uint8_t *data, size_t len);
+
+/* Pops up to `len` bytes into `buf`, returns the number popped. */
+size_t rb_pop_bytes(RbHandle *rb, uint8_t *buf, size_t len);
+
+/* Frees the buffer along with any bytes left in it, NULL is ignored. */
+void rb_free(RbHandle *rb);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
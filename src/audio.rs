@@ -0,0 +1,234 @@
+#[cfg(feature = "cpal")]
+use cpal::{InputCallbackInfo, OutputCallbackInfo, SizedSample};
+
+use {Consumer as Samples, Producer as SampleProducer, RingBuffer};
+
+/// SPSC ring buffer of interleaved audio samples which only ever transfers whole frames, one
+/// sample per channel, so the consumer never sees a frame missing some of its channels.
+///
+/// Neither half blocks or allocates, so both are fine to call from an audio callback. The
+/// capacity is the number of slots divided by the channel count, and a trailing partial frame
+/// of the samples passed in is never transferred.
+///
+/// With the `cpal` feature the halves turn into cpal stream callbacks, see
+/// `Producer::into_input_callback` and `Consumer::into_output_callback`.
+pub struct AudioRingBuffer<T = f32> {
+    buffer: RingBuffer<T>,
+    channels: usize,
+}
+
+/// What pushing more frames than fit does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overrun {
+    /// Push the frames which fit and drop the rest.
+    DropNewest,
+    /// Drop all of the frames unless they fit, so the consumer only ever sees whole periods of
+    /// a capture device.
+    DropAll,
+}
+
+/// Writing half of a split `AudioRingBuffer`.
+pub struct Producer<T = f32> {
+    producer: SampleProducer<T>,
+    channels: usize,
+}
+
+/// Reading half of a split `AudioRingBuffer`.
+pub struct Consumer<T = f32> {
+    consumer: Samples<T>,
+    channels: usize,
+}
+
+impl<T> AudioRingBuffer<T> {
+    /// Creates a buffer holding at least `frames` frames of `channels` samples each.
+    ///
+    /// Panics if `channels` is zero.
+    pub fn new(frames: usize, channels: usize) -> AudioRingBuffer<T> {
+        assert!(channels > 0, "an audio buffer needs at least one channel");
+        AudioRingBuffer { buffer: RingBuffer::new(frames * channels), channels }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Number of frames the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity() / self.channels
+    }
+
+    /// Splits the buffer into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let (producer, consumer) = self.buffer.split();
+        let channels = self.channels;
+        (Producer { producer, channels }, Consumer { consumer, channels })
+    }
+}
+
+impl<T: Copy> Producer<T> {
+    /// Pushes as many whole frames of the interleaved `samples` as fit, returns the number of
+    /// frames pushed.
+    pub fn push_frames(&mut self, samples: &[T]) -> usize {
+        let frames = (samples.len() / self.channels).min(self.frames_free());
+        // Only this half frees slots up, so all of them fit.
+        self.producer.try_write(&samples[..frames * self.channels]) / self.channels
+    }
+
+    /// Like `push_frames` but handles the frames which don't fit according to `overrun`.
+    pub fn push_frames_or(&mut self, samples: &[T], overrun: Overrun) -> usize {
+        match overrun {
+            Overrun::DropAll if samples.len() / self.channels > self.frames_free() => 0,
+            _ => self.push_frames(samples),
+        }
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl<T: SizedSample + Send + 'static> Producer<T> {
+    /// Turns the producer into a data callback for `build_input_stream`, pushing the captured
+    /// frames and handling those which don't fit according to `overrun`. The stream's channel
+    /// count has to match the buffer's.
+    pub fn into_input_callback(mut self, overrun: Overrun)
+                               -> impl FnMut(&[T], &InputCallbackInfo) + Send + 'static {
+        move |samples, _| {
+            self.push_frames_or(samples, overrun);
+        }
+    }
+}
+
+impl<T> Producer<T> {
+    /// Number of frames which can be pushed without the buffer running full.
+    pub fn frames_free(&self) -> usize {
+        self.producer.slots_free() / self.channels
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Whether the consumer has been dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.producer.is_abandoned()
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Pops as many whole frames as are buffered and fit into `samples`, interleaved, returns
+    /// the number of frames popped. The samples after the last whole frame are left alone.
+    pub fn pop_frames(&mut self, samples: &mut [T]) -> usize {
+        let frames = (samples.len() / self.channels).min(self.len());
+        self.consumer.read_into(&mut samples[..frames * self.channels]) / self.channels
+    }
+
+    /// Like `pop_frames` but fills what is left of `samples` with `silence` when too few frames
+    /// are buffered.
+    pub fn pop_frames_or(&mut self, samples: &mut [T], silence: T) -> usize where T: Copy {
+        let frames = self.pop_frames(samples);
+        for sample in &mut samples[frames * self.channels..] {
+            *sample = silence;
+        }
+        frames
+    }
+
+    /// Number of buffered frames.
+    pub fn len(&self) -> usize {
+        self.consumer.len() / self.channels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Whether the producer has been dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.consumer.is_abandoned()
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl<T: SizedSample + Send + 'static> Consumer<T> {
+    /// Turns the consumer into a data callback for `build_output_stream`, playing the buffered
+    /// frames and silence once they run out. The stream's channel count has to match the
+    /// buffer's.
+    pub fn into_output_callback(mut self)
+                                -> impl FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static {
+        move |samples, _| {
+            self.pop_frames_or(samples, T::EQUILIBRIUM);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AudioRingBuffer, Overrun};
+    use std::thread;
+
+    #[test]
+    fn whole_frames() {
+        let rb = AudioRingBuffer::new(3, 3);
+        // 5 frames of 3 channels fit the 16 slots, the last slot is never used.
+        assert_eq!(rb.capacity(), 5);
+        let (mut p, mut c) = rb.split();
+        assert_eq!(p.push_frames(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]), 2);
+        assert_eq!(p.push_frames(&[0.5; 12]), 3);
+        assert_eq!(p.frames_free(), 0);
+        assert_eq!(p.push_frames(&[0.0; 3]), 0);
+
+        let mut out = [0.0; 8];
+        assert_eq!(c.pop_frames(&mut out), 2);
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 0.0, 0.0]);
+        assert_eq!(c.len(), 3);
+        assert_eq!(p.push_frames(&[9.0; 6]), 2);
+        let mut out = [0.0; 15];
+        assert_eq!(c.pop_frames(&mut out), 5);
+        assert_eq!(&out[9..], &[9.0; 6]);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn underrun_overrun() {
+        let (mut p, mut c) = AudioRingBuffer::new(4, 2).split();
+        assert_eq!(p.push_frames_or(&[1; 6], Overrun::DropAll), 3);
+        assert_eq!(p.push_frames_or(&[2; 4], Overrun::DropAll), 0);
+        assert_eq!(p.push_frames_or(&[2; 4], Overrun::DropNewest), 1);
+        let mut out = [0; 12];
+        assert_eq!(c.pop_frames_or(&mut out[..5], 9), 2);
+        assert_eq!(out[..5], [1, 1, 1, 1, 9]);
+        assert_eq!(c.pop_frames_or(&mut out, 9), 2);
+        assert_eq!(out, [1, 1, 2, 2, 9, 9, 9, 9, 9, 9, 9, 9]);
+    }
+
+    #[test]
+    fn threads() {
+        let (mut p, mut c) = AudioRingBuffer::<u32>::new(8, 6).split();
+        let t = thread::spawn(move || {
+            let samples: Vec<u32> = (0..10_000).flat_map(|f| vec![f; 6]).collect();
+            let mut frame = 0;
+            while frame < 10_000 {
+                // Offer a few frames and a torn one at a time.
+                let end = (frame * 6 + 7 * 6 + 1).min(samples.len());
+                match p.push_frames(&samples[frame * 6..end]) {
+                    0 => thread::yield_now(),
+                    n => frame += n,
+                }
+            }
+        });
+        let mut next = 0;
+        let mut out = [0; 6 * 5];
+        while next < 10_000 {
+            let frames = c.pop_frames(&mut out);
+            if frames == 0 {
+                thread::yield_now();
+            }
+            for frame in out[..frames * 6].chunks(6) {
+                assert_eq!(frame, &[next; 6]);
+                next += 1;
+            }
+        }
+        t.join().unwrap();
+    }
+}
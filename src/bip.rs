@@ -0,0 +1,276 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::slice;
+use sync::atomic::{AtomicUsize, Ordering};
+use alloc::boxed::Box;
+use sync::Arc;
+
+use cache_padded::CachePadded;
+
+/// Lockfree SPSC bip-buffer of bytes.
+///
+/// Writable and readable regions are always contiguous: a grant which doesn't fit before the
+/// end of the storage starts over at the front, and the skipped tail is marked by a watermark
+/// which the reader stops at.
+pub struct BipBuffer {
+    size: usize,
+    bytes: Box<[UnsafeCell<u8>]>,
+    write: CachePadded<AtomicUsize>,
+    read: CachePadded<AtomicUsize>,
+    // End of the readable data while the writer has wrapped around ahead of the reader.
+    watermark: AtomicUsize,
+}
+
+unsafe impl Send for BipBuffer { }
+// Only the split halves can touch the bytes.
+unsafe impl Sync for BipBuffer { }
+
+/// Writing half of a split `BipBuffer`.
+pub struct Producer {
+    buffer: Arc<BipBuffer>,
+}
+
+unsafe impl Send for Producer { }
+
+/// Reading half of a split `BipBuffer`.
+pub struct Consumer {
+    buffer: Arc<BipBuffer>,
+}
+
+unsafe impl Send for Consumer { }
+
+/// Contiguous free region handed out by `Producer::grant`.
+///
+/// Nothing is published if the grant is dropped without committing.
+pub struct WriteGrant<'a> {
+    producer: &'a mut Producer,
+    start: usize,
+    len: usize,
+}
+
+/// Contiguous readable region handed out by `Consumer::read`.
+///
+/// Nothing is released if the grant is dropped without releasing.
+pub struct ReadGrant<'a> {
+    consumer: &'a mut Consumer,
+    start: usize,
+    len: usize,
+}
+
+impl BipBuffer {
+    pub fn new(size: usize) -> BipBuffer {
+        BipBuffer {
+            size,
+            bytes: (0..size).map(|_| UnsafeCell::new(0)).collect(),
+            write: CachePadded::new(AtomicUsize::new(0)),
+            read: CachePadded::new(AtomicUsize::new(0)),
+            watermark: AtomicUsize::new(size),
+        }
+    }
+
+    /// Number of bytes the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Splits the buffer into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer, Consumer) {
+        let buffer = Arc::new(self);
+        (Producer { buffer: buffer.clone() }, Consumer { buffer })
+    }
+
+    fn bytes(&self, start: usize, len: usize) -> *mut u8 {
+        debug_assert!(start + len <= self.size);
+        // Straight to the bytes, the other half may be borrowing its region of them.
+        unsafe { UnsafeCell::raw_get(self.bytes.as_ptr().add(start)) }
+    }
+}
+
+impl Producer {
+    /// Grants exactly `n` contiguous free bytes, or `None` if there is no such region.
+    pub fn grant(&mut self, n: usize) -> Option<WriteGrant<'_>> {
+        let buffer = &*self.buffer;
+        let write = buffer.write.load(Ordering::Relaxed);
+        let read = buffer.read.load(Ordering::Acquire);
+        let start = if write < read {
+            // Already wrapped, the writer must stay strictly behind the reader.
+            if n < read - write { write } else { return None; }
+        } else if n <= buffer.size - write {
+            write
+        } else if n < read {
+            // Start over at the front, leaving the tail unused.
+            0
+        } else {
+            return None;
+        };
+        Some(WriteGrant { producer: self, start, len: n })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.size
+    }
+}
+
+impl<'a> WriteGrant<'a> {
+    /// Publishes the first `used` bytes of the grant.
+    ///
+    /// # Panics
+    ///
+    /// If `used` is larger than the grant.
+    pub fn commit(self, used: usize) {
+        assert!(used <= self.len, "committing more bytes than were granted");
+        let buffer = &*self.producer.buffer;
+        let write = buffer.write.load(Ordering::Relaxed);
+        let new_write = self.start + used;
+        if self.start < write {
+            // Wrapped around, the reader has to stop where the old data ends.
+            buffer.watermark.store(write, Ordering::Release);
+        } else if new_write > buffer.watermark.load(Ordering::Relaxed) {
+            // Writing past the old watermark it no longer applies.
+            buffer.watermark.store(buffer.size, Ordering::Release);
+        }
+        buffer.write.store(new_write, Ordering::Release);
+    }
+}
+
+impl<'a> Deref for WriteGrant<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.producer.buffer.bytes(self.start, self.len), self.len) }
+    }
+}
+
+impl<'a> DerefMut for WriteGrant<'a> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        let bytes = self.producer.buffer.bytes(self.start, self.len);
+        unsafe { slice::from_raw_parts_mut(bytes, self.len) }
+    }
+}
+
+impl Consumer {
+    /// Grants all contiguous readable bytes, or `None` if there are none.
+    pub fn read(&mut self) -> Option<ReadGrant<'_>> {
+        let buffer = &*self.buffer;
+        let mut read = buffer.read.load(Ordering::Relaxed);
+        let write = buffer.write.load(Ordering::Acquire);
+        let watermark = buffer.watermark.load(Ordering::Acquire);
+        if write < read && read == watermark {
+            // Everything before the watermark is read, follow the writer to the front.
+            read = 0;
+            buffer.read.store(0, Ordering::Release);
+        }
+        let end = if write < read { watermark } else { write };
+        if end == read {
+            return None;
+        }
+        Some(ReadGrant { consumer: self, start: read, len: end - read })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.size
+    }
+}
+
+impl<'a> ReadGrant<'a> {
+    /// Hands the first `used` bytes back to the producer.
+    ///
+    /// # Panics
+    ///
+    /// If `used` is larger than the grant.
+    pub fn release(self, used: usize) {
+        assert!(used <= self.len, "releasing more bytes than were granted");
+        self.consumer.buffer.read.store(self.start + used, Ordering::Release);
+    }
+}
+
+impl<'a> Deref for ReadGrant<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.consumer.buffer.bytes(self.start, self.len), self.len) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::BipBuffer;
+    use std::thread;
+
+    #[test]
+    fn grant_commit_read_release() {
+        let (mut p, mut c) = BipBuffer::new(8).split();
+        assert!(c.read().is_none());
+        let mut grant = p.grant(4).unwrap();
+        grant.copy_from_slice(b"abcd");
+        grant.commit(3);
+        let grant = c.read().unwrap();
+        assert_eq!(&*grant, b"abc");
+        grant.release(2);
+        assert_eq!(&*c.read().unwrap(), b"c");
+        assert!(p.grant(9).is_none());
+    }
+
+    #[test]
+    fn wraps_to_stay_contiguous() {
+        let (mut p, mut c) = BipBuffer::new(8).split();
+        p.grant(6).unwrap().commit(6);
+        c.read().unwrap().release(4);
+        // Only two bytes are free at the end, so a grant of three starts over at the front.
+        assert!(p.grant(4).is_none());
+        let mut grant = p.grant(3).unwrap();
+        grant.copy_from_slice(b"xyz");
+        grant.commit(3);
+        assert_eq!(c.read().unwrap().len(), 2);
+        c.read().unwrap().release(2);
+        assert_eq!(&*c.read().unwrap(), b"xyz");
+    }
+
+    #[test]
+    fn huge_grant() {
+        let (mut p, mut c) = BipBuffer::new(8).split();
+        assert!(p.grant(usize::MAX).is_none());
+        p.grant(6).unwrap().commit(6);
+        c.read().unwrap().release(4);
+        p.grant(2).unwrap().commit(2);
+        assert!(p.grant(usize::MAX).is_none());
+        p.grant(2).unwrap().commit(2);
+        // Wrapped around, the free bytes are between the two positions.
+        assert!(p.grant(usize::MAX).is_none());
+        assert_eq!(p.grant(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn threads() {
+        let (mut p, mut c) = BipBuffer::new(64).split();
+        let t = thread::spawn(move || {
+            for i in 0..1000u32 {
+                loop {
+                    if let Some(mut grant) = p.grant(4) {
+                        grant.copy_from_slice(&i.to_le_bytes());
+                        grant.commit(4);
+                        break;
+                    }
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut next = 0u32;
+        while next < 1000 {
+            match c.read() {
+                Some(grant) => {
+                    // Frames are never split since every grant was a whole frame.
+                    assert_eq!(grant.len() % 4, 0);
+                    for frame in grant.chunks(4) {
+                        assert_eq!(frame, next.to_le_bytes());
+                        next += 1;
+                    }
+                    let len = grant.len();
+                    grant.release(len);
+                }
+                None => thread::yield_now(),
+            }
+        }
+        t.join().unwrap();
+    }
+}
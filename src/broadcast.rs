@@ -0,0 +1,297 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+
+use cache_padded::CachePadded;
+use error::{Disconnected, PushError};
+use index;
+use wait::WaitStrategy;
+use waiter::Waiter;
+
+/// Fixed size ring buffer where every subscriber observes every item.
+///
+/// Each `Subscriber` keeps its own read cursor, the producer only reuses a slot once all of
+/// them have moved past it, so pushing blocks on the slowest subscriber. Dropping it disconnects
+/// the subscribers once they have seen the rest of the items.
+pub struct BroadcastRingBuffer<T> {
+    shared: Arc<Shared<T>>,
+    min_read: usize,
+}
+
+/// Reading handle obtained from `BroadcastRingBuffer::subscribe`.
+pub struct Subscriber<T> {
+    shared: Arc<Shared<T>>,
+    cursor: Arc<CachePadded<AtomicUsize>>,
+}
+
+struct Shared<T> {
+    size: usize,
+    items: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Set once every slot has been written, items are kept after being read since subscribers
+    /// clone them, so from then on each slot is initialized.
+    wrapped: AtomicBool,
+    write_pos: CachePadded<AtomicUsize>,
+    cursors: Mutex<Vec<Arc<CachePadded<AtomicUsize>>>>,
+    push_waiter: Waiter,
+    pop_waiter: Waiter,
+    abandoned: AtomicBool,
+}
+
+unsafe impl<T: Send + Sync> Send for Shared<T>{ }
+unsafe impl<T: Send + Sync> Sync for Shared<T>{ }
+
+impl<T> Shared<T> {
+    /// Position of the slowest subscriber, or `write_pos` if there are none.
+    fn min_read(&self, write_pos: usize) -> usize {
+        let lag = self.cursors.lock().unwrap().iter()
+            .map(|c| write_pos.wrapping_sub(c.load(Ordering::Acquire)))
+            .max()
+            .unwrap_or(0);
+        write_pos.wrapping_sub(lag)
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let filled = if *self.wrapped.get_mut() {
+            self.size
+        } else {
+            *self.write_pos.get_mut()
+        };
+        for item in &mut self.items[..filled] {
+            unsafe { item.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+impl<T: Clone> BroadcastRingBuffer<T> {
+    /// Creates a buffer holding at least `size` items, the capacity is rounded up to a power of
+    /// two.
+    pub fn new(size: usize) -> BroadcastRingBuffer<T> {
+        let size = index::capacity(size);
+        let items = (0..size).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect();
+        BroadcastRingBuffer {
+            shared: Arc::new(Shared {
+                size,
+                items,
+                wrapped: AtomicBool::new(false),
+                write_pos: CachePadded::new(AtomicUsize::new(0)),
+                cursors: Mutex::new(Vec::new()),
+                push_waiter: Waiter::new(),
+                pop_waiter: Waiter::new(),
+                abandoned: AtomicBool::new(false),
+            }),
+            min_read: 0,
+        }
+    }
+
+    /// Like `new` but a blocked producer or subscriber waits according to `strategy` instead of
+    /// parking.
+    pub fn with_strategy<S: WaitStrategy + 'static>(size: usize, strategy: S)
+                                                    -> BroadcastRingBuffer<T> {
+        let strategy: Arc<dyn WaitStrategy> = Arc::new(strategy);
+        let mut rb = BroadcastRingBuffer::new(size);
+        {
+            let shared = Arc::get_mut(&mut rb.shared).unwrap();
+            shared.push_waiter = Waiter::with_strategy(strategy.clone());
+            shared.pop_waiter = Waiter::with_strategy(strategy);
+        }
+        rb
+    }
+
+    /// Creates a subscriber which sees every item pushed from now on.
+    pub fn subscribe(&self) -> Subscriber<T> {
+        let mut cursors = self.shared.cursors.lock().unwrap();
+        let write_pos = self.shared.write_pos.load(Ordering::Acquire);
+        let cursor = Arc::new(CachePadded::new(AtomicUsize::new(write_pos)));
+        cursors.push(cursor.clone());
+        Subscriber { shared: self.shared.clone(), cursor }
+    }
+
+    pub fn push(&mut self, item: T) {
+        let shared = &*self.shared;
+        let write_pos = shared.write_pos.load(Ordering::Relaxed);
+        if write_pos.wrapping_sub(self.min_read) == shared.size {
+            shared.push_waiter.wait(|| {
+                write_pos.wrapping_sub(shared.min_read(write_pos)) != shared.size
+            });
+            self.min_read = shared.min_read(write_pos);
+        }
+        self.put(write_pos, item);
+    }
+
+    /// Pushes an item unless the slowest subscriber is a full buffer behind, in which case it is
+    /// handed back.
+    pub fn try_push(&mut self, item: T) -> Result<(), PushError<T>> {
+        let write_pos = self.shared.write_pos.load(Ordering::Relaxed);
+        if write_pos.wrapping_sub(self.min_read) == self.shared.size {
+            self.min_read = self.shared.min_read(write_pos);
+            if write_pos.wrapping_sub(self.min_read) == self.shared.size {
+                return Err(PushError::Full(item));
+            }
+        }
+        self.put(write_pos, item);
+        Ok(())
+    }
+
+    fn put(&mut self, write_pos: usize, item: T) {
+        let shared = &*self.shared;
+        let i = write_pos & (shared.size - 1);
+        let slot = unsafe { &mut *shared.items[i].get() };
+        if shared.wrapped.load(Ordering::Relaxed) {
+            unsafe { slot.assume_init_drop() };
+        } else if i == shared.size - 1 {
+            shared.wrapped.store(true, Ordering::Relaxed);
+        }
+        slot.write(item);
+        shared.write_pos.store(write_pos.wrapping_add(1), Ordering::Release);
+        shared.pop_waiter.notify();
+    }
+
+    /// Number of subscribers currently attached.
+    pub fn subscribers(&self) -> usize {
+        self.shared.cursors.lock().unwrap().len()
+    }
+
+    /// Number of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.shared.size
+    }
+}
+
+impl<T> Drop for BroadcastRingBuffer<T> {
+    fn drop(&mut self) {
+        self.shared.abandoned.store(true, Ordering::Release);
+        self.shared.pop_waiter.notify();
+    }
+}
+
+impl<T: Clone> Subscriber<T> {
+    /// Blocks until an item is available, failing once the producer is gone and every item has
+    /// been seen.
+    pub fn pop(&mut self) -> Result<T, Disconnected> {
+        let shared = &*self.shared;
+        let read_pos = self.cursor.load(Ordering::Relaxed);
+        shared.pop_waiter.wait(|| {
+            shared.write_pos.load(Ordering::Acquire) != read_pos || self.is_abandoned()
+        });
+        self.try_pop().ok_or(Disconnected)
+    }
+
+    pub fn try_pop(&mut self) -> Option<T> {
+        let read_pos = self.cursor.load(Ordering::Relaxed);
+        if self.shared.write_pos.load(Ordering::Acquire) == read_pos {
+            return None;
+        }
+        Some(self.take(read_pos))
+    }
+
+    fn take(&mut self, read_pos: usize) -> T {
+        let shared = &*self.shared;
+        let item = unsafe { (*shared.items[read_pos & (shared.size - 1)].get()).assume_init_ref() };
+        let item = item.clone();
+        self.cursor.store(read_pos.wrapping_add(1), Ordering::Release);
+        shared.push_waiter.notify();
+        item
+    }
+
+    /// Number of items this subscriber has yet to see.
+    pub fn len(&self) -> usize {
+        let write_pos = self.shared.write_pos.load(Ordering::Acquire);
+        write_pos.wrapping_sub(self.cursor.load(Ordering::Relaxed))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the producer has been dropped, there may still be items left to pop.
+    pub fn is_abandoned(&self) -> bool {
+        self.shared.abandoned.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for Subscriber<T> {
+    fn drop(&mut self) {
+        self.shared.cursors.lock().unwrap().retain(|c| !Arc::ptr_eq(c, &self.cursor));
+        self.shared.push_waiter.notify();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BroadcastRingBuffer, Disconnected, PushError};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn every_subscriber_sees_every_item() {
+        let mut rb = BroadcastRingBuffer::new(2);
+        let mut a = rb.subscribe();
+        let mut b = rb.subscribe();
+        assert_eq!(Ok(()), rb.try_push(1));
+        assert_eq!(Ok(()), rb.try_push(2));
+        assert_eq!(Err(PushError::Full(3)), rb.try_push(3));
+        assert_eq!(a.try_pop(), Some(1));
+        assert_eq!(a.try_pop(), Some(2));
+        assert_eq!(a.try_pop(), None);
+        assert_eq!(Err(PushError::Full(3)), rb.try_push(3));
+        assert_eq!(b.try_pop(), Some(1));
+        assert_eq!(Ok(()), rb.try_push(3));
+        assert_eq!(a.pop(), Ok(3));
+        assert_eq!(b.len(), 2);
+    }
+
+    #[test]
+    fn dropped_subscriber_unblocks_producer() {
+        let mut rb = BroadcastRingBuffer::new(1);
+        let a = rb.subscribe();
+        rb.push(1);
+        assert_eq!(Err(PushError::Full(2)), rb.try_push(2));
+        drop(a);
+        assert_eq!(rb.subscribers(), 0);
+        assert_eq!(Ok(()), rb.try_push(2));
+    }
+
+    #[test]
+    fn drops_overwritten_items() {
+        let item = Arc::new(());
+        let mut rb = BroadcastRingBuffer::new(2);
+        let mut a = rb.subscribe();
+        for _ in 0..3 {
+            rb.push(item.clone());
+            drop(a.pop().unwrap());
+        }
+        // Read items stay in their slot until the next lap overwrites them.
+        assert_eq!(Arc::strong_count(&item), 3);
+        drop((rb, a));
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn dropped_producer_disconnects() {
+        let mut rb = BroadcastRingBuffer::new(2);
+        let mut a = rb.subscribe();
+        let t = thread::spawn(move || (a.pop(), a.pop()));
+        rb.push(1);
+        drop(rb);
+        assert_eq!(t.join().unwrap(), (Ok(1), Err(Disconnected)));
+    }
+
+    #[test]
+    fn threads() {
+        let mut rb = BroadcastRingBuffer::new(4);
+        let threads: Vec<_> = (0..3).map(|_| {
+            let mut s = rb.subscribe();
+            thread::spawn(move || (0..500).map(|_| s.pop().unwrap()).collect::<Vec<_>>())
+        }).collect();
+        for i in 0..500 {
+            rb.push(i);
+        }
+        for t in threads {
+            assert_eq!(t.join().unwrap(), (0..500).collect::<Vec<_>>());
+        }
+    }
+}
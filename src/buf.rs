@@ -0,0 +1,63 @@
+use bytes::Buf;
+
+use {Consumer, Producer};
+
+/// The buffered bytes, `chunk` only returns the ones before the wrap point.
+impl Buf for Consumer<u8> {
+    fn remaining(&self) -> usize {
+        self.len()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self.as_slices().0
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        assert!(cnt <= self.len(), "advancing past the buffered bytes");
+        self.discard(cnt);
+    }
+}
+
+impl Producer<u8> {
+    /// Copies as much of `src` as fits and advances it past the copied bytes, returns their
+    /// number.
+    pub fn write_buf<B: Buf>(&mut self, src: &mut B) -> usize {
+        if self.is_abandoned() {
+            return 0;
+        }
+        let mut written = 0;
+        while src.has_remaining() {
+            let (n, len) = {
+                let chunk = src.chunk();
+                (self.buffer.try_write(chunk), chunk.len())
+            };
+            src.advance(n);
+            written += n;
+            if n < len {
+                break;
+            }
+        }
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::RingBuffer;
+    use bytes::Buf;
+
+    #[test]
+    fn buf() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let mut src = (&b"ab"[..]).chain(&b"cdef"[..]);
+        assert_eq!(p.write_buf(&mut src), 4);
+        assert_eq!(src.remaining(), 2);
+        assert_eq!(c.remaining(), 4);
+        assert_eq!(c.get_u16(), u16::from_be_bytes(*b"ab"));
+        assert_eq!(p.write_buf(&mut src), 2);
+        assert!(!src.has_remaining());
+        // The bytes wrap around, so this reads across two chunks.
+        assert_eq!(c.copy_to_bytes(4), &b"cdef"[..]);
+        assert!(!c.has_remaining());
+    }
+}
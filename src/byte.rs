@@ -0,0 +1,184 @@
+use core::mem::MaybeUninit;
+
+use {Consumer as ByteConsumer, NewError, Producer as ByteProducer, RingBuffer};
+
+/// Lockfree SPSC ring buffer of bytes, the type to stream data through.
+///
+/// It is a `RingBuffer<u8>` whose storage is zeroed up front, so both halves can lend out their
+/// part of it as plain byte slices: `Producer::as_mut_slice` the free bytes to read into and
+/// `Consumer::as_slices` the buffered ones. `write` and `read` copy whole runs before and after
+/// the wrap point, and the halves implement `std::io::Write`, `Read` and `BufRead`, and with the
+/// `tokio` feature `AsyncWrite` and `AsyncRead`.
+pub struct ByteRingBuffer {
+    buffer: RingBuffer<u8>,
+}
+
+/// Writing half of a split `ByteRingBuffer`.
+pub struct Producer {
+    producer: ByteProducer<u8>,
+}
+
+/// Reading half of a split `ByteRingBuffer`.
+pub struct Consumer {
+    consumer: ByteConsumer<u8>,
+}
+
+impl ByteRingBuffer {
+    /// Creates a buffer holding at least `size` bytes, the capacity is rounded up to a power of
+    /// two.
+    pub fn new(size: usize) -> ByteRingBuffer {
+        ByteRingBuffer::zeroed(RingBuffer::new(size))
+    }
+
+    /// Like `new` but fails instead of panicking or aborting if `size` is zero or too large, or
+    /// the storage can't be allocated.
+    pub fn try_new(size: usize) -> Result<ByteRingBuffer, NewError> {
+        RingBuffer::try_new(size).map(ByteRingBuffer::zeroed)
+    }
+
+    fn zeroed(buffer: RingBuffer<u8>) -> ByteRingBuffer {
+        // Bytes stay initialized once written, whether they are consumed or not.
+        for i in 0..buffer.size {
+            unsafe { (*buffer.items.slot(i)).write(0) };
+        }
+        ByteRingBuffer { buffer }
+    }
+
+    /// Number of bytes the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Splits the buffer into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer, Consumer) {
+        let (producer, consumer) = self.buffer.split();
+        (Producer { producer }, Consumer { consumer })
+    }
+}
+
+impl Producer {
+    /// Copies as much of `data` as fits, returns the number of bytes written.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        self.producer.try_write(data)
+    }
+
+    /// Borrows the free bytes before the wrap point, fill a prefix of them and `commit` it.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        let capacity = self.capacity();
+        let slots: *mut [MaybeUninit<u8>] = self.producer.write_chunk(capacity).as_mut_slice();
+        // The storage was zeroed up front.
+        unsafe { &mut *(slots as *mut [u8]) }
+    }
+
+    /// Publishes the first `n` bytes of `as_mut_slice`.
+    ///
+    /// # Panics
+    ///
+    /// If `as_mut_slice` is shorter than `n` bytes.
+    pub fn commit(&mut self, n: usize) {
+        unsafe { self.producer.write_chunk(n).commit(n) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.producer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.producer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.producer.is_full()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.producer.slots_free()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.producer.capacity()
+    }
+
+    /// Whether the consumer has been dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.producer.is_abandoned()
+    }
+
+    /// The generic half this wraps, for the operations only `Producer<u8>` offers.
+    pub fn as_inner(&mut self) -> &mut ByteProducer<u8> {
+        &mut self.producer
+    }
+}
+
+impl Consumer {
+    /// Copies as many buffered bytes as fit into `buf`, returns the number of bytes read.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        self.consumer.read_into(buf)
+    }
+
+    /// Borrows the buffered bytes, the second slice holds those wrapped around to the front.
+    pub fn as_slices(&self) -> (&[u8], &[u8]) {
+        self.consumer.as_slices()
+    }
+
+    /// Releases the first `n` buffered bytes back to the producer.
+    ///
+    /// # Panics
+    ///
+    /// If fewer than `n` bytes are buffered.
+    pub fn consume(&mut self, n: usize) {
+        assert!(n <= self.len(), "consuming more bytes than are buffered");
+        self.consumer.discard(n);
+    }
+
+    pub fn len(&self) -> usize {
+        self.consumer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.consumer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.consumer.is_full()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.consumer.capacity()
+    }
+
+    /// Whether the producer has been dropped or the buffer closed.
+    pub fn is_abandoned(&self) -> bool {
+        self.consumer.is_abandoned()
+    }
+
+    /// The generic half this wraps, for the operations only `Consumer<u8>` offers.
+    pub fn as_inner(&mut self) -> &mut ByteConsumer<u8> {
+        &mut self.consumer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ByteRingBuffer;
+
+    #[test]
+    fn write_read() {
+        let (mut p, mut c) = ByteRingBuffer::new(8).split();
+        assert_eq!(p.write(b"hello"), 5);
+        let mut buf = [0; 3];
+        assert_eq!(c.read(&mut buf), 3);
+        assert_eq!(&buf, b"hel");
+        // Wraps around, only the free bytes before the end are one slice.
+        assert_eq!(p.write(b", world"), 6);
+        assert_eq!(c.as_slices(), (&b"lo, w"[..], &b"orl"[..]));
+        c.consume(6);
+        assert_eq!(c.as_slices(), (&b"rl"[..], &b""[..]));
+        assert_eq!(p.as_mut_slice().len(), 5);
+        p.as_mut_slice()[..2].copy_from_slice(b"!?");
+        p.commit(2);
+        let mut buf = [0; 8];
+        assert_eq!(c.read(&mut buf), 4);
+        assert_eq!(&buf[..4], b"rl!?");
+    }
+}
@@ -0,0 +1,211 @@
+//! `std::sync::mpsc` style front end of `RingBuffer`.
+//!
+//! `channel` returns a `Sender` and `Receiver` with the method names of `std::sync::mpsc`,
+//! failing with its very error types, which are re-exported here. Code written against a
+//! channel switches to the ring buffer by importing from `ring_buffer::channel` instead of
+//! `std::sync::mpsc` and passing a capacity. Unlike `std`'s the channel is bounded and has
+//! exactly one sender, `Sender` isn't `Clone`.
+//!
+//! The errors mean the same as in `std`: sends fail with the item handed back once the
+//! receiver is gone, receives only fail as disconnected once the sender is gone and everything
+//! it sent has been received.
+
+pub use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+
+use std::time::{Duration, Instant};
+
+use error::{PopTimeoutError, PushError};
+use {Consumer, IntoIter, Producer, RingBuffer};
+
+/// Creates a channel buffering at least `cap` items, the capacity is rounded up to a power of
+/// two.
+pub fn channel<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    let (producer, consumer) = RingBuffer::new(cap).split();
+    (Sender { producer }, Receiver { consumer })
+}
+
+/// Sending half of a `channel`.
+pub struct Sender<T> {
+    producer: Producer<T>,
+}
+
+/// Receiving half of a `channel`.
+pub struct Receiver<T> {
+    consumer: Consumer<T>,
+}
+
+/// Blocking iterator returned by `Receiver::iter`, ending once the sender is gone.
+pub struct Iter<'a, T: 'a> {
+    receiver: &'a Receiver<T>,
+}
+
+/// Iterator returned by `Receiver::try_iter`, ending at the first empty poll.
+pub struct TryIter<'a, T: 'a> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Sender<T> {
+    /// Blocks until there is room for `t`, handing it back if the receiver is gone.
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        self.producer.push_until(t, None).map_err(|e| SendError(e.into_inner()))
+    }
+
+    /// Sends `t` only if there is room for it right now.
+    pub fn try_send(&self, t: T) -> Result<(), TrySendError<T>> {
+        if self.producer.is_abandoned() {
+            return Err(TrySendError::Disconnected(t));
+        }
+        self.producer.buffer.try_push(t).map_err(|e| match e {
+            PushError::Full(t) => TrySendError::Full(t),
+            PushError::Disconnected(t) => TrySendError::Disconnected(t),
+        })
+    }
+
+    /// Number of items which can be buffered.
+    pub fn capacity(&self) -> usize {
+        self.producer.capacity()
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until an item arrives, failing once the sender is gone and everything it sent
+    /// has been received.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.consumer.pop_until(None).map_err(|_| RecvError)
+    }
+
+    /// Like `recv` but gives up once `timeout` has elapsed.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Like `recv` but gives up once `deadline` has passed.
+    pub fn recv_deadline(&self, deadline: Instant) -> Result<T, RecvTimeoutError> {
+        self.recv_until(Some(deadline))
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        self.consumer.pop_until(deadline).map_err(|e| match e {
+            PopTimeoutError::Timeout => RecvTimeoutError::Timeout,
+            PopTimeoutError::Disconnected | PopTimeoutError::Closed => {
+                RecvTimeoutError::Disconnected
+            }
+        })
+    }
+
+    /// Receives an item only if one is buffered right now.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let buffer = &*self.consumer.buffer;
+        if let Some(item) = buffer.try_pop() {
+            return Ok(item);
+        }
+        if !self.consumer.is_abandoned() {
+            return Err(TryRecvError::Empty);
+        }
+        // The sender may have sent right before it was dropped.
+        buffer.try_pop().ok_or(TryRecvError::Disconnected)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { receiver: self }
+    }
+
+    /// Number of items which can be buffered.
+    pub fn capacity(&self) -> usize {
+        self.consumer.capacity()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'a, T> Iterator for TryIter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        self.consumer.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use channel::{channel, RecvTimeoutError, TryRecvError, TrySendError};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn send_recv() {
+        let (tx, rx) = channel(4);
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                tx.send(i).unwrap();
+            }
+        });
+        assert_eq!(rx.iter().collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn try_send_recv() {
+        let (tx, rx) = channel(1);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        tx.try_send(1).unwrap();
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+        assert_eq!(rx.recv_timeout(Duration::from_millis(1)), Ok(1));
+        assert_eq!(rx.recv_timeout(Duration::from_millis(1)), Err(RecvTimeoutError::Timeout));
+        tx.try_send(3).unwrap();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn recv_timeout_disconnected() {
+        let (tx, rx) = channel(2);
+        tx.send(1).unwrap();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            drop(tx);
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok(1));
+        // Woken by the sender going away long before the timeout.
+        assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Err(RecvTimeoutError::Disconnected));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn send_disconnected() {
+        let (tx, rx) = channel(1);
+        drop(rx);
+        assert_eq!(tx.send(1).unwrap_err().0, 1);
+        assert_eq!(tx.try_send(2), Err(TrySendError::Disconnected(2)));
+    }
+}
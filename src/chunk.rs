@@ -0,0 +1,195 @@
+use core::mem::{self, MaybeUninit};
+use core::slice;
+use sync::atomic::Ordering;
+
+use index;
+use {Consumer, Producer, Release, Unpublished};
+
+/// Free slots handed out by `Producer::write_chunk`, fill them in place and publish them with
+/// `commit`.
+///
+/// Nothing is published if the chunk is dropped without committing, items written into it
+/// through `as_mut_slice` are leaked.
+pub struct WriteChunk<'a, T: 'a> {
+    producer: &'a mut Producer<T>,
+    write_pos: usize,
+    len: usize,
+}
+
+/// Buffered items borrowed by `Consumer::read_chunk`, process them in place and release them
+/// with `commit`.
+///
+/// Nothing is popped if the chunk is dropped without committing.
+pub struct ReadChunk<'a, T: 'a> {
+    consumer: &'a mut Consumer<T>,
+    read_pos: usize,
+    len: usize,
+}
+
+impl<T> Producer<T> {
+    /// Borrows up to `n` free slots which are contiguous in the storage, the chunk may be
+    /// shorter when the buffer is nearly full or the free slots wrap around.
+    pub fn write_chunk(&mut self, n: usize) -> WriteChunk<'_, T> {
+        let buffer = &*self.buffer;
+        let write_pos = buffer.write.pos.load(Ordering::Relaxed);
+        let read_pos = buffer.read.pos.load(Ordering::Acquire);
+        buffer.write.cached.set(read_pos);
+        let free = buffer.size - index::distance(write_pos, read_pos, buffer.size);
+        let contiguous = buffer.size - index::index(write_pos, buffer.size);
+        let len = n.min(free).min(contiguous);
+        WriteChunk { producer: self, write_pos, len }
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Borrows up to `n` buffered items which are contiguous in the storage, the chunk may be
+    /// shorter when fewer are buffered or they wrap around.
+    pub fn read_chunk(&mut self, n: usize) -> ReadChunk<'_, T> {
+        let buffer = &*self.buffer;
+        let read_pos = buffer.read.pos.load(Ordering::Relaxed);
+        let write_pos = buffer.write.pos.load(Ordering::Acquire);
+        buffer.read.cached.set(write_pos);
+        let available = index::distance(write_pos, read_pos, buffer.size);
+        let contiguous = buffer.size - index::index(read_pos, buffer.size);
+        let len = n.min(available).min(contiguous);
+        ReadChunk { consumer: self, read_pos, len }
+    }
+}
+
+impl<'a, T> WriteChunk<'a, T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The free slots, write to a prefix of them before committing it.
+    pub fn as_mut_slice(&mut self) -> &mut [MaybeUninit<T>] {
+        let buffer = &*self.producer.buffer;
+        unsafe {
+            let start = index::index(self.write_pos, buffer.size);
+            slice::from_raw_parts_mut(buffer.items.slot(start), self.len)
+        }
+    }
+
+    /// Publishes the first `n` slots to the consumer.
+    ///
+    /// # Safety
+    ///
+    /// The first `n` slots must have been initialized through `as_mut_slice`.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is larger than the chunk.
+    pub unsafe fn commit(self, n: usize) {
+        assert!(n <= self.len, "committing more slots than the chunk holds");
+        let buffer = &*self.producer.buffer;
+        buffer.publish(self.write_pos, n);
+    }
+
+    /// Moves items from `iter` into the chunk until either runs out and commits them, returns
+    /// the number of items written.
+    pub fn fill_from_iter<I: IntoIterator<Item = T>>(mut self, iter: I) -> usize {
+        let slots: *mut [MaybeUninit<T>] = self.as_mut_slice();
+        let buffer = &*self.producer.buffer;
+        // Drops what was written if `iter` panics.
+        let mut filled = Unpublished { buffer, write_pos: self.write_pos, written: 0 };
+        for (slot, item) in unsafe { &mut *slots }.iter_mut().zip(iter) {
+            slot.write(item);
+            filled.written += 1;
+        }
+        let n = filled.written;
+        mem::forget(filled);
+        unsafe { self.commit(n) };
+        n
+    }
+}
+
+impl<'a, T> ReadChunk<'a, T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        let buffer = &*self.consumer.buffer;
+        unsafe {
+            let start = index::index(self.read_pos, buffer.size);
+            slice::from_raw_parts(buffer.items.slot(start) as *const T, self.len)
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        let buffer = &*self.consumer.buffer;
+        unsafe {
+            let start = index::index(self.read_pos, buffer.size);
+            slice::from_raw_parts_mut(buffer.items.slot(start) as *mut T, self.len)
+        }
+    }
+
+    /// Drops the first `n` items and hands their slots back to the producer.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is larger than the chunk.
+    pub fn commit(mut self, n: usize) {
+        assert!(n <= self.len, "committing more items than the chunk holds");
+        let items: *mut [T] = &mut self.as_mut_slice()[..n];
+        let buffer = &*self.consumer.buffer;
+        // The drop glue drops all of them even if one panics, so they are popped either way.
+        let _release = Release { buffer, read_pos: self.read_pos, moved: n };
+        unsafe { ::core::ptr::drop_in_place(items) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::RingBuffer;
+
+    #[test]
+    fn write_chunk() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        assert_eq!(p.write_chunk(8).fill_from_iter(0..3), 3);
+        assert_eq!(c.try_read(2), vec![0, 1]);
+        // Only the slot before the wrap point is contiguous.
+        let mut chunk = p.write_chunk(8);
+        assert_eq!(chunk.len(), 1);
+        chunk.as_mut_slice()[0].write(3);
+        unsafe { chunk.commit(1) };
+        assert_eq!(p.write_chunk(8).len(), 2);
+        assert_eq!(c.try_read(4), vec![2, 3]);
+    }
+
+    #[test]
+    fn read_chunk() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        p.write_chunk(4).fill_from_iter(0..4);
+        {
+            let mut chunk = c.read_chunk(8);
+            assert_eq!(chunk.as_slice(), &[0, 1, 2, 3]);
+            chunk.as_mut_slice()[1] = 10;
+            chunk.commit(1);
+        }
+        assert_eq!(c.read_chunk(8).as_slice(), &[10, 2, 3]);
+        assert_eq!(p.write_chunk(8).fill_from_iter(4..8), 1);
+        // The item behind the wrap point needs a second chunk.
+        c.read_chunk(8).commit(3);
+        assert_eq!(c.read_chunk(8).as_slice(), &[4]);
+    }
+
+    #[test]
+    fn uncommitted_chunk() {
+        let (mut p, mut c) = RingBuffer::<u8>::new(2).split();
+        {
+            let mut chunk = p.write_chunk(2);
+            chunk.as_mut_slice()[0].write(1);
+        }
+        assert_eq!(c.try_pop(), None);
+        assert_eq!(p.write_chunk(1).len(), 1);
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use error::PushError;
+use waiter::Waiter;
+
+/// Bounded SPSC queue which conflates items by key: pushing a key which is still waiting to be
+/// popped replaces its value in place instead of taking another slot.
+///
+/// Memory stays bounded by the number of distinct keys in flight and the consumer always gets
+/// the freshest value per key, in the order the keys were first pushed. Unlike the other buffers
+/// this one takes a short lock for every operation.
+pub struct ConflatingRingBuffer<K, V> {
+    size: usize,
+    queue: Mutex<Queue<K, V>>,
+    push_waiter: Waiter,
+    pop_waiter: Waiter,
+}
+
+struct Queue<K, V> {
+    order: VecDeque<K>,
+    values: HashMap<K, V>,
+}
+
+/// Writing half of a split `ConflatingRingBuffer`.
+pub struct Producer<K, V> {
+    buffer: Arc<ConflatingRingBuffer<K, V>>,
+}
+
+/// Reading half of a split `ConflatingRingBuffer`.
+pub struct Consumer<K, V> {
+    buffer: Arc<ConflatingRingBuffer<K, V>>,
+}
+
+impl<K: Hash + Eq + Clone, V> ConflatingRingBuffer<K, V> {
+    /// Creates a buffer holding values for up to `size` distinct keys.
+    pub fn new(size: usize) -> ConflatingRingBuffer<K, V> {
+        assert!(size > 0, "capacity must not be zero");
+        ConflatingRingBuffer {
+            size,
+            queue: Mutex::new(Queue {
+                order: VecDeque::with_capacity(size),
+                values: HashMap::with_capacity(size),
+            }),
+            push_waiter: Waiter::new(),
+            pop_waiter: Waiter::new(),
+        }
+    }
+
+    /// Number of distinct keys the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Number of keys waiting to be popped.
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits the buffer into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer<K, V>, Consumer<K, V>) {
+        let buffer = Arc::new(self);
+        (Producer { buffer: buffer.clone() }, Consumer { buffer })
+    }
+
+    fn try_push(&self, key: K, value: V) -> Result<Option<V>, PushError<(K, V)>> {
+        let replaced = {
+            let mut queue = self.queue.lock().unwrap();
+            if let Some(old) = queue.values.get_mut(&key) {
+                return Ok(Some(::std::mem::replace(old, value)));
+            }
+            if queue.order.len() == self.size {
+                return Err(PushError::Full((key, value)));
+            }
+            queue.order.push_back(key.clone());
+            queue.values.insert(key, value)
+        };
+        self.pop_waiter.notify();
+        Ok(replaced)
+    }
+
+    fn try_pop(&self) -> Option<(K, V)> {
+        let popped = {
+            let mut queue = self.queue.lock().unwrap();
+            let key = queue.order.pop_front()?;
+            let value = queue.values.remove(&key).expect("queued key without a value");
+            (key, value)
+        };
+        self.push_waiter.notify();
+        Some(popped)
+    }
+
+    /// Whether `key` could be pushed right now without waiting.
+    fn accepts(&self, key: &K) -> bool {
+        let queue = self.queue.lock().unwrap();
+        queue.order.len() < self.size || queue.values.contains_key(key)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Producer<K, V> {
+    /// Pushes a value, waiting for a free slot if `key` is new and the buffer is full. Returns
+    /// the value it replaced if `key` was still waiting to be popped.
+    pub fn push(&mut self, key: K, value: V) -> Option<V> {
+        let (mut key, mut value) = (key, value);
+        loop {
+            match self.buffer.try_push(key, value) {
+                Ok(replaced) => return replaced,
+                Err(e) => {
+                    let (k, v) = e.into_inner();
+                    key = k;
+                    value = v;
+                }
+            }
+            let buffer = &*self.buffer;
+            buffer.push_waiter.wait(|| buffer.accepts(&key));
+        }
+    }
+
+    /// Like `push` but hands the key and value back if they would need a slot and the buffer
+    /// is full.
+    pub fn try_push(&mut self, key: K, value: V) -> Result<Option<V>, PushError<(K, V)>> {
+        self.buffer.try_push(key, value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.size
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Consumer<K, V> {
+    /// Pops the oldest key with its latest value, waiting for one if the buffer is empty.
+    pub fn pop(&mut self) -> (K, V) {
+        loop {
+            if let Some(item) = self.buffer.try_pop() {
+                return item;
+            }
+            let buffer = &*self.buffer;
+            buffer.pop_waiter.wait(|| !buffer.is_empty());
+        }
+    }
+
+    pub fn try_pop(&mut self) -> Option<(K, V)> {
+        self.buffer.try_pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {ConflatingRingBuffer, PushError};
+    use std::thread;
+
+    #[test]
+    fn conflates_by_key() {
+        let (mut p, mut c) = ConflatingRingBuffer::new(2).split();
+        assert_eq!(p.try_push("a", 1), Ok(None));
+        assert_eq!(p.try_push("b", 2), Ok(None));
+        assert_eq!(p.try_push("a", 3), Ok(Some(1)));
+        assert_eq!(p.try_push("c", 4), Err(PushError::Full(("c", 4))));
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.try_pop(), Some(("a", 3)));
+        // Once popped the key queues up again behind the others.
+        assert_eq!(p.try_push("a", 5), Ok(None));
+        assert_eq!(c.try_pop(), Some(("b", 2)));
+        assert_eq!(c.try_pop(), Some(("a", 5)));
+        assert_eq!(c.try_pop(), None);
+    }
+
+    #[test]
+    fn freshest_value_per_key() {
+        let (mut p, mut c) = ConflatingRingBuffer::new(4).split();
+        let t = thread::spawn(move || {
+            for i in 0..1000 {
+                p.push(i % 8, i);
+            }
+        });
+        let mut last = [None; 8];
+        loop {
+            let (key, value) = c.pop();
+            // Values for a key only ever get fresher.
+            assert!(last[key].is_none_or(|last| last < value));
+            last[key] = Some(value);
+            if last.iter().all(|v| v.is_some_and(|v| v >= 992)) {
+                break;
+            }
+        }
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+//! CRC-32 (IEEE) for checking data written by another process or read back from disk.
+
+const TABLE: [u32; 256] = table();
+
+const fn table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Continues the checksum `crc` of some bytes over `bytes`, start from 0.
+pub fn update(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, &b| TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    update(0, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, update};
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(update(crc32(b"1234"), b"56789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+}
@@ -0,0 +1,93 @@
+use core::fmt;
+use sync::atomic::Ordering;
+
+use index;
+use {Consumer, Producer, RingBuffer};
+
+/// The first buffered items of a consumer, returned by `Consumer::preview`.
+pub struct Preview<'a, T: 'a> {
+    consumer: &'a Consumer<T>,
+    n: usize,
+}
+
+impl<T> Consumer<T> {
+    /// Formats up to `n` of the buffered items in pop order, for logging what a stuck consumer
+    /// is sitting on.
+    pub fn preview(&self, n: usize) -> Preview<'_, T> {
+        Preview { consumer: self, n }
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for Preview<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (head, tail) = self.consumer.as_slices();
+        let mut list = f.debug_list();
+        list.entries(head.iter().chain(tail).take(self.n));
+        if head.len() + tail.len() > self.n {
+            list.entry(&format_args!(".."));
+        }
+        list.finish()
+    }
+}
+
+impl<T> RingBuffer<T> {
+    /// Adds the positions and occupancy to `f`, the positions count up to twice the capacity
+    /// before wrapping.
+    fn debug_fields(&self, f: &mut fmt::DebugStruct) {
+        let read_pos = self.read.pos.load(Ordering::Acquire);
+        let write_pos = self.write.pos.load(Ordering::Acquire);
+        f.field("capacity", &self.size)
+            .field("len", &index::distance(write_pos, read_pos, self.size))
+            .field("read", &read_pos)
+            .field("write", &write_pos);
+    }
+}
+
+impl<T> fmt::Debug for RingBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("RingBuffer");
+        self.debug_fields(&mut s);
+        s.finish()
+    }
+}
+
+impl<T> fmt::Debug for Producer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("Producer");
+        self.buffer.debug_fields(&mut s);
+        s.field("abandoned", &self.is_abandoned()).finish()
+    }
+}
+
+impl<T> fmt::Debug for Consumer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("Consumer");
+        self.buffer.debug_fields(&mut s);
+        s.field("abandoned", &self.is_abandoned()).finish()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use ::RingBuffer;
+
+    #[test]
+    fn debug() {
+        let rb = RingBuffer::new(4);
+        rb.push(1);
+        assert_eq!(format!("{:?}", rb), "RingBuffer { capacity: 4, len: 1, read: 0, write: 1 }");
+        let (mut p, mut c) = rb.split();
+        for i in 2..5 {
+            p.try_push(i).unwrap();
+        }
+        c.try_pop().unwrap();
+        p.try_push(5).unwrap();
+        assert_eq!(format!("{:?}", p),
+                   "Producer { capacity: 4, len: 4, read: 1, write: 5, abandoned: false }");
+        assert_eq!(format!("{:?}", c.preview(3)), "[2, 3, 4, ..]");
+        assert_eq!(format!("{:?}", c.preview(8)), "[2, 3, 4, 5]");
+        drop(p);
+        assert_eq!(format!("{:?}", c),
+                   "Consumer { capacity: 4, len: 4, read: 1, write: 5, abandoned: true }");
+    }
+}
@@ -0,0 +1,131 @@
+use core::iter::FusedIterator;
+use sync::atomic::Ordering;
+
+use index;
+use Consumer;
+
+/// Iterator returned by `Consumer::drain`, popping the items which were buffered when it was
+/// created. Dropping it pops the rest of them too.
+pub struct Drain<'a, T: 'a> {
+    consumer: &'a mut Consumer<T>,
+    end: usize,
+}
+
+impl<T> Consumer<T> {
+    /// Drops every buffered item.
+    pub fn clear(&mut self) {
+        let len = self.len();
+        self.discard(len);
+    }
+
+    /// Drops up to `n` buffered items without moving them out, returns how many were dropped.
+    pub fn skip(&mut self, n: usize) -> usize {
+        let n = n.min(self.len());
+        self.discard(n);
+        n
+    }
+
+    /// Pops the items buffered right now, items pushed meanwhile are left in the buffer.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        let end = self.buffer.write.pos.load(Ordering::Acquire);
+        Drain { consumer: self, end }
+    }
+
+    /// Drops the next `n` buffered items, a chunk at a time. At least `n` must be buffered.
+    pub(crate) fn discard(&mut self, mut n: usize) {
+        while n > 0 {
+            let chunk = self.read_chunk(n);
+            let len = chunk.len();
+            chunk.commit(len);
+            n -= len;
+        }
+    }
+}
+
+impl<'a, T> Drain<'a, T> {
+    fn remaining(&self) -> usize {
+        let buffer = &*self.consumer.buffer;
+        index::distance(self.end, buffer.read.pos.load(Ordering::Relaxed), buffer.size)
+    }
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let buffer = &*self.consumer.buffer;
+        let read_pos = buffer.read.pos.load(Ordering::Relaxed);
+        if read_pos == self.end {
+            return None;
+        }
+        Some(buffer.take(read_pos))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Drain<'a, T> { }
+
+impl<'a, T> FusedIterator for Drain<'a, T> { }
+
+impl<'a, T> Drop for Drain<'a, T> {
+    fn drop(&mut self) {
+        let remaining = self.remaining();
+        self.consumer.discard(remaining);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::RingBuffer;
+    use std::rc::Rc;
+
+    #[test]
+    fn clear_drops_items() {
+        let item = Rc::new(());
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        // Wrap around so the items span both ends of the storage.
+        p.try_write(&[item.clone(), item.clone(), item.clone()]);
+        c.try_read(2);
+        p.try_write(&[item.clone(), item.clone(), item.clone()]);
+        assert_eq!(Rc::strong_count(&item), 5);
+        c.clear();
+        assert_eq!(Rc::strong_count(&item), 1);
+        assert!(c.is_empty());
+        assert_eq!(p.try_write(&vec![item.clone(); 4]), 4);
+    }
+
+    #[test]
+    fn skip() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        p.try_write(&[1, 2, 3]);
+        c.try_read(2);
+        p.try_write(&[4, 5, 6]);
+        // Skips across the wrap point.
+        assert_eq!(c.skip(3), 3);
+        assert_eq!(c.skip(3), 1);
+        assert_eq!(c.skip(1), 0);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn drain() {
+        let (mut p, mut c) = RingBuffer::new(8).split();
+        p.try_write(&[1, 2, 3, 4]);
+        {
+            let mut drain = c.drain();
+            assert_eq!(drain.len(), 4);
+            assert_eq!(drain.next(), Some(1));
+            // Pushed after the drain started, so it stays in the buffer.
+            p.try_push(5).unwrap();
+            assert_eq!(drain.next(), Some(2));
+            assert_eq!(drain.len(), 2);
+        }
+        assert_eq!(c.try_read(8), vec![5]);
+        p.try_write(&[6, 7]);
+        assert_eq!(c.drain().collect::<Vec<_>>(), vec![6, 7]);
+    }
+}
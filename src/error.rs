@@ -0,0 +1,214 @@
+use core::fmt;
+
+/// Error returned by `try_push`, handing back the item which could not be pushed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PushError<T> {
+    /// The buffer had no free slot.
+    Full(T),
+    /// The consumer is gone, the item would never be read.
+    Disconnected(T),
+}
+
+/// Error returned by blocking operations once the other half of a split buffer is dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Disconnected;
+
+/// Error returned by `Consumer::pop` once the buffer is drained and won't be pushed to again.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PopError {
+    /// The producer was dropped.
+    Disconnected,
+    /// The producer closed the buffer.
+    Closed,
+}
+
+/// Error returned by `RingBuffer::try_new`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NewError {
+    /// A capacity of zero was requested.
+    ZeroCapacity,
+    /// The capacity is above `MAX_SIZE` once rounded up.
+    TooLarge,
+    /// The storage couldn't be allocated.
+    AllocFailed,
+}
+
+/// Error returned by `push_timeout` and friends, handing back the item which could not be pushed.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PushTimeoutError<T> {
+    /// No slot became free in time.
+    Timeout(T),
+    /// The consumer is gone, the item would never be read.
+    Disconnected(T),
+}
+
+/// Error returned by `pop_timeout` and friends.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PopTimeoutError {
+    /// No item arrived in time.
+    Timeout,
+    /// The producer is gone and the buffer is drained.
+    Disconnected,
+    /// The producer closed the buffer and it is drained.
+    Closed,
+}
+
+impl<T> PushError<T> {
+    /// Returns the rejected item.
+    pub fn into_inner(self) -> T {
+        match self {
+            PushError::Full(item) | PushError::Disconnected(item) => item,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        match *self {
+            PushError::Full(_) => true,
+            PushError::Disconnected(_) => false,
+        }
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        !self.is_full()
+    }
+
+    /// The same error handing back `item` instead.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn with<U>(self, item: U) -> PushError<U> {
+        match self {
+            PushError::Full(_) => PushError::Full(item),
+            PushError::Disconnected(_) => PushError::Disconnected(item),
+        }
+    }
+}
+
+impl<T> fmt::Debug for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PushError::Full(_) => f.write_str("Full(..)"),
+            PushError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PushError::Full(_) => f.write_str("pushing into a full buffer"),
+            PushError::Disconnected(_) => f.write_str("pushing into a disconnected buffer"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> PushTimeoutError<T> {
+    /// Returns the rejected item.
+    pub fn into_inner(self) -> T {
+        match self {
+            PushTimeoutError::Timeout(item) | PushTimeoutError::Disconnected(item) => item,
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        match *self {
+            PushTimeoutError::Timeout(_) => true,
+            PushTimeoutError::Disconnected(_) => false,
+        }
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        !self.is_timeout()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> fmt::Debug for PushTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PushTimeoutError::Timeout(_) => f.write_str("Timeout(..)"),
+            PushTimeoutError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> fmt::Display for PushTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PushTimeoutError::Timeout(_) => f.write_str("timed out waiting for a free slot"),
+            PushTimeoutError::Disconnected(_) => f.write_str("pushing into a disconnected buffer"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for PopTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PopTimeoutError::Timeout => f.write_str("timed out waiting for an item"),
+            PopTimeoutError::Disconnected => f.write_str("popping from a disconnected buffer"),
+            PopTimeoutError::Closed => f.write_str("popping from a closed buffer"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for PopError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PopError::Disconnected => f.write_str("popping from a disconnected buffer"),
+            PopError::Closed => f.write_str("popping from a closed buffer"),
+        }
+    }
+}
+
+impl fmt::Display for NewError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NewError::ZeroCapacity => f.write_str("capacity must not be zero"),
+            NewError::TooLarge => f.write_str("capacity too large"),
+            NewError::AllocFailed => f.write_str("failed to allocate the buffer"),
+        }
+    }
+}
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the other half of the buffer is gone")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> ::std::error::Error for PushError<T> {}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Disconnected {}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for NewError {}
+
+#[cfg(feature = "std")]
+impl<T> ::std::error::Error for PushTimeoutError<T> {}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for PopTimeoutError {}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for PopError {}
+
+#[cfg(test)]
+mod tests {
+    use ::PushError;
+
+    #[test]
+    fn into_inner() {
+        let err = PushError::Full(vec![1, 2]);
+        assert!(err.is_full());
+        assert_eq!(format!("{:?}", err), "Full(..)");
+        assert_eq!(err.to_string(), "pushing into a full buffer");
+        assert_eq!(err.into_inner(), vec![1, 2]);
+    }
+}
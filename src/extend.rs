@@ -0,0 +1,56 @@
+use Producer;
+
+/// Pushes every item, blocking while the buffer is full. Once the consumer is gone the rest of
+/// the iterator is left alone.
+impl<T> Extend<T> for Producer<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            if self.push(item).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+impl<T> Producer<T> {
+    /// Like `extend` but clones whole chunks of `items` straight into the free slots. Items
+    /// which don't fit before the consumer is dropped are skipped.
+    pub fn extend_from_slice(&mut self, items: &[T]) where T: Clone {
+        let _ = self.write(items);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::RingBuffer;
+    use std::thread;
+
+    #[test]
+    fn extend() {
+        let (mut p, c) = RingBuffer::new(4).split();
+        let t = thread::spawn(move || c.into_iter().collect::<Vec<_>>());
+        p.extend(0..100);
+        drop(p);
+        assert_eq!(t.join().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn extend_from_slice() {
+        let (mut p, c) = RingBuffer::new(4).split();
+        let data: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let expected = data.clone();
+        let t = thread::spawn(move || c.into_iter().collect::<Vec<_>>());
+        p.extend_from_slice(&data);
+        drop(p);
+        assert_eq!(t.join().unwrap(), expected);
+    }
+
+    #[test]
+    fn stops_on_disconnect() {
+        let (mut p, c) = RingBuffer::new(2).split();
+        drop(c);
+        p.extend(0..10);
+        p.extend_from_slice(&[1, 2, 3]);
+        assert!(p.is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+//! C API of a byte `RingBuffer`, declared in `include/ring_buffer.h`.
+//!
+//! `rb_create` returns a handle owning both halves, so one thread can push with
+//! `rb_push_bytes` while another pops with `rb_pop_bytes`, e.g. a C audio callback producing
+//! for a Rust consumer. Neither blocks, and with nothing ever blocking on the buffer neither
+//! has anyone to wake, so they don't allocate or take a lock either and are fine to call from
+//! a real time thread. Build the crate with the `ffi` feature as a static or dynamic
+//! library to link it, e.g. `cargo rustc --release --features ffi --crate-type staticlib`.
+
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::slice;
+
+use {Consumer, Producer, RingBuffer};
+
+/// Handle returned by `rb_create`, opaque to C which only ever holds a pointer to it.
+#[repr(C)]
+pub struct RbHandle {
+    producer: UnsafeCell<Producer<u8>>,
+    consumer: UnsafeCell<Consumer<u8>>,
+}
+
+/// Creates a buffer holding at least `capacity` bytes, the capacity is rounded up to a power
+/// of two. Returns null if `capacity` is zero or too large, or the storage can't be allocated.
+#[no_mangle]
+pub extern "C" fn rb_create(capacity: usize) -> *mut RbHandle {
+    match RingBuffer::try_new(capacity) {
+        Ok(buffer) => {
+            let (producer, consumer) = buffer.split();
+            let handle = RbHandle {
+                producer: UnsafeCell::new(producer),
+                consumer: UnsafeCell::new(consumer),
+            };
+            Box::into_raw(Box::new(handle))
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Pushes as many of the `len` bytes at `data` as fit, returns the number pushed.
+///
+/// # Safety
+///
+/// `rb` must come from `rb_create` and not be freed, `data` must be valid for reading `len`
+/// bytes. Only one thread at a time may push.
+#[no_mangle]
+pub unsafe extern "C" fn rb_push_bytes(rb: *mut RbHandle, data: *const u8, len: usize) -> usize {
+    if rb.is_null() || len == 0 {
+        return 0;
+    }
+    let producer = &mut *(*rb).producer.get();
+    producer.try_write(slice::from_raw_parts(data, len))
+}
+
+/// Pops up to `len` bytes into `buf`, returns the number popped.
+///
+/// # Safety
+///
+/// `rb` must come from `rb_create` and not be freed, `buf` must be valid for writing `len`
+/// bytes. Only one thread at a time may pop.
+#[no_mangle]
+pub unsafe extern "C" fn rb_pop_bytes(rb: *mut RbHandle, buf: *mut u8, len: usize) -> usize {
+    if rb.is_null() || len == 0 {
+        return 0;
+    }
+    let consumer = &mut *(*rb).consumer.get();
+    consumer.read_into(slice::from_raw_parts_mut(buf, len))
+}
+
+/// Frees the buffer along with any bytes left in it, null is ignored.
+///
+/// # Safety
+///
+/// `rb` must come from `rb_create`, and no push or pop may be using it or follow.
+#[no_mangle]
+pub unsafe extern "C" fn rb_free(rb: *mut RbHandle) {
+    if !rb.is_null() {
+        drop(Box::from_raw(rb));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rb_create, rb_free, rb_pop_bytes, rb_push_bytes};
+    use std::ptr;
+
+    #[test]
+    fn push_pop() {
+        assert!(rb_create(0).is_null());
+        let rb = rb_create(4);
+        unsafe {
+            assert_eq!(rb_push_bytes(rb, b"hello".as_ptr(), 5), 4);
+            let mut buf = [0; 8];
+            assert_eq!(rb_pop_bytes(rb, buf.as_mut_ptr(), 3), 3);
+            assert_eq!(&buf[..3], b"hel");
+            assert_eq!(rb_push_bytes(rb, b"ab".as_ptr(), 2), 2);
+            assert_eq!(rb_pop_bytes(rb, buf.as_mut_ptr(), 8), 3);
+            assert_eq!(&buf[..3], b"lab");
+            assert_eq!(rb_pop_bytes(ptr::null_mut(), buf.as_mut_ptr(), 8), 0);
+            rb_free(rb);
+            rb_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use {Consumer, PopError, Producer, PushError};
+
+/// Future returned by `Producer::push_async`.
+pub struct PushFuture<'a, T: 'a> {
+    producer: &'a mut Producer<T>,
+    item: Option<T>,
+}
+
+/// Future returned by `Consumer::pop_async`.
+pub struct PopFuture<'a, T: 'a> {
+    consumer: &'a mut Consumer<T>,
+}
+
+// The item is never pinned, it is only moved into the buffer.
+impl<'a, T> Unpin for PushFuture<'a, T> { }
+
+impl<T> Producer<T> {
+    /// Pushes an item, waiting for a free slot without blocking the thread. Like `push` the item
+    /// is handed back if the consumer is gone.
+    pub fn push_async(&mut self, item: T) -> PushFuture<'_, T> {
+        PushFuture { producer: self, item: Some(item) }
+    }
+
+    /// Polls for a free slot or a dropped consumer, registering the task to be woken once
+    /// either happens.
+    pub(crate) fn poll_push_ready(&self, cx: &mut Context) -> Poll<()> {
+        let buffer = &*self.buffer;
+        if buffer.len() == buffer.size && !self.is_abandoned() {
+            buffer.push_waiter.register(cx.waker());
+            if buffer.len() == buffer.size && !self.is_abandoned() {
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Pops an item, waiting for one to arrive without blocking the thread. Like `pop` this fails
+    /// once the producer is gone and the buffer is drained, with `Closed` if it closed the
+    /// buffer.
+    pub fn pop_async(&mut self) -> PopFuture<'_, T> {
+        PopFuture { consumer: self }
+    }
+
+    /// Polls for an available item or a dropped producer, registering the task to be woken once
+    /// either happens.
+    pub(crate) fn poll_pop_ready(&self, cx: &mut Context) -> Poll<()> {
+        let buffer = &*self.buffer;
+        if buffer.is_empty() && !self.is_abandoned() {
+            buffer.pop_waiter.register(cx.waker());
+            if buffer.is_empty() && !self.is_abandoned() {
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(())
+    }
+
+    /// Polls for an item, registering the task to be woken once one is available.
+    pub(crate) fn poll_pop(&mut self, cx: &mut Context) -> Poll<Result<T, PopError>> {
+        if self.poll_pop_ready(cx).is_pending() {
+            return Poll::Pending;
+        }
+        Poll::Ready(match self.buffer.try_pop() {
+            Some(item) => Ok(item),
+            None if self.is_closed() => Err(PopError::Closed),
+            None => Err(PopError::Disconnected),
+        })
+    }
+}
+
+impl<'a, T> Future for PushFuture<'a, T> {
+    type Output = Result<(), PushError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if this.producer.poll_push_ready(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let item = this.item.take().expect("PushFuture polled after completion");
+        Poll::Ready(this.producer.try_push(item))
+    }
+}
+
+impl<'a, T> Future for PopFuture<'a, T> {
+    type Output = Result<T, PopError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().consumer.poll_pop(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {PopError, RingBuffer};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+    use std::thread::{self, Thread};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = ::std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn push_pop_async() {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                block_on(p.push_async(i)).unwrap();
+            }
+        });
+        for i in 0..100 {
+            assert_eq!(block_on(c.pop_async()), Ok(i));
+        }
+        t.join().unwrap();
+        assert_eq!(block_on(c.pop_async()), Err(PopError::Disconnected));
+    }
+
+    #[test]
+    fn closed_async() {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        p.push(1).unwrap();
+        p.close();
+        assert_eq!(block_on(c.pop_async()), Ok(1));
+        assert_eq!(block_on(c.pop_async()), Err(PopError::Closed));
+    }
+
+    #[test]
+    fn pending_when_empty() {
+        let (mut p, mut c) = RingBuffer::new(1).split();
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        {
+            let mut pop = c.pop_async();
+            assert_eq!(Pin::new(&mut pop).poll(&mut cx), Poll::Pending);
+        }
+        p.push(1).unwrap();
+        assert_eq!(Pin::new(&mut c.pop_async()).poll(&mut cx), Poll::Ready(Ok(1)));
+    }
+}
@@ -0,0 +1,209 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use error::{Disconnected, PushError};
+use RingBuffer;
+
+/// SPSC ring buffer the producer can resize while it is in use, without losing or reordering
+/// the items in flight.
+///
+/// Resizing moves the producer on to a new segment and leaves it for the consumer, which
+/// switches over once it has drained the old one. Pushes and pops are those of `RingBuffer`,
+/// only a resize and the consumer's switch take a short lock.
+pub struct GrowableRingBuffer<T> {
+    buffer: RingBuffer<T>,
+}
+
+/// Writing half of a split `GrowableRingBuffer`.
+pub struct Producer<T> {
+    producer: ::Producer<T>,
+    link: Arc<Link<T>>,
+}
+
+/// Reading half of a split `GrowableRingBuffer`.
+pub struct Consumer<T> {
+    consumer: ::Consumer<T>,
+    link: Arc<Link<T>>,
+}
+
+/// Shared by both halves of a segment, the producer leaves the next segment here.
+struct Link<T> {
+    next: Mutex<Next<T>>,
+}
+
+enum Next<T> {
+    Empty,
+    // The consumer is gone, there is no point in resizing any more.
+    Closed,
+    Segment(::Consumer<T>, Arc<Link<T>>),
+}
+
+impl<T> Link<T> {
+    fn new() -> Arc<Link<T>> {
+        Arc::new(Link { next: Mutex::new(Next::Empty) })
+    }
+
+    fn take(&self, replacement: Next<T>) -> Next<T> {
+        mem::replace(&mut *self.next.lock().unwrap(), replacement)
+    }
+}
+
+impl<T> GrowableRingBuffer<T> {
+    /// Creates a buffer holding at least `size` items, the capacity is rounded up to a power of
+    /// two.
+    pub fn new(size: usize) -> GrowableRingBuffer<T> {
+        GrowableRingBuffer { buffer: RingBuffer::new(size) }
+    }
+
+    /// Number of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Splits the buffer into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let (producer, consumer) = self.buffer.split();
+        let link = Link::new();
+        (Producer { producer, link: link.clone() }, Consumer { consumer, link })
+    }
+}
+
+impl<T> Producer<T> {
+    /// Blocks until the item is pushed, handing it back if the consumer is gone.
+    pub fn push(&mut self, item: T) -> Result<(), PushError<T>> {
+        self.producer.push(item)
+    }
+
+    pub fn try_push(&mut self, item: T) -> Result<(), PushError<T>> {
+        self.producer.try_push(item)
+    }
+
+    /// Moves on to a new segment holding at least `size` items, the items still buffered are
+    /// popped first. Fails if the consumer is gone.
+    pub fn resize(&mut self, size: usize) -> Result<(), Disconnected> {
+        let (producer, consumer) = RingBuffer::new(size).split();
+        let link = Link::new();
+        {
+            let mut next = self.link.next.lock().unwrap();
+            if let Next::Closed = *next {
+                return Err(Disconnected);
+            }
+            *next = Next::Segment(consumer, link.clone());
+        }
+        // Dropping the old producer tells the consumer to switch once the old segment is empty.
+        self.producer = producer;
+        self.link = link;
+        Ok(())
+    }
+
+    /// Number of items the current segment can hold.
+    pub fn capacity(&self) -> usize {
+        self.producer.capacity()
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Blocks until an item is available, failing once the producer is gone and the buffer is
+    /// drained.
+    pub fn pop(&mut self) -> Result<T, Disconnected> {
+        loop {
+            match self.consumer.pop() {
+                Ok(item) => return Ok(item),
+                Err(_) => {
+                    if !self.advance() {
+                        return Err(Disconnected);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn try_pop(&mut self) -> Option<T> {
+        loop {
+            // Checked first, once the producer left the segment everything it pushed is visible.
+            let left = self.consumer.is_abandoned();
+            if let Some(item) = self.consumer.try_pop() {
+                return Some(item);
+            }
+            if !left || !self.advance() {
+                return None;
+            }
+        }
+    }
+
+    /// Switches to the segment the producer moved on to, if any.
+    fn advance(&mut self) -> bool {
+        match self.link.take(Next::Empty) {
+            Next::Segment(consumer, link) => {
+                self.consumer = consumer;
+                self.link = link;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        // Closes every segment the producer already moved on to, failing its pushes and resizes.
+        let mut link = self.link.clone();
+        while let Next::Segment(_, next) = link.take(Next::Closed) {
+            link = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Disconnected, GrowableRingBuffer, PushError};
+    use std::thread;
+
+    #[test]
+    fn resize_keeps_items() {
+        let (mut p, mut c) = GrowableRingBuffer::new(2).split();
+        p.try_push(1).unwrap();
+        p.try_push(2).unwrap();
+        assert_eq!(p.try_push(3), Err(PushError::Full(3)));
+        p.resize(8).unwrap();
+        assert_eq!(p.capacity(), 8);
+        for i in 3..10 {
+            p.try_push(i).unwrap();
+        }
+        assert_eq!(c.try_pop(), Some(1));
+        p.resize(4).unwrap();
+        p.try_push(10).unwrap();
+        let rest: Vec<_> = (0..9).map(|_| c.try_pop().unwrap()).collect();
+        assert_eq!(rest, (2..11).collect::<Vec<_>>());
+        assert_eq!(c.try_pop(), None);
+        drop(p);
+        assert_eq!(c.pop(), Err(Disconnected));
+    }
+
+    #[test]
+    fn consumer_gone() {
+        let (mut p, c) = GrowableRingBuffer::new(2).split();
+        p.resize(4).unwrap();
+        drop(c);
+        assert_eq!(p.try_push(1), Err(PushError::Disconnected(1)));
+        assert_eq!(p.resize(8), Err(Disconnected));
+    }
+
+    #[test]
+    fn threads() {
+        let (mut p, mut c) = GrowableRingBuffer::new(1).split();
+        let t = thread::spawn(move || {
+            for i in 0..10_000 {
+                if i % 1000 == 0 {
+                    p.resize(i / 100 + 1).unwrap();
+                }
+                p.push(i).unwrap();
+            }
+        });
+        for i in 0..10_000 {
+            assert_eq!(c.pop(), Ok(i));
+        }
+        assert_eq!(c.pop(), Err(Disconnected));
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+//! Position arithmetic for the SPSC buffers.
+//!
+//! Positions don't grow forever but run over `0..2 * size`, the second lap is what tells a full
+//! buffer (`distance == size`) apart from an empty one (`distance == 0`). Every position stays
+//! below `2 * size` so nothing can overflow however long the buffer is used, which is why
+//! capacities are limited to `MAX_SIZE`.
+//!
+//! Sizes are always powers of two so all of this is done with masks instead of divisions.
+//!
+//! Since positions wrap at `2 * size` rather than at the integer's limit, any width that holds
+//! `2 * size - 1` works the same and none of them ever overflows. `StaticRingBuffer` lets the
+//! positions be stored as a narrower `Index` to save space on small targets, the arithmetic
+//! itself is always done on `usize`.
+
+use core::cell::Cell;
+#[cfg(any(target_has_atomic = "64", feature = "portable-atomic"))]
+use sync::atomic::AtomicU64;
+use sync::atomic::{AtomicU16, AtomicU32, AtomicUsize};
+// The type itself, with `strict-ordering` the one in `sync::atomic` only holds constants.
+use core::sync::atomic::Ordering;
+
+/// Largest supported capacity.
+pub const MAX_SIZE: usize = usize::MAX / 4 + 1;
+
+/// Rounds a requested capacity up to the power of two actually used.
+#[cfg(feature = "alloc")]
+pub fn capacity(size: usize) -> usize {
+    assert!(size <= MAX_SIZE, "capacity too large");
+    size.next_power_of_two()
+}
+
+/// Position `n <= size` steps after `pos`.
+#[inline]
+pub fn advance(pos: usize, n: usize, size: usize) -> usize {
+    if cfg!(feature = "strict-ordering") {
+        assert!(pos < 2 * size && n <= size, "advancing {} by {} with capacity {}", pos, n, size);
+    }
+    (pos + n) & (2 * size - 1)
+}
+
+/// Number of items between `read` and `write`.
+#[inline]
+pub fn distance(write: usize, read: usize, size: usize) -> usize {
+    let distance = write.wrapping_sub(read) & (2 * size - 1);
+    if cfg!(feature = "strict-ordering") {
+        assert!(write < 2 * size && read < 2 * size && distance <= size,
+                "write position {} and read position {} out of step with capacity {}", write, read,
+                size);
+    }
+    distance
+}
+
+/// Slot backing `pos`.
+#[inline]
+pub fn index(pos: usize, size: usize) -> usize {
+    pos & (size - 1)
+}
+
+/// Integer type the positions of a `StaticRingBuffer` are stored as: `u16`, `u32`, `u64` or
+/// `usize`.
+///
+/// Positions wrap at twice the capacity rather than at the limit of the type, so a narrow index
+/// never overflows and only bounds the capacity: a buffer holds at most `MAX_SIZE` items, a
+/// quarter of the range of the type. `u64` is only available where the target has 64 bit
+/// atomics or with the `portable-atomic` feature.
+pub trait Index: Copy + Sealed {
+    /// Largest capacity whose positions fit.
+    const MAX_SIZE: usize;
+
+    #[doc(hidden)]
+    type Atomic;
+    #[doc(hidden)]
+    const ZERO: Self;
+    #[doc(hidden)]
+    #[cfg(not(feature = "loom"))]
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ATOMIC_ZERO: Self::Atomic;
+    // Loom's atomics can't be created in a constant.
+    #[doc(hidden)]
+    #[cfg(feature = "loom")]
+    fn atomic_zero() -> Self::Atomic;
+    #[doc(hidden)]
+    fn load(atomic: &Self::Atomic, order: Ordering) -> usize;
+    #[doc(hidden)]
+    fn store(atomic: &Self::Atomic, pos: usize, order: Ordering);
+    #[doc(hidden)]
+    fn from_usize(pos: usize) -> Self;
+    #[doc(hidden)]
+    fn to_usize(self) -> usize;
+}
+
+/// Keeps `Index` to the types implemented here, the buffers rely on them being exact.
+pub trait Sealed { }
+
+macro_rules! impl_index {
+    ($($ty:ty, $atomic:ty;)*) => {$(
+        impl Sealed for $ty { }
+
+        impl Index for $ty {
+            const MAX_SIZE: usize = {
+                let max = (<$ty>::MAX / 4) as u128 + 1;
+                if max > MAX_SIZE as u128 { MAX_SIZE } else { max as usize }
+            };
+
+            type Atomic = $atomic;
+            const ZERO: $ty = 0;
+            #[cfg(not(feature = "loom"))]
+            #[allow(clippy::declare_interior_mutable_const)]
+            const ATOMIC_ZERO: $atomic = <$atomic>::new(0);
+
+            #[cfg(feature = "loom")]
+            fn atomic_zero() -> $atomic {
+                <$atomic>::new(0)
+            }
+
+            #[inline]
+            fn load(atomic: &$atomic, order: Ordering) -> usize {
+                atomic.load(order) as usize
+            }
+
+            #[inline]
+            fn store(atomic: &$atomic, pos: usize, order: Ordering) {
+                atomic.store(pos as $ty, order)
+            }
+
+            #[inline]
+            fn from_usize(pos: usize) -> $ty {
+                pos as $ty
+            }
+
+            #[inline]
+            fn to_usize(self) -> usize {
+                self as usize
+            }
+        }
+    )*};
+}
+
+impl_index! {
+    u16, AtomicU16;
+    u32, AtomicU32;
+    usize, AtomicUsize;
+}
+
+#[cfg(any(target_has_atomic = "64", feature = "portable-atomic"))]
+impl_index! {
+    u64, AtomicU64;
+}
+
+/// One side's position, next to its cached copy of the other side's position.
+///
+/// Each side only reloads the other's position once the cached one says the buffer is full (or
+/// empty), which keeps the other side's cache line out of most operations.
+pub struct Cursor<I: Index = usize> {
+    pub pos: I::Atomic,
+    // Only touched by the side owning `pos`.
+    pub cached: Cell<I>,
+}
+
+// Only `StaticRingBuffer` uses the accessors, which loom builds leave out.
+#[cfg_attr(feature = "loom", allow(dead_code))]
+impl<I: Index> Cursor<I> {
+    #[cfg(not(feature = "loom"))]
+    pub const fn new() -> Cursor<I> {
+        Cursor {
+            pos: I::ATOMIC_ZERO,
+            cached: Cell::new(I::ZERO),
+        }
+    }
+
+    #[cfg(feature = "loom")]
+    pub fn new() -> Cursor<I> {
+        Cursor {
+            pos: I::atomic_zero(),
+            cached: Cell::new(I::ZERO),
+        }
+    }
+
+    #[inline]
+    pub fn load(&self, order: Ordering) -> usize {
+        I::load(&self.pos, order)
+    }
+
+    #[inline]
+    pub fn store(&self, pos: usize, order: Ordering) {
+        I::store(&self.pos, pos, order)
+    }
+
+    #[inline]
+    pub fn cached(&self) -> usize {
+        self.cached.get().to_usize()
+    }
+
+    #[inline]
+    pub fn set_cached(&self, pos: usize) {
+        self.cached.set(I::from_usize(pos))
+    }
+}
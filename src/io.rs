@@ -0,0 +1,279 @@
+//! `std::io` support for byte buffers.
+
+use std::io::{self, BufRead, IoSlice, IoSliceMut, Read, Write};
+use std::mem::MaybeUninit;
+
+use byte;
+use {Consumer, Producer};
+
+impl Write for Producer<u8> {
+    /// Blocks until at least one byte can be written, then writes as much of `buf` as fits.
+    /// Fails with `BrokenPipe` once the consumer is gone.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match buf.split_first() {
+            Some((first, rest)) => {
+                if self.push(*first).is_err() {
+                    return Err(io::ErrorKind::BrokenPipe.into());
+                }
+                Ok(1 + self.try_write(rest))
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Blocks like `write` for the first non-empty slice, then copies from the following ones
+    /// until the buffer is full.
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        let first = match bufs.iter().position(|buf| !buf.is_empty()) {
+            Some(first) => first,
+            None => return Ok(0),
+        };
+        let mut written = Write::write(self, &bufs[first])?;
+        if written == bufs[first].len() {
+            for buf in &bufs[first + 1..] {
+                let n = self.try_write(buf);
+                written += n;
+                if n < buf.len() {
+                    break;
+                }
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Producer<u8> {
+    /// Reads from `reader` straight into the free slots, returns the number of bytes read.
+    ///
+    /// Only one call to `reader.read` is made, with what is contiguously free, so this returns
+    /// `Ok(0)` when the buffer is full as well as at end of file.
+    pub fn read_from<R: Read>(&mut self, reader: &mut R) -> io::Result<usize> {
+        let cap = self.capacity();
+        let mut chunk = self.write_chunk(cap);
+        let slots = chunk.as_mut_slice();
+        for slot in slots.iter_mut() {
+            slot.write(0);
+        }
+        let buf = unsafe { &mut *(slots as *mut [MaybeUninit<u8>] as *mut [u8]) };
+        let n = reader.read(buf)?;
+        unsafe { chunk.commit(n) };
+        Ok(n)
+    }
+}
+
+impl Read for Consumer<u8> {
+    /// Blocks until at least one byte is available, then reads as much as is buffered. Once the
+    /// producer is gone and the buffer is drained this reports end of file.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = match self.pop() {
+            Ok(b) => b,
+            Err(_) => return Ok(0),
+        };
+        Ok(1 + self.read_into(&mut buf[1..]))
+    }
+
+    /// Blocks like `read` for the first non-empty slice, then fills the following ones until the
+    /// buffer is drained.
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        let first = match bufs.iter().position(|buf| !buf.is_empty()) {
+            Some(first) => first,
+            None => return Ok(0),
+        };
+        let (head, rest) = bufs[first..].split_first_mut().unwrap();
+        let mut read = Read::read(self, head)?;
+        if read == head.len() {
+            for buf in rest {
+                let n = self.read_into(buf);
+                read += n;
+                if n < buf.len() {
+                    break;
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Reads straight out of the buffer's storage, so `fill_buf` only returns the bytes before the
+/// wrap point.
+impl BufRead for Consumer<u8> {
+    /// Blocks until at least one byte is available, an empty slice means the producer is gone.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        {
+            let buffer = &*self.buffer;
+            buffer.pop_waiter.wait(|| !buffer.is_empty() || self.is_abandoned());
+        }
+        Ok(self.as_slices().0)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.read_chunk(amt).commit(amt);
+    }
+}
+
+/// Blocks like the `Producer<u8>` impl.
+impl Write for byte::Producer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Write::write(self.as_inner(), buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        self.as_inner().write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl byte::Producer {
+    /// Reads from `reader` straight into the free bytes before the wrap point, returns the
+    /// number of bytes read. Like `Producer::<u8>::read_from` but without zeroing them first.
+    ///
+    /// Only one call to `reader.read` is made too, so this returns `Ok(0)` when the buffer
+    /// is full as well as at end of file, check `is_full` to tell them apart.
+    pub fn read_from<R: Read>(&mut self, reader: &mut R) -> io::Result<usize> {
+        let n = reader.read(self.as_mut_slice())?;
+        self.commit(n);
+        Ok(n)
+    }
+}
+
+/// Blocks like the `Consumer<u8>` impl.
+impl Read for byte::Consumer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Read::read(self.as_inner(), buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        self.as_inner().read_vectored(bufs)
+    }
+}
+
+impl BufRead for byte::Consumer {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.as_inner().fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        byte::Consumer::consume(self, amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::{ByteRingBuffer, RingBuffer};
+    use std::io::{BufRead, IoSlice, IoSliceMut, Read, Write};
+    use std::thread;
+
+    #[test]
+    fn read_write() {
+        let (mut p, mut c) = RingBuffer::new(16).split();
+        let data: Vec<u8> = (0..255).collect();
+        let expected = data.clone();
+        let t = thread::spawn(move || {
+            p.write_all(&data).unwrap();
+        });
+        let mut out = vec![0; 255];
+        c.read_exact(&mut out).unwrap();
+        assert_eq!(out, expected);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn read_to_end_after_drop() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        p.write_all(&[1, 2, 3]).unwrap();
+        drop(p);
+        let mut out = Vec::new();
+        c.read_to_end(&mut out).unwrap();
+        assert_eq!(out, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn write_after_drop() {
+        let (mut p, c) = RingBuffer::new(4).split();
+        drop(c);
+        let err = Write::write(&mut p, &[1]).unwrap_err();
+        assert_eq!(err.kind(), ::std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn read_from() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let mut src = &[1, 2, 3, 4, 5][..];
+        assert_eq!(p.read_from(&mut src).unwrap(), 4);
+        assert_eq!(p.read_from(&mut src).unwrap(), 0);
+        assert_eq!(c.try_read(2), vec![1, 2]);
+        assert_eq!(p.read_from(&mut src).unwrap(), 1);
+        assert_eq!(c.try_read(4), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn buf_read() {
+        let (mut p, c) = RingBuffer::new(8).split();
+        let t = thread::spawn(move || {
+            p.write_all(b"one\ntwo\nthree\n").unwrap();
+        });
+        let lines: Vec<_> = c.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, ["one", "two", "three"]);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn partial_write() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        assert_eq!(Write::write(&mut p, &[1, 2, 3, 4, 5, 6]).unwrap(), 4);
+        let mut out = [0; 8];
+        assert_eq!(Read::read(&mut c, &mut out).unwrap(), 4);
+        assert_eq!(&out[..4], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn vectored() {
+        let (mut p, mut c) = RingBuffer::new(8).split();
+        let bufs = [IoSlice::new(&[]), IoSlice::new(&[1, 2, 3]), IoSlice::new(&[4, 5, 6, 7, 8, 9])];
+        assert_eq!(p.write_vectored(&bufs).unwrap(), 8);
+        let (mut a, mut b, mut d) = ([0; 2], [0; 4], [0; 4]);
+        {
+            let mut bufs = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b),
+                            IoSliceMut::new(&mut d)];
+            assert_eq!(c.read_vectored(&mut bufs).unwrap(), 8);
+        }
+        assert_eq!((a, b, d), ([1, 2], [3, 4, 5, 6], [7, 8, 0, 0]));
+        drop(p);
+        assert_eq!(c.read_vectored(&mut [IoSliceMut::new(&mut a)]).unwrap(), 0);
+    }
+
+    #[test]
+    fn byte_ring_buffer() {
+        let (mut p, mut c) = ByteRingBuffer::new(8).split();
+        let mut src = &b"one\ntwo\nthree\n"[..];
+        assert_eq!(p.read_from(&mut src).unwrap(), 8);
+        // Full while `src` has bytes left.
+        assert_eq!(p.read_from(&mut src).unwrap(), 0);
+        assert!(p.is_full() && src.len() == 6);
+        let mut line = String::new();
+        c.read_line(&mut line).unwrap();
+        assert_eq!(line, "one\n");
+        // Only the bytes freed before the wrap point are read into.
+        assert_eq!(p.read_from(&mut src).unwrap(), 4);
+        assert_eq!(p.read_from(&mut src).unwrap(), 0);
+        c.consume(4);
+        assert_eq!(p.read_from(&mut src).unwrap(), 2);
+        // Now at end of file.
+        assert_eq!(p.read_from(&mut src).unwrap(), 0);
+        assert!(!p.is_full());
+        let t = thread::spawn(move || p.write_all(b"four\n").unwrap());
+        let lines: Vec<_> = c.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, ["three", "four"]);
+        t.join().unwrap();
+    }
+}
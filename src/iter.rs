@@ -0,0 +1,181 @@
+use alloc::vec::Vec;
+
+use {Consumer, RingBuffer};
+
+/// Blocking iterator returned by `Consumer::iter`, ends once the producer is gone and the
+/// buffer has been drained.
+#[cfg(feature = "std")]
+pub struct Iter<'a, T: 'a> {
+    consumer: &'a mut Consumer<T>,
+}
+
+/// Blocking iterator owning the consumer, see `Iter`. Iterating a `RingBuffer` itself yields
+/// the buffered items without blocking since there is no producer left.
+#[cfg(feature = "std")]
+pub struct IntoIter<T> {
+    consumer: Consumer<T>,
+}
+
+/// Iterator returned by `Consumer::try_iter`, ends as soon as the buffer is empty.
+pub struct TryIter<'a, T: 'a> {
+    consumer: &'a mut Consumer<T>,
+}
+
+/// Iterator returned by `Consumer::pop_while`.
+pub struct PopWhile<'a, T: 'a, F> {
+    consumer: &'a mut Consumer<T>,
+    pred: F,
+    done: bool,
+}
+
+impl<T> Consumer<T> {
+    /// Iterates over items as they arrive, blocking for each of them.
+    #[cfg(feature = "std")]
+    pub fn iter(&mut self) -> Iter<'_, T> {
+        Iter { consumer: self }
+    }
+
+    /// Iterates over the items available without blocking.
+    pub fn try_iter(&mut self) -> TryIter<'_, T> {
+        TryIter { consumer: self }
+    }
+
+    /// Pops items while `pred` accepts them, stopping for good at the first item it rejects or
+    /// once the buffer is empty.
+    pub fn pop_while<F: FnMut(&T) -> bool>(&mut self, pred: F) -> PopWhile<'_, T, F> {
+        PopWhile { consumer: self, pred, done: false }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.consumer.pop().ok()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.consumer.pop().ok()
+    }
+}
+
+impl<'a, T> Iterator for TryIter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.consumer.try_pop()
+    }
+}
+
+impl<'a, T, F: FnMut(&T) -> bool> Iterator for PopWhile<'a, T, F> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.done {
+            return None;
+        }
+        let pred = &mut self.pred;
+        let item = self.consumer.pop_if(|item| pred(item));
+        self.done = item.is_none();
+        item
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, T> IntoIterator for &'a mut Consumer<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> IntoIterator for Consumer<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { consumer: self }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> IntoIterator for RingBuffer<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        let (_, consumer) = self.split();
+        consumer.into_iter()
+    }
+}
+
+impl<T> RingBuffer<T> {
+    /// Collects the buffered items in pop order.
+    pub fn into_vec(self) -> Vec<T> {
+        let (_, mut consumer) = self.split();
+        consumer.try_iter().collect()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use ::RingBuffer;
+    use std::thread;
+
+    #[test]
+    fn ends_on_disconnect() {
+        let (mut p, c) = RingBuffer::new(4).split();
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                p.push(i).unwrap();
+            }
+        });
+        let mut next = 0;
+        for item in c {
+            assert_eq!(item, next);
+            next += 1;
+        }
+        assert_eq!(next, 100);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn borrowed() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        p.try_write(&[1, 2, 3]);
+        assert_eq!(c.iter().take(2).collect::<Vec<_>>(), vec![1, 2]);
+        drop(p);
+        assert_eq!((&mut c).into_iter().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn owned_buffer() {
+        let rb = RingBuffer::new(4);
+        rb.push(1);
+        rb.push(2);
+        assert_eq!(rb.into_iter().collect::<Vec<_>>(), vec![1, 2]);
+        let rb = RingBuffer::new(4);
+        rb.try_write(&[3, 4, 5]);
+        rb.try_pop();
+        assert_eq!(rb.into_vec(), vec![4, 5]);
+    }
+
+    #[test]
+    fn try_iter() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        assert_eq!(c.try_iter().next(), None);
+        p.try_write(&[1, 2]);
+        assert_eq!(c.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        p.try_push(3).unwrap();
+        assert_eq!(c.try_iter().collect::<Vec<_>>(), vec![3]);
+    }
+}
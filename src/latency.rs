@@ -0,0 +1,262 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use error::{Disconnected, PushError};
+use RingBuffer;
+
+/// SPSC ring buffer which stamps every item when it is pushed and records how long it was
+/// queued when it is popped, to measure the latency the buffer adds under load.
+///
+/// The latencies are collected in a `Histogram` owned by the consumer, `pop_timed` and
+/// `try_pop_timed` also return them per item.
+pub struct TimedRingBuffer<T> {
+    buffer: RingBuffer<(Instant, T)>,
+}
+
+/// Writing half of a split `TimedRingBuffer`.
+pub struct Producer<T> {
+    producer: ::Producer<(Instant, T)>,
+}
+
+/// Reading half of a split `TimedRingBuffer`.
+pub struct Consumer<T> {
+    consumer: ::Consumer<(Instant, T)>,
+    histogram: Histogram,
+}
+
+/// Distribution of latencies over power of two buckets of nanoseconds.
+#[derive(Clone)]
+pub struct Histogram {
+    // `buckets[i]` counts the latencies below `2^i` ns which aren't in an earlier bucket.
+    buckets: [u64; 64],
+    count: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl<T> TimedRingBuffer<T> {
+    /// Creates a buffer holding at least `size` items, the capacity is rounded up to a power of
+    /// two.
+    pub fn new(size: usize) -> TimedRingBuffer<T> {
+        TimedRingBuffer { buffer: RingBuffer::new(size) }
+    }
+
+    /// Number of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Splits the buffer into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let (producer, consumer) = self.buffer.split();
+        (Producer { producer }, Consumer { consumer, histogram: Histogram::new() })
+    }
+}
+
+fn untimed<T>(e: PushError<(Instant, T)>) -> PushError<T> {
+    match e {
+        PushError::Full((_, item)) => PushError::Full(item),
+        PushError::Disconnected((_, item)) => PushError::Disconnected(item),
+    }
+}
+
+impl<T> Producer<T> {
+    /// Blocks until the item is pushed, handing it back if the consumer is gone. The item is
+    /// stamped once it has a slot, so time spent waiting here isn't counted.
+    pub fn push(&mut self, item: T) -> Result<(), PushError<T>> {
+        self.producer.push_with(|| (Instant::now(), item))
+            .map_err(|e| PushError::Disconnected(e.into_inner()().1))
+    }
+
+    pub fn try_push(&mut self, item: T) -> Result<(), PushError<T>> {
+        self.producer.try_push((Instant::now(), item)).map_err(untimed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.producer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.producer.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.producer.capacity()
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Blocks until an item is available, failing once the producer is gone and the buffer is
+    /// drained.
+    pub fn pop(&mut self) -> Result<T, Disconnected> {
+        self.pop_timed().map(|(item, _)| item)
+    }
+
+    pub fn try_pop(&mut self) -> Option<T> {
+        self.try_pop_timed().map(|(item, _)| item)
+    }
+
+    /// Like `pop` but also returns how long the item was queued.
+    pub fn pop_timed(&mut self) -> Result<(T, Duration), Disconnected> {
+        let stamped = self.consumer.pop().map_err(|_| Disconnected)?;
+        Ok(self.record(stamped))
+    }
+
+    /// Like `try_pop` but also returns how long the item was queued.
+    pub fn try_pop_timed(&mut self) -> Option<(T, Duration)> {
+        let stamped = self.consumer.try_pop()?;
+        Some(self.record(stamped))
+    }
+
+    fn record(&mut self, (pushed, item): (Instant, T)) -> (T, Duration) {
+        let latency = pushed.elapsed();
+        self.histogram.record(latency);
+        (item, latency)
+    }
+
+    /// Latencies of the items popped so far.
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+
+    /// Returns the latencies recorded so far and starts over with an empty histogram.
+    pub fn take_histogram(&mut self) -> Histogram {
+        ::std::mem::take(&mut self.histogram)
+    }
+
+    pub fn len(&self) -> usize {
+        self.consumer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.consumer.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.consumer.capacity()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram {
+            buckets: [0; 64],
+            count: 0,
+            total: Duration::ZERO,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = latency.as_nanos().min(u64::MAX as u128) as u64;
+        self.buckets[Histogram::bucket(nanos)] += 1;
+        self.count += 1;
+        self.total = self.total.saturating_add(latency);
+        self.min = self.min.min(latency);
+        self.max = self.max.max(latency);
+    }
+
+    fn bucket(nanos: u64) -> usize {
+        (64 - nanos.leading_zeros() as usize).min(63)
+    }
+
+    /// Number of latencies recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        if self.count == 0 { None } else { Some(self.min) }
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        if self.count == 0 { None } else { Some(self.max) }
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(Duration::from_nanos((self.total.as_nanos() / self.count as u128) as u64))
+    }
+
+    /// Upper bound of the latency below which the fraction `q` of the items were queued, off
+    /// by at most a factor of two.
+    ///
+    /// # Panics
+    ///
+    /// If `q` isn't between 0 and 1.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        assert!((0.0..=1.0).contains(&q), "quantile out of range");
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let bound = Duration::from_nanos(1u64.checked_shl(i as u32).unwrap_or(u64::MAX));
+                return Some(bound.min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram::new()
+    }
+}
+
+impl fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Histogram")
+            .field("count", &self.count)
+            .field("min", &self.min())
+            .field("mean", &self.mean())
+            .field("max", &self.max())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Histogram;
+    use ::TimedRingBuffer;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn records_latency() {
+        let (mut p, mut c) = TimedRingBuffer::new(4).split();
+        assert!(c.histogram().mean().is_none());
+        p.try_push(1).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        let (item, latency) = c.try_pop_timed().unwrap();
+        assert_eq!(item, 1);
+        assert!(latency >= Duration::from_millis(20));
+        p.push(2).unwrap();
+        assert_eq!(c.pop(), Ok(2));
+        let histogram = c.take_histogram();
+        assert_eq!(histogram.count(), 2);
+        assert_eq!(histogram.max(), Some(latency));
+        assert_eq!(c.histogram().count(), 0);
+    }
+
+    #[test]
+    fn quantiles() {
+        let mut h = Histogram::new();
+        for micros in 1..=100 {
+            h.record(Duration::from_micros(micros));
+        }
+        assert_eq!(h.min(), Some(Duration::from_micros(1)));
+        assert_eq!(h.mean(), Some(Duration::from_nanos(50_500)));
+        let p50 = h.quantile(0.5).unwrap();
+        assert!(p50 >= Duration::from_micros(50) && p50 <= Duration::from_micros(100));
+        assert_eq!(h.quantile(1.0), Some(Duration::from_micros(100)));
+    }
+}
@@ -1,113 +1,733 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::cell::UnsafeCell;
-use std::mem;
-use std::thread;
-use std::time::Duration;
+//! Lockfree fixed size ring buffers.
+//!
+//! Features:
+//!
+//! - `std` (default): blocking operations, async support and `std::io` impls. Implies `alloc`.
+//! - `alloc`: the heap allocated buffers, usable with `#![no_std]`.
+//! - `bytes`: `bytes::Buf` for byte consumers and `Producer::write_buf`.
+//! - `mirror`: `MirroredRingBuffer`, a byte buffer mapped twice in virtual memory. Unix and
+//!   Windows only.
+//! - `shm`: `ShmRingBuffer`, an SPSC buffer in memory shared between processes.
+//! - `persistent`: `PersistentRingBuffer`, a byte buffer stored in a file which survives
+//!   restarts. Unix only.
+//! - `readiness`: `Consumer::readiness_fd`, a descriptor for epoll or kqueue loops. Unix only.
+//! - `mio`: registering a `Consumer` with a mio `Poll`, implies `readiness`.
+//! - `tokio-readiness`: `AsyncConsumer`, awaiting items through the tokio reactor.
+//! - `stats`: `stats()` counters of the pushes, pops and overflows of a `RingBuffer`.
+//! - `metrics`: `RingBuffer::metrics`, reporting the same counts through the `metrics` facade.
+//! - `tracing`: trace events for blocking waits and for a `RingBuffer` turning full or empty.
+//! - `allocator_api`: `RingBuffer::new_in`, taking the storage from a custom allocator. Needs a
+//!   nightly compiler.
+//! - `hugepages`: `RingBuffer::with_huge_pages`, backing the storage with huge pages. Unix only.
+//! - `numa`: `RingBuffer::on_node`, binding the storage to a NUMA node. Linux only.
+//! - `portable-atomic`: atomics from the `portable-atomic` crate, for targets without native
+//!   compare-and-swap such as thumbv6m. Single core targets also need its
+//!   `unsafe-assume-single-core` or `critical-section` feature enabled.
+//! - `loom`: loom's atomics in place of the real ones, for the crate's own model tests. The
+//!   buffers only work inside `loom::model` then.
+//! - `serde`: serializing a `Consumer`'s capacity and buffered items, and deserializing a
+//!   `RingBuffer` from them, to persist unprocessed items across restarts.
+//! - `ffi`: `rb_create` and friends, a C API of a byte buffer declared in
+//!   `include/ring_buffer.h`.
+//! - `cpal`: turning the halves of an `AudioRingBuffer` into cpal input and output callbacks.
+//! - `python`: `python::BytesProducer` and `python::ObjectProducer`, pyo3 classes for feeding
+//!   a Rust consumer from Python with backpressure.
+//! - `rt-safe`: waking the other side of a `RingBuffer` from the non-blocking operations without
+//!   allocating or blocking on a lock, and `CheckedAlloc`, enforcing that in debug builds.
+//! - `strict-ordering`: every atomic operation `SeqCst` and the positions checked for staying in
+//!   step on every use, panicking otherwise. For ruling out ordering bugs, not for production.
+//!
+//! With neither enabled the crate is allocation free and only provides `StaticRingBuffer`,
+//! suitable for bare-metal targets.
+//!
+//! # Position width
+//!
+//! Only `StaticRingBuffer` takes the integer type its positions are stored as, see `Index`. The
+//! heap allocated buffers, `RingBuffer` included, always store them as `usize`: each of their
+//! positions sits on a cache line of its own, so a narrower type wouldn't make them smaller.
+//!
+//! # WebAssembly
+//!
+//! The buffers can connect a page's main thread with web workers when the module is built with
+//! shared memory, i.e. with `-C target-feature=+atomics,+bulk-memory` and a `std` rebuilt for
+//! it (`-Z build-std=panic_abort,std` on nightly), and the workers are instantiated with the
+//! same module and `WebAssembly.Memory`. Split the buffer on one side and hand the other half
+//! over as a pointer, e.g. `Box::into_raw(Box::new(consumer)) as usize` posted to the worker.
+//!
+//! Blocking waits use `Atomics.wait`, which browsers only allow in workers. On the main thread
+//! stick to the `try_` operations or the async ones, which never block: their wake-ups only
+//! spin briefly on a contended lock. Timeouts need `Instant`, which `wasm32-unknown-unknown`
+//! lacks.
+//!
+//! # Panics in callbacks
+//!
+//! A panic never poisons a buffer, it is left as if the operation had not started or had
+//! completed. Nothing is pushed if the closure of `push_with` or the iterator passed to
+//! `push_all` panics, the items already moved into the buffer by then are dropped. The item
+//! stays buffered if the closure of `pop_with` panics and is popped once its drop started, even
+//! if that panics. `push_with_slot` can't know how much of the slot was written, so whatever
+//! its closure wrote before panicking is leaked.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+#[cfg(any(feature = "std", test))]
+extern crate core;
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "futures")]
+extern crate futures;
+#[cfg(feature = "metrics")]
+extern crate metrics;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "loom")]
+extern crate loom;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "cpal")]
+extern crate cpal;
+#[cfg(feature = "portable-atomic")]
+extern crate portable_atomic;
+#[cfg(all(feature = "portable-atomic", feature = "alloc", not(target_has_atomic = "ptr")))]
+extern crate portable_atomic_util;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(all(feature = "mio", unix))]
+extern crate mio;
+#[cfg(all(any(feature = "mirror", feature = "shm", feature = "persistent",
+              feature = "readiness", feature = "hugepages", feature = "numa"), unix))]
+extern crate libc;
+#[cfg(all(any(feature = "mirror", feature = "shm"), windows))]
+extern crate windows_sys;
+
+#[cfg(feature = "alloc")]
+use sync::atomic::{self, AtomicBool, Ordering};
+#[cfg(feature = "alloc")]
+use core::mem::{self, MaybeUninit};
+#[cfg(feature = "alloc")]
+use core::{ptr, slice};
+#[cfg(feature = "alloc")]
+use sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "allocator_api")]
+use alloc::alloc::{handle_alloc_error, Allocator};
+#[cfg(feature = "allocator_api")]
+use core::alloc::Layout;
+#[cfg(all(feature = "readiness", unix))]
+use std::sync::OnceLock;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "alloc")]
+use cache_padded::CachePadded;
+#[cfg(feature = "alloc")]
+use index::Cursor;
+#[cfg(feature = "alloc")]
+use rt::Section;
+#[cfg(all(feature = "readiness", unix))]
+use readiness::Readiness;
+#[cfg(feature = "alloc")]
+use stats::Counters;
+#[cfg(feature = "alloc")]
+use storage::Storage;
+#[cfg(feature = "alloc")]
+use waiter::Waiter;
+#[cfg(feature = "std")]
+use waiter::Threshold;
+
+#[cfg(feature = "alloc")]
+pub use audio::{AudioRingBuffer, Overrun};
+#[cfg(feature = "alloc")]
+pub use bip::BipBuffer;
+#[cfg(feature = "alloc")]
+pub use byte::ByteRingBuffer;
+#[cfg(feature = "std")]
+pub use broadcast::BroadcastRingBuffer;
+#[cfg(feature = "std")]
+pub use channel::{channel, Receiver, Sender};
+#[cfg(feature = "alloc")]
+pub use chunk::{ReadChunk, WriteChunk};
+#[cfg(feature = "std")]
+pub use conflate::ConflatingRingBuffer;
+#[cfg(feature = "alloc")]
+pub use debug::Preview;
+#[cfg(feature = "alloc")]
+pub use drain::Drain;
+pub use error::{Disconnected, NewError, PushError};
+#[cfg(feature = "std")]
+pub use error::{PopError, PopTimeoutError, PushTimeoutError};
+#[cfg(feature = "std")]
+pub use future::{PopFuture, PushFuture};
+#[cfg(feature = "std")]
+pub use grow::GrowableRingBuffer;
+#[cfg(feature = "std")]
+pub use iter::{IntoIter, Iter};
+#[cfg(feature = "std")]
+pub use latency::TimedRingBuffer;
+#[cfg(feature = "alloc")]
+pub use iter::{PopWhile, TryIter};
+#[cfg(feature = "std")]
+pub use wait::{NotifyPolicy, WaitStrategy};
+#[cfg(feature = "alloc")]
+pub use watch::Watch;
+#[cfg(all(feature = "mirror", any(unix, windows)))]
+pub use mirror::MirroredRingBuffer;
+#[cfg(all(feature = "persistent", unix))]
+pub use persistent::PersistentRingBuffer;
+#[cfg(feature = "futures")]
+pub use merge::Merge;
+#[cfg(feature = "alloc")]
+pub use mpmc::MpmcRingBuffer;
+#[cfg(feature = "alloc")]
+pub use mpsc::MpscRingBuffer;
+#[cfg(feature = "alloc")]
+pub use overwrite::{OverflowPolicy, OverwritingRingBuffer};
+#[cfg(all(feature = "numa", target_os = "linux"))]
+pub use numa::NumaNode;
+#[cfg(feature = "std")]
+pub use pipeline::PipelineRingBuffer;
+#[cfg(feature = "std")]
+pub use select::Select;
+#[cfg(feature = "shm")]
+pub use shm::ShmRingBuffer;
+#[cfg(feature = "rt-safe")]
+pub use rt::CheckedAlloc;
+#[cfg(feature = "alloc")]
+pub use spmc::SpmcRingBuffer;
+#[cfg(feature = "stats")]
+pub use stats::Stats;
+pub use index::Index;
+// Needs its atomics to be created in a constant, which loom's can't.
+#[cfg(not(feature = "loom"))]
+pub use static_buffer::StaticRingBuffer;
+#[cfg(all(feature = "tokio-readiness", unix))]
+pub use tokio_readiness::{AsyncConsumer, Readable};
+#[cfg(feature = "alloc")]
+pub use unbounded::UnboundedRingBuffer;
+
+#[cfg(feature = "alloc")]
+pub mod audio;
+#[cfg(feature = "alloc")]
+pub mod bip;
+#[cfg(feature = "std")]
+pub mod broadcast;
+#[cfg(feature = "bytes")]
+mod buf;
+#[cfg(feature = "alloc")]
+pub mod byte;
+mod cache_padded;
+#[cfg(feature = "std")]
+pub mod channel;
+#[cfg(feature = "alloc")]
+mod chunk;
+#[cfg(feature = "std")]
+pub mod conflate;
+#[cfg(any(feature = "shm", all(feature = "persistent", unix)))]
+mod crc;
+#[cfg(feature = "alloc")]
+mod debug;
+#[cfg(feature = "alloc")]
+mod drain;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+mod extend;
+mod error;
+#[cfg(feature = "std")]
+mod future;
+#[cfg(feature = "std")]
+pub mod grow;
+mod index;
+#[cfg(feature = "std")]
+mod io;
+#[cfg(feature = "alloc")]
+mod iter;
+#[cfg(feature = "std")]
+pub mod latency;
+#[cfg(all(feature = "mirror", any(unix, windows)))]
+pub mod mirror;
+#[cfg(feature = "futures")]
+mod merge;
+#[cfg(all(feature = "mio", unix))]
+mod mio_source;
+#[cfg(feature = "alloc")]
+mod mpmc;
+#[cfg(feature = "alloc")]
+pub mod mpsc;
+#[cfg(all(test, feature = "loom"))]
+mod model;
+#[cfg(all(feature = "numa", target_os = "linux"))]
+mod numa;
+#[cfg(feature = "alloc")]
+pub mod overwrite;
+#[cfg(all(feature = "persistent", unix))]
+pub mod persistent;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "alloc")]
+mod rt;
+#[cfg(feature = "std")]
+mod select;
+#[cfg(feature = "shm")]
+pub mod shm;
+#[cfg(feature = "alloc")]
+pub mod spmc;
+#[cfg(all(feature = "readiness", unix))]
+mod readiness;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "alloc")]
+mod slot;
+#[cfg(not(feature = "loom"))]
+pub mod static_buffer;
+#[cfg(feature = "alloc")]
+mod stats;
+#[cfg(feature = "alloc")]
+mod storage;
+#[cfg(feature = "futures")]
+mod stream;
+mod sync;
+#[cfg(feature = "tokio")]
+mod tokio_io;
+#[cfg(all(feature = "tokio-readiness", unix))]
+mod tokio_readiness;
+#[cfg(feature = "alloc")]
+pub mod unbounded;
+#[cfg(feature = "std")]
+pub mod wait;
+#[cfg(feature = "alloc")]
+mod waiter;
+#[cfg(feature = "alloc")]
+pub mod watch;
 
 /// Lockfree SPSC fixed size ring buffer.
+///
+/// The buffer itself can only be used from one thread at a time, use `split` to obtain a
+/// `Producer` and `Consumer` which can be sent to different threads. The blocking operations
+/// need the `std` feature.
+///
+/// Zero sized items are supported and take no storage at all, a `RingBuffer<()>` is just the
+/// two positions. It works like a bounded semaphore: pushes hand out permits, which block or
+/// fail once `capacity` are outstanding, and pops return them.
+#[cfg(feature = "alloc")]
 pub struct RingBuffer<T> {
     size: usize,
-    items: UnsafeCell<Vec<Option<T>>>,
-    write_pos: AtomicUsize,
-    read_pos: AtomicUsize,
+    // Only the slots in `read_pos..write_pos` are initialized.
+    items: Storage<T>,
+    write: CachePadded<Cursor>,
+    read: CachePadded<Cursor>,
+    push_waiter: Waiter,
+    pop_waiter: Waiter,
+    // Set once either half of a split buffer is dropped or the producer closes it.
+    abandoned: AtomicBool,
+    closed: AtomicBool,
+    #[cfg(all(feature = "readiness", unix))]
+    readiness: OnceLock<Arc<Readiness>>,
+    stats: Counters,
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send> Send for RingBuffer<T>{ }
+
+/// Writing half of a split `RingBuffer`.
+///
+/// Dropping it lets the consumer drain the remaining items, after which its blocking operations
+/// fail with `Disconnected`.
+#[cfg(feature = "alloc")]
+pub struct Producer<T> {
+    buffer: Arc<RingBuffer<T>>,
 }
 
-unsafe impl<T> Send for RingBuffer<T>{ }
-unsafe impl<T> Sync for RingBuffer<T>{ }
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send> Send for Producer<T>{ }
+
+/// Reading half of a split `RingBuffer`.
+///
+/// Dropping it makes every further push from the producer fail.
+#[cfg(feature = "alloc")]
+pub struct Consumer<T> {
+    buffer: Arc<RingBuffer<T>>,
+}
 
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send> Send for Consumer<T>{ }
+
+#[cfg(feature = "alloc")]
 impl<T> RingBuffer<T> {
+    /// Creates a buffer holding at least `size` items, the capacity is rounded up to a power of
+    /// two.
     pub fn new(size: usize) -> RingBuffer<T> {
-        let items = (0..size).map(|_| None).collect();
+        RingBuffer::with_items(Storage::new(index::capacity(size)))
+    }
+
+    /// Like `new` but fails instead of panicking or aborting if `size` is zero or too large, or
+    /// the storage can't be allocated.
+    pub fn try_new(size: usize) -> Result<RingBuffer<T>, NewError> {
+        if size == 0 {
+            return Err(NewError::ZeroCapacity);
+        }
+        if size > index::MAX_SIZE {
+            return Err(NewError::TooLarge);
+        }
+        let items = Storage::try_new(size.next_power_of_two()).map_err(|_| NewError::AllocFailed)?;
+        Ok(RingBuffer::with_items(items))
+    }
+
+    /// Like `new` but allocates the storage from `alloc`, which is dropped along with the
+    /// buffer.
+    #[cfg(feature = "allocator_api")]
+    pub fn new_in<A: Allocator + Send + 'static>(size: usize, alloc: A) -> RingBuffer<T> {
+        let size = index::capacity(size);
+        match Storage::new_in(size, alloc) {
+            Ok(items) => RingBuffer::with_items(items),
+            Err(_) => handle_alloc_error(Layout::array::<T>(size).expect("capacity too large")),
+        }
+    }
+
+    /// Like `new_in` but fails like `try_new` instead of panicking or aborting.
+    #[cfg(feature = "allocator_api")]
+    pub fn try_new_in<A>(size: usize, alloc: A) -> Result<RingBuffer<T>, NewError>
+        where A: Allocator + Send + 'static
+    {
+        if size == 0 {
+            return Err(NewError::ZeroCapacity);
+        }
+        if size > index::MAX_SIZE {
+            return Err(NewError::TooLarge);
+        }
+        let items = Storage::new_in(size.next_power_of_two(), alloc)
+            .map_err(|_| NewError::AllocFailed)?;
+        Ok(RingBuffer::with_items(items))
+    }
+
+    /// Like `new` but aligns the first slot to at least `align` bytes, e.g. to load blocks of
+    /// items into SIMD registers. The other slots follow at `size_of::<T>()` strides, so they are
+    /// aligned as well if the size is a multiple of `align`.
+    ///
+    /// # Panics
+    ///
+    /// If `align` isn't a power of two.
+    pub fn with_alignment(size: usize, align: usize) -> RingBuffer<T> {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        RingBuffer::with_items(Storage::aligned(index::capacity(size), align))
+    }
+
+    /// Like `new` but maps the storage onto huge pages, saving TLB misses on large buffers.
+    ///
+    /// On Linux reserved 1GB or 2MB pages are used if there are any, otherwise the storage is
+    /// aligned to 2MB and advised to use transparent huge pages. The mapping is rounded up to a
+    /// whole huge page.
+    #[cfg(all(feature = "hugepages", unix))]
+    pub fn with_huge_pages(size: usize) -> ::std::io::Result<RingBuffer<T>> {
+        Storage::huge_pages(index::capacity(size)).map(RingBuffer::with_items)
+    }
+
+    /// Like `new` but binds the storage to `node`, so neither side of a buffer between threads
+    /// pinned to that node reaches across to another one.
+    ///
+    /// The storage is mapped on its own pages, so it doesn't share them with other allocations.
+    #[cfg(all(feature = "numa", target_os = "linux"))]
+    pub fn on_node(size: usize, node: NumaNode) -> ::std::io::Result<RingBuffer<T>> {
+        Storage::on_node(index::capacity(size), node).map(RingBuffer::with_items)
+    }
+
+    fn with_items(items: Storage<T>) -> RingBuffer<T> {
         RingBuffer {
-            size: size,
-            items: UnsafeCell::new(items),
-            write_pos: AtomicUsize::new(0),
-            read_pos: AtomicUsize::new(0),
+            size: items.len(),
+            items,
+            write: CachePadded::new(Cursor::new()),
+            read: CachePadded::new(Cursor::new()),
+            push_waiter: Waiter::new(),
+            pop_waiter: Waiter::new(),
+            abandoned: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            #[cfg(all(feature = "readiness", unix))]
+            readiness: OnceLock::new(),
+            stats: Counters::default(),
         }
     }
 
-    pub fn push(&self, item: T) {
-        let write_pos = self.write_pos.load(Ordering::Acquire);
-        loop {
-            let read_pos = self.read_pos.load(Ordering::Acquire);
-            if write_pos - read_pos != self.size {
-                break;
-            } else {
-                thread::park_timeout(Duration::from_millis(1));
+    /// Like `new` but blocked threads wait according to `strategy` instead of parking.
+    #[cfg(feature = "std")]
+    pub fn with_strategy<S: WaitStrategy + 'static>(size: usize, strategy: S) -> RingBuffer<T> {
+        let strategy: ::std::sync::Arc<dyn WaitStrategy> = ::std::sync::Arc::new(strategy);
+        let mut rb = RingBuffer::new(size);
+        rb.push_waiter = Waiter::with_strategy(strategy.clone());
+        rb.pop_waiter = Waiter::with_strategy(strategy);
+        rb
+    }
+
+    /// Like `new` but each side wakes the other blocked one according to `policy`, keeping a
+    /// high rate stream from waking the other side for every item.
+    #[cfg(feature = "std")]
+    pub fn with_notify_policy(size: usize, policy: NotifyPolicy) -> RingBuffer<T> {
+        let mut rb = RingBuffer::new(size);
+        match policy {
+            NotifyPolicy::Every => { }
+            NotifyPolicy::Batch { items, latency } => {
+                rb.pop_waiter.set_batch(items.clamp(1, rb.size), latency);
+            }
+            NotifyPolicy::Watermarks { low, high } => {
+                rb.push_waiter.set_threshold(Threshold::Below(low.clamp(1, rb.size)));
+                rb.pop_waiter.set_threshold(Threshold::Above(high.min(rb.size - 1)));
             }
         }
+        rb
+    }
 
-        unsafe {
-            let mut items = &mut *self.items.get();
-            mem::replace(&mut items[write_pos % self.size], Some(item));
+    #[cfg(feature = "std")]
+    pub fn push(&self, item: T) {
+        let write_pos = self.write.pos.load(Ordering::Relaxed);
+        if !self.can_write(write_pos) {
+            self.stats.full();
+            self.push_waiter.wait(|| self.can_write(write_pos));
         }
-        self.write_pos.store(write_pos + 1, Ordering::Release);
+        self.put(write_pos, item);
     }
 
-    pub fn try_push(&self, item: T) -> Option<()> {
-        let write_pos = self.write_pos.load(Ordering::Acquire);
-        let read_pos = self.read_pos.load(Ordering::Acquire);
-        if write_pos - read_pos == self.size {
-            return None;
+    /// Pushes an item unless the buffer is full, in which case it is handed back.
+    pub fn try_push(&self, item: T) -> Result<(), PushError<T>> {
+        let write_pos = self.write.pos.load(Ordering::Relaxed);
+        if !self.can_write(write_pos) {
+            self.stats.full();
+            return Err(PushError::Full(item));
         }
+        self.put(write_pos, item);
+        Ok(())
+    }
+
+    /// Writes `item` into the free slot at `write_pos` and publishes it.
+    fn put(&self, write_pos: usize, item: T) {
         unsafe {
-            let mut items = &mut *self.items.get();
-            mem::replace(&mut items[write_pos % self.size], Some(item));
+            self.put_with(write_pos, |slot| {
+                slot.write(item);
+            });
         }
-        self.write_pos.store(write_pos + 1, Ordering::Release);
-        Some(())
     }
 
-    pub fn pop(&self) -> T {
-        let read_pos = self.read_pos.load(Ordering::Acquire);
-        loop {
-            let write_pos = self.write_pos.load(Ordering::Acquire);
-            if write_pos != read_pos {
-                break;
-            } else {
-                thread::park_timeout(Duration::from_millis(1));
-            }
+    /// Lets `init` fill the free slot at `write_pos` in place and publishes it, nothing is
+    /// published if `init` panics.
+    ///
+    /// `init` must initialize the slot.
+    unsafe fn put_with<F: FnOnce(&mut MaybeUninit<T>)>(&self, write_pos: usize, init: F) {
+        let i = index::index(write_pos, self.size);
+        self.items.track_write(i);
+        init(&mut *self.items.slot(i));
+        self.publish(write_pos, 1);
+    }
+
+    /// Whether the producer at `write_pos` has a free slot.
+    ///
+    /// Each side only ever stores its own position, so it loads that one `Relaxed` and only the
+    /// other side's position needs to synchronize. That one is loaded `Relaxed` as well and the
+    /// fence acquires the consumer's reads of the slot, once it turns out to be free: a waiting
+    /// producer polls this without paying for an acquire each time. A cached position saying
+    /// the slot is free was fenced when it was loaded.
+    fn can_write(&self, write_pos: usize) -> bool {
+        let cached = &self.write.cached;
+        if index::distance(write_pos, cached.get(), self.size) != self.size {
+            return true;
         }
+        cached.set(self.read.pos.load(Ordering::Relaxed));
+        if index::distance(write_pos, cached.get(), self.size) == self.size {
+            return false;
+        }
+        atomic::fence(Ordering::Acquire);
+        true
+    }
 
-        let item = unsafe {
-            let mut items = &mut *self.items.get();
-            mem::replace(&mut items[read_pos % self.size], None)
-        };
-        self.read_pos.store(read_pos + 1, Ordering::Release);
-        item.unwrap()
+    /// Whether the consumer at `read_pos` has an item available, see `can_write`. The fence
+    /// acquires the producer's writes of the item.
+    fn can_read(&self, read_pos: usize) -> bool {
+        let cached = &self.read.cached;
+        if cached.get() != read_pos {
+            return true;
+        }
+        cached.set(self.write.pos.load(Ordering::Relaxed));
+        if cached.get() == read_pos {
+            return false;
+        }
+        atomic::fence(Ordering::Acquire);
+        true
+    }
+
+    #[cfg(feature = "std")]
+    pub fn pop(&self) -> T {
+        let read_pos = self.read.pos.load(Ordering::Relaxed);
+        if !self.can_read(read_pos) {
+            self.stats.empty();
+            self.pop_waiter.wait(|| self.can_read(read_pos));
+        }
+        self.take(read_pos)
     }
 
     pub fn try_pop(&self) -> Option<T> {
-        let read_pos = self.read_pos.load(Ordering::Acquire);
-        let write_pos = self.write_pos.load(Ordering::Acquire);
-        if write_pos == read_pos {
+        let read_pos = self.read.pos.load(Ordering::Relaxed);
+        if !self.can_read(read_pos) {
+            self.stats.empty();
             return None;
         }
+        Some(self.take(read_pos))
+    }
+
+    /// Hands the slots before `read_pos` back to the producer.
+    fn release(&self, read_pos: usize) {
+        let _rt = Section::enter();
+        self.read.pos.store(read_pos, Ordering::Release);
+        self.push_waiter.notify_popped(|| {
+            index::distance(self.write.pos.load(Ordering::Relaxed), read_pos, self.size)
+        });
+    }
+
+    /// Moves the published item at `read_pos` out and frees its slot.
+    fn take(&self, read_pos: usize) -> T {
         let item = unsafe {
-            let mut items = &mut *self.items.get();
-            mem::replace(&mut items[read_pos % self.size], None)
+            let i = index::index(read_pos, self.size);
+            self.items.track_read(i);
+            (*self.items.slot(i)).assume_init_read()
         };
-        self.read_pos.store(read_pos + 1, Ordering::Release);
-        Some(item.unwrap())
+        self.release(index::advance(read_pos, 1, self.size));
+        self.stats.popped(1);
+        item
     }
 
+    #[cfg(feature = "std")]
     pub fn write(&self, buffer: &[T]) where T: Clone {
         for item in buffer {
             self.push(item.clone());
         }
     }
 
+    /// Clones as many items of `buffer` as fit into the free slots, the slots wrapping around
+    /// are filled in two contiguous runs and published with one store.
     pub fn try_write(&self, buffer: &[T]) -> usize where T: Clone {
-        let mut counter = 0;
-        for item in buffer {
-            if let None = self.try_push(item.clone()) {
-                return counter;
+        let write_pos = self.write.pos.load(Ordering::Relaxed);
+        let read_pos = self.read.pos.load(Ordering::Acquire);
+        self.write.cached.set(read_pos);
+        let n = buffer.len().min(self.size - index::distance(write_pos, read_pos, self.size));
+        if n == 0 {
+            if !buffer.is_empty() {
+                self.stats.full();
             }
-            counter += 1;
-         }
-        counter
+            return 0;
+        }
+        // For `Copy` items this is a plain copy of each run.
+        let n = unsafe { self.fill_slice(write_pos, &buffer[..n]) };
+        self.publish(write_pos, n);
+        n
+    }
+
+    /// Moves items from `items` into up to `n` free slots from `write_pos` on without
+    /// publishing them, the slots wrapping around are filled in two contiguous runs. Returns
+    /// how many were written.
+    ///
+    /// At least `n` slots must be free.
+    unsafe fn fill<I: Iterator<Item = T>>(&self, write_pos: usize, n: usize, items: I) -> usize {
+        let start = index::index(write_pos, self.size);
+        let first = n.min(self.size - start);
+        // Only the free runs are borrowed, the consumer may be reading the other slots.
+        let tail = slice::from_raw_parts_mut(self.items.slot(start), first);
+        let head = slice::from_raw_parts_mut(self.items.slot(0), n - first);
+        let slots = tail.iter_mut().chain(head);
+        let mut filled = Unpublished { buffer: self, write_pos, written: 0 };
+        for (slot, item) in slots.zip(items) {
+            slot.write(item);
+            filled.written += 1;
+        }
+        let written = filled.written;
+        mem::forget(filled);
+        for i in 0..written {
+            self.items.track_write(index::index(write_pos + i, self.size));
+        }
+        written
     }
 
+    /// Like `fill` for all of `items`, cloning each contiguous run with a loop simple enough to
+    /// compile to a plain copy of the run for `Copy` items.
+    ///
+    /// At least `items.len()` slots must be free.
+    unsafe fn fill_slice(&self, write_pos: usize, items: &[T]) -> usize where T: Clone {
+        let start = index::index(write_pos, self.size);
+        let first = items.len().min(self.size - start);
+        let mut filled = Unpublished { buffer: self, write_pos, written: 0 };
+        clone_run(self.items.slot(start), &items[..first], &mut filled.written);
+        clone_run(self.items.slot(0), &items[first..], &mut filled.written);
+        mem::forget(filled);
+        if cfg!(feature = "loom") {
+            for i in 0..items.len() {
+                self.items.track_write(index::index(write_pos + i, self.size));
+            }
+        }
+        items.len()
+    }
+
+    /// Hands the `n` slots filled from `write_pos` on to the consumer with one store.
+    fn publish(&self, write_pos: usize, n: usize) {
+        let _rt = Section::enter();
+        let write_pos = index::advance(write_pos, n, self.size);
+        self.write.pos.store(write_pos, Ordering::Release);
+        self.pop_waiter.notify_pushed(n, || {
+            index::distance(write_pos, self.read.pos.load(Ordering::Relaxed), self.size)
+        });
+        self.record_push(write_pos, n);
+    }
+
+    /// Counts `n` items pushed up to `write_pos`.
+    #[inline]
+    fn record_push(&self, write_pos: usize, n: usize) {
+        self.stats.pushed(n, || {
+            index::distance(write_pos, self.read.pos.load(Ordering::Relaxed), self.size)
+        });
+    }
+
+    /// Moves up to `buf.len()` items into `buf` and frees their slots with one store, items
+    /// which don't need dropping are copied a contiguous run at a time.
+    fn take_into(&self, buf: &mut [T]) -> usize {
+        let read_pos = self.read.pos.load(Ordering::Relaxed);
+        let write_pos = self.write.pos.load(Ordering::Acquire);
+        self.read.cached.set(write_pos);
+        let n = buf.len().min(index::distance(write_pos, read_pos, self.size));
+        if n == 0 {
+            if !buf.is_empty() {
+                self.stats.empty();
+            }
+            return 0;
+        }
+        let start = index::index(read_pos, self.size);
+        let first = n.min(self.size - start);
+        let mut release = Release { buffer: self, read_pos, moved: 0 };
+        unsafe {
+            for i in 0..n {
+                self.items.track_read(index::index(read_pos + i, self.size));
+            }
+            let items = self.items.slot(0) as *const T;
+            if !mem::needs_drop::<T>() {
+                ptr::copy_nonoverlapping(items.add(start), buf.as_mut_ptr(), first);
+                ptr::copy_nonoverlapping(items, buf.as_mut_ptr().add(first), n - first);
+                release.moved = n;
+            } else {
+                for (i, dst) in buf[..n].iter_mut().enumerate() {
+                    let pos = index::advance(read_pos, i, self.size);
+                    let item = items.add(index::index(pos, self.size)).read();
+                    release.moved += 1;
+                    *dst = item;
+                }
+            }
+        }
+        n
+    }
+
+    #[cfg(feature = "std")]
     pub fn read(&self, size: usize) -> Vec<T> {
         let mut v = Vec::with_capacity(size);
         for _ in 0..size {
@@ -119,25 +739,635 @@ impl<T> RingBuffer<T> {
     pub fn try_read(&self, size: usize) -> Vec<T> {
         let mut v = Vec::with_capacity(size);
         for _ in 0..size {
-            if let Some(i) = self.try_pop() {
-                v.push(i);
-            } else {
+            let read_pos = self.read.pos.load(Ordering::Relaxed);
+            if !self.can_read(read_pos) {
+                // Only a read which got nothing found the buffer empty.
+                if v.is_empty() {
+                    self.stats.empty();
+                }
                 return v;
             }
+            v.push(self.take(read_pos));
+        }
+        v
+    }
+
+    pub fn len(&self) -> usize {
+        let read_pos = self.read.pos.load(Ordering::Acquire);
+        let write_pos = self.write.pos.load(Ordering::Acquire);
+        index::distance(write_pos, read_pos, self.size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Number of items which can be pushed before the buffer is full.
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Number of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Snapshot of the buffer's counters.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// Reports the buffer through the `metrics` recorder installed now, as the counters
+    /// `<prefix>.pushes`, `.pops`, `.full` and `.empty` and the gauge `<prefix>.occupancy`.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, prefix: &str) -> RingBuffer<T> {
+        let len = self.len();
+        self.stats.register_metrics(prefix, len);
+        self
+    }
+
+    /// Splits the buffer into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let buffer = Arc::new(self);
+        (Producer { buffer: buffer.clone() }, Consumer { buffer })
+    }
+}
+
+/// Frees the `moved` slots from `read_pos` on when dropped, so they are handed back even if
+/// dropping one of their items panics.
+#[cfg(feature = "alloc")]
+struct Release<'a, T: 'a> {
+    buffer: &'a RingBuffer<T>,
+    read_pos: usize,
+    moved: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T> Drop for Release<'a, T> {
+    fn drop(&mut self) {
+        let buffer = self.buffer;
+        buffer.release(index::advance(self.read_pos, self.moved, buffer.size));
+        buffer.stats.popped(self.moved);
+    }
+}
+
+/// Drops the `written` items moved into the slots from `write_pos` on if filling them is cut
+/// short by a panic, before they could be published.
+#[cfg(feature = "alloc")]
+struct Unpublished<'a, T: 'a> {
+    buffer: &'a RingBuffer<T>,
+    write_pos: usize,
+    written: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T> Drop for Unpublished<'a, T> {
+    fn drop(&mut self) {
+        let buffer = self.buffer;
+        for i in 0..self.written {
+            let pos = index::advance(self.write_pos, i, buffer.size);
+            unsafe { (*buffer.items.slot(index::index(pos, buffer.size))).assume_init_drop(); }
+        }
+    }
+}
+
+/// Clones `items` into the slots from `dst` on, counting each one in `written` once it landed.
+///
+/// Items which don't need dropping leave nothing to clean up when a clone panics, so they are
+/// only counted once the whole run landed and the loop is left with nothing but the clones.
+#[cfg(feature = "alloc")]
+#[inline]
+unsafe fn clone_run<T: Clone>(dst: *mut MaybeUninit<T>, items: &[T], written: &mut usize) {
+    let slots = slice::from_raw_parts_mut(dst, items.len());
+    if !mem::needs_drop::<T>() {
+        for (slot, item) in slots.iter_mut().zip(items) {
+            slot.write(item.clone());
+        }
+        *written += items.len();
+        return;
+    }
+    for (slot, item) in slots.iter_mut().zip(items) {
+        slot.write(item.clone());
+        *written += 1;
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        let read_pos = self.read.pos.load(Ordering::Relaxed);
+        let len = index::distance(self.write.pos.load(Ordering::Relaxed), read_pos, self.size);
+        for i in 0..len {
+            let pos = index::advance(read_pos, i, self.size);
+            unsafe { (*self.items.slot(index::index(pos, self.size))).assume_init_drop(); }
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Producer<T> {
+    /// Blocks until the item is pushed, handing it back if the consumer is gone.
+    #[cfg(feature = "std")]
+    pub fn push(&mut self, item: T) -> Result<(), PushError<T>> {
+        self.push_until(item, None).map_err(|e| PushError::Disconnected(e.into_inner()))
+    }
+
+    /// Like `push` but gives up once `timeout` has elapsed.
+    #[cfg(feature = "std")]
+    pub fn push_timeout(&mut self, item: T, timeout: Duration) -> Result<(), PushTimeoutError<T>> {
+        self.push_until(item, Instant::now().checked_add(timeout))
+    }
+
+    /// Like `push` but gives up once `deadline` has passed.
+    #[cfg(feature = "std")]
+    pub fn push_deadline(&mut self, item: T, deadline: Instant) -> Result<(), PushTimeoutError<T>> {
+        self.push_until(item, Some(deadline))
+    }
+
+    #[cfg(feature = "std")]
+    fn push_until(&self, item: T, deadline: Option<Instant>)
+                  -> Result<(), PushTimeoutError<T>> {
+        match self.free_slot(deadline) {
+            Ok(write_pos) => {
+                self.buffer.put(write_pos, item);
+                Ok(())
+            }
+            Err(PushTimeoutError::Timeout(())) => Err(PushTimeoutError::Timeout(item)),
+            Err(PushTimeoutError::Disconnected(())) => Err(PushTimeoutError::Disconnected(item)),
+        }
+    }
+
+    /// Waits for a free slot and returns its position.
+    #[cfg(feature = "std")]
+    fn free_slot(&self, deadline: Option<Instant>) -> Result<usize, PushTimeoutError<()>> {
+        let buffer = &*self.buffer;
+        let write_pos = buffer.write.pos.load(Ordering::Relaxed);
+        if !buffer.can_write(write_pos) {
+            buffer.stats.full();
+        }
+        let ready = buffer.push_waiter.wait_until(|| {
+            self.is_abandoned() || buffer.can_write(write_pos)
+        }, deadline);
+        if self.is_abandoned() {
+            return Err(PushTimeoutError::Disconnected(()));
+        }
+        if !ready {
+            return Err(PushTimeoutError::Timeout(()));
+        }
+        Ok(write_pos)
+    }
+
+    /// Position of a free slot, if there is one right now.
+    fn try_free_slot(&self) -> Result<usize, PushError<()>> {
+        if self.is_abandoned() {
+            return Err(PushError::Disconnected(()));
+        }
+        let write_pos = self.buffer.write.pos.load(Ordering::Relaxed);
+        if !self.buffer.can_write(write_pos) {
+            self.buffer.stats.full();
+            return Err(PushError::Full(()));
+        }
+        Ok(write_pos)
+    }
+
+    pub fn try_push(&mut self, item: T) -> Result<(), PushError<T>> {
+        if self.is_abandoned() {
+            return Err(PushError::Disconnected(item));
+        }
+        self.buffer.try_push(item)
+    }
+
+    /// Like `push` but only calls `f` to build the item once a slot is free, handing `f` back
+    /// if the consumer is gone.
+    #[cfg(feature = "std")]
+    pub fn push_with<F: FnOnce() -> T>(&mut self, f: F) -> Result<(), PushError<F>> {
+        match self.free_slot(None) {
+            Ok(write_pos) => {
+                self.buffer.put(write_pos, f());
+                Ok(())
+            }
+            Err(_) => Err(PushError::Disconnected(f)),
+        }
+    }
+
+    /// Like `try_push` but only calls `f` to build the item if a slot is free.
+    pub fn try_push_with<F: FnOnce() -> T>(&mut self, f: F) -> Result<(), PushError<F>> {
+        match self.try_free_slot() {
+            Ok(write_pos) => {
+                self.buffer.put(write_pos, f());
+                Ok(())
+            }
+            Err(e) => Err(e.with(f)),
+        }
+    }
+
+    /// Like `push_with` but `init` writes the item straight into the free slot, so large items
+    /// are never moved. Nothing is pushed if `init` panics.
+    ///
+    /// # Safety
+    ///
+    /// `init` must initialize the slot.
+    #[cfg(feature = "std")]
+    pub unsafe fn push_with_slot<F>(&mut self, init: F) -> Result<(), PushError<F>>
+        where F: FnOnce(&mut MaybeUninit<T>)
+    {
+        match self.free_slot(None) {
+            Ok(write_pos) => {
+                self.buffer.put_with(write_pos, init);
+                Ok(())
+            }
+            Err(_) => Err(PushError::Disconnected(init)),
+        }
+    }
+
+    /// Like `try_push_with` but `init` writes the item straight into the free slot.
+    ///
+    /// # Safety
+    ///
+    /// `init` must initialize the slot.
+    pub unsafe fn try_push_with_slot<F>(&mut self, init: F) -> Result<(), PushError<F>>
+        where F: FnOnce(&mut MaybeUninit<T>)
+    {
+        match self.try_free_slot() {
+            Ok(write_pos) => {
+                self.buffer.put_with(write_pos, init);
+                Ok(())
+            }
+            Err(e) => Err(e.with(init)),
+        }
+    }
+
+    /// Blocks until all of `buffer` is pushed, stopping early if the consumer is gone.
+    #[cfg(feature = "std")]
+    pub fn write(&mut self, buffer: &[T]) -> Result<(), Disconnected> where T: Clone {
+        let mut rest = buffer;
+        while !rest.is_empty() {
+            if self.is_abandoned() {
+                return Err(Disconnected);
+            }
+            let n = self.buffer.try_write(rest);
+            rest = &rest[n..];
+            if n == 0 {
+                let buffer = &*self.buffer;
+                buffer.push_waiter.wait(|| self.is_abandoned() || buffer.len() != buffer.size);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes as many of the items in `buffer` as fit without blocking, returns the number
+    /// pushed. `Copy` items are copied in at most two runs, before and after the wrap point,
+    /// at about memcpy speed, and so are popped ones by `Consumer::read_into`.
+    pub fn try_write(&mut self, buffer: &[T]) -> usize where T: Clone {
+        if self.is_abandoned() {
+            return 0;
+        }
+        self.buffer.try_write(buffer)
+    }
+
+    /// Blocks until all of `items` fit and pushes them with one store, so the consumer sees
+    /// either none or all of them.
+    ///
+    /// # Panics
+    ///
+    /// If `items` is larger than the buffer.
+    #[cfg(feature = "std")]
+    pub fn push_batch(&mut self, items: &[T]) -> Result<(), Disconnected> where T: Clone {
+        assert!(items.len() <= self.capacity(), "batch larger than the buffer");
+        let buffer = &*self.buffer;
+        if buffer.slots_free() < items.len() {
+            buffer.stats.full();
+        }
+        buffer.push_waiter.wait(|| self.is_abandoned() || buffer.slots_free() >= items.len());
+        if self.is_abandoned() {
+            return Err(Disconnected);
+        }
+        let write_pos = buffer.write.pos.load(Ordering::Relaxed);
+        let n = unsafe { buffer.fill_slice(write_pos, items) };
+        buffer.publish(write_pos, n);
+        Ok(())
+    }
+
+    /// Like `push_batch` but fails without pushing anything if not all of `items` fit.
+    pub fn try_push_batch(&mut self, items: &[T]) -> Result<(), PushError<()>> where T: Clone {
+        if self.is_abandoned() {
+            return Err(PushError::Disconnected(()));
+        }
+        let buffer = &*self.buffer;
+        if buffer.slots_free() < items.len() {
+            buffer.stats.full();
+            return Err(PushError::Full(()));
+        }
+        let write_pos = buffer.write.pos.load(Ordering::Relaxed);
+        let n = unsafe { buffer.fill_slice(write_pos, items) };
+        buffer.publish(write_pos, n);
+        Ok(())
+    }
+
+    /// Moves items out of `items` into the free slots until either runs out and publishes them
+    /// with one store, returning how many were pushed. Pass the iterator by reference to keep
+    /// the items which didn't fit.
+    pub fn push_all<I: IntoIterator<Item = T>>(&mut self, items: I) -> usize {
+        if self.is_abandoned() {
+            return 0;
+        }
+        let buffer = &*self.buffer;
+        let write_pos = buffer.write.pos.load(Ordering::Relaxed);
+        let n = unsafe { buffer.fill(write_pos, buffer.slots_free(), items.into_iter()) };
+        buffer.publish(write_pos, n);
+        n
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Wakes a waiting consumer for the items a `NotifyPolicy` is still holding back.
+    #[cfg(feature = "std")]
+    pub fn notify_consumer(&self) {
+        self.buffer.pop_waiter.notify();
+    }
+
+    /// Marks the stream finished without dropping the producer. The consumer drains the items
+    /// pushed so far and then fails with `Closed` rather than `Disconnected`, its async and io
+    /// adapters end like after a drop. Pushing fails from now on.
+    pub fn close(&mut self) {
+        self.buffer.closed.store(true, Ordering::Relaxed);
+        self.buffer.abandoned.store(true, Ordering::Release);
+        self.buffer.pop_waiter.notify();
+    }
+
+    /// Whether the consumer has been dropped or the buffer closed.
+    pub fn is_abandoned(&self) -> bool {
+        self.buffer.abandoned.load(Ordering::Acquire)
+    }
+
+    /// Snapshot of the buffer's counters.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        self.buffer.stats.snapshot()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.buffer.abandoned.store(true, Ordering::Release);
+        self.buffer.pop_waiter.notify();
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Consumer<T> {
+    /// Blocks until an item is available, failing once the producer is gone or closed the
+    /// buffer and it has been drained.
+    #[cfg(feature = "std")]
+    pub fn pop(&mut self) -> Result<T, PopError> {
+        match self.pop_until(None) {
+            Ok(item) => Ok(item),
+            Err(PopTimeoutError::Closed) => Err(PopError::Closed),
+            Err(_) => Err(PopError::Disconnected),
+        }
+    }
+
+    /// Like `pop` but gives up once `timeout` has elapsed.
+    #[cfg(feature = "std")]
+    pub fn pop_timeout(&mut self, timeout: Duration) -> Result<T, PopTimeoutError> {
+        self.pop_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Like `pop` but gives up once `deadline` has passed.
+    #[cfg(feature = "std")]
+    pub fn pop_deadline(&mut self, deadline: Instant) -> Result<T, PopTimeoutError> {
+        self.pop_until(Some(deadline))
+    }
+
+    #[cfg(feature = "std")]
+    fn pop_until(&self, deadline: Option<Instant>) -> Result<T, PopTimeoutError> {
+        let buffer = &*self.buffer;
+        let read_pos = buffer.read.pos.load(Ordering::Relaxed);
+        if !buffer.can_read(read_pos) {
+            buffer.stats.empty();
+        }
+        let ready = buffer.pop_waiter.wait_until(|| {
+            buffer.can_read(read_pos) || self.is_abandoned()
+        }, deadline);
+        // The producer may have pushed right before it was dropped, so check again.
+        if buffer.can_read(read_pos) {
+            Ok(buffer.take(read_pos))
+        } else if ready && self.is_closed() {
+            Err(PopTimeoutError::Closed)
+        } else if ready {
+            Err(PopTimeoutError::Disconnected)
+        } else {
+            Err(PopTimeoutError::Timeout)
+        }
+    }
+
+    pub fn try_pop(&mut self) -> Option<T> {
+        self.buffer.try_pop()
+    }
+
+    /// Borrows the next item without popping it.
+    pub fn peek(&self) -> Option<&T> {
+        let buffer = &*self.buffer;
+        let read_pos = buffer.read.pos.load(Ordering::Relaxed);
+        if !buffer.can_read(read_pos) {
+            return None;
+        }
+        let i = index::index(read_pos, buffer.size);
+        buffer.items.track_read(i);
+        unsafe { Some((*buffer.items.slot(i)).assume_init_ref()) }
+    }
+
+    /// Pops the next item only if `pred` accepts it.
+    pub fn pop_if<F: FnOnce(&T) -> bool>(&mut self, pred: F) -> Option<T> {
+        if !pred(self.peek()?) {
+            return None;
+        }
+        self.try_pop()
+    }
+
+    /// Pops the next item by running `f` on it in its slot and dropping it there afterwards, so
+    /// large items are never moved out. The item stays buffered if `f` panics.
+    pub fn pop_with<R, F: FnOnce(&mut T) -> R>(&mut self, f: F) -> Option<R> {
+        let buffer = &*self.buffer;
+        let read_pos = buffer.read.pos.load(Ordering::Relaxed);
+        if !buffer.can_read(read_pos) {
+            buffer.stats.empty();
+            return None;
+        }
+        let i = index::index(read_pos, buffer.size);
+        buffer.items.track_write(i);
+        unsafe {
+            let slot = &mut *buffer.items.slot(i);
+            let result = f(slot.assume_init_mut());
+            // Popped even if dropping it panics, the drop glue has run by then.
+            let _release = Release { buffer, read_pos, moved: 1 };
+            slot.assume_init_drop();
+            Some(result)
+        }
+    }
+
+    /// Mutably borrows the next item without popping it.
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        let buffer = &*self.buffer;
+        let read_pos = buffer.read.pos.load(Ordering::Relaxed);
+        if !buffer.can_read(read_pos) {
+            return None;
+        }
+        let i = index::index(read_pos, buffer.size);
+        buffer.items.track_write(i);
+        unsafe { Some((*buffer.items.slot(i)).assume_init_mut()) }
+    }
+
+    /// Borrows the buffered items in pop order, the second slice is only non-empty when they
+    /// wrap around the end of the storage.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let buffer = &*self.buffer;
+        let read_pos = buffer.read.pos.load(Ordering::Relaxed);
+        let write_pos = buffer.write.pos.load(Ordering::Acquire);
+        buffer.read.cached.set(write_pos);
+        let len = index::distance(write_pos, read_pos, buffer.size);
+        let start = index::index(read_pos, buffer.size);
+        let first = len.min(buffer.size - start);
+        unsafe {
+            let items = buffer.items.slot(0) as *const T;
+            (slice::from_raw_parts(items.add(start), first),
+             slice::from_raw_parts(items, len - first))
+        }
+    }
+
+    /// Clones the buffered items in pop order without popping them. Items pushed while cloning
+    /// are left out, so this is what a pop loop would have seen at the start.
+    pub fn snapshot(&self) -> Vec<T> where T: Clone {
+        let (head, tail) = self.as_slices();
+        let mut items = Vec::with_capacity(head.len() + tail.len());
+        items.extend_from_slice(head);
+        items.extend_from_slice(tail);
+        items
+    }
+
+    /// Blocks until `size` items are read, returning fewer if the producer is gone.
+    #[cfg(feature = "std")]
+    pub fn read(&mut self, size: usize) -> Vec<T> {
+        let mut v = Vec::with_capacity(size);
+        while v.len() < size {
+            match self.pop() {
+                Ok(item) => v.push(item),
+                Err(_) => break,
+            }
         }
         v
     }
 
+    pub fn try_read(&mut self, size: usize) -> Vec<T> {
+        self.buffer.try_read(size)
+    }
+
+    /// Pops items into `buf` until it is full or the buffer is empty, returns the number of
+    /// items popped.
+    pub fn read_into(&mut self, buf: &mut [T]) -> usize {
+        self.buffer.take_into(buf)
+    }
+
+    /// Blocks until `buf` is filled, failing if the producer is gone first. What part of `buf`
+    /// was overwritten is unspecified then.
+    #[cfg(feature = "std")]
+    pub fn read_exact_into(&mut self, buf: &mut [T]) -> Result<(), Disconnected> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let n = self.buffer.take_into(&mut buf[filled..]);
+            filled += n;
+            if n == 0 {
+                let buffer = &*self.buffer;
+                let ready = || !buffer.is_empty() || self.is_abandoned();
+                buffer.pop_waiter.wait(ready);
+                // The producer may have pushed right before it was dropped.
+                if buffer.is_empty() {
+                    return Err(Disconnected);
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn len(&self) -> usize {
-        let read_pos = self.read_pos.load(Ordering::Acquire);
-        let write_pos = self.write_pos.load(Ordering::Acquire);
-        write_pos - read_pos
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Whether the producer has been dropped or closed the buffer, there may still be items
+    /// left to pop.
+    pub fn is_abandoned(&self) -> bool {
+        self.buffer.abandoned.load(Ordering::Acquire)
+    }
+
+    /// Whether the producer closed the buffer, rather than just being dropped.
+    pub fn is_closed(&self) -> bool {
+        self.is_abandoned() && self.buffer.closed.load(Ordering::Relaxed)
+    }
+
+    /// Snapshot of the buffer's counters.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        self.buffer.stats.snapshot()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.buffer.abandoned.store(true, Ordering::Release);
+        self.buffer.push_waiter.notify();
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use ::RingBuffer;
+    use {Disconnected, NewError, PopError, PopTimeoutError, PushError, PushTimeoutError};
+    use RingBuffer;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
     #[test]
     fn push_pop() {
         let rb = RingBuffer::new(1);
@@ -150,9 +1380,9 @@ mod tests {
     #[test]
     fn try_push_pop() {
         let rb = RingBuffer::new(2);
-        assert_eq!(Some(()), rb.try_push(1));
-        assert_eq!(Some(()), rb.try_push(2));
-        assert_eq!(None, rb.try_push(3));
+        assert_eq!(Ok(()), rb.try_push(1));
+        assert_eq!(Ok(()), rb.try_push(2));
+        assert_eq!(Err(PushError::Full(3)), rb.try_push(3));
         assert_eq!(Some(1), rb.try_pop());
         assert_eq!(Some(2), rb.try_pop());
         assert_eq!(None, rb.try_pop());
@@ -170,7 +1400,7 @@ mod tests {
     #[test]
     fn write() {
         let rb = RingBuffer::new(3);
-        rb.write(&vec![1,2,3]);
+        rb.write(&[1,2,3]);
         assert_eq!(rb.read(3), vec![1,2,3]);
     }
 
@@ -186,7 +1416,553 @@ mod tests {
     #[test]
     fn try_write() {
         let rb = RingBuffer::new(3);
-        rb.try_write(&vec![1,2,3,4]);
+        rb.try_write(&[1,2,3,4]);
         assert_eq!(rb.read(3), vec![1,2,3]);
     }
+
+    #[test]
+    fn split() {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        p.push(1).unwrap();
+        assert_eq!(c.len(), 1);
+        assert_eq!(c.pop(), Ok(1));
+        assert_eq!(c.try_pop(), None);
+        assert!(p.is_empty());
+    }
+
+    #[test]
+    fn peek() {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        assert_eq!(c.peek(), None);
+        p.push(1).unwrap();
+        p.push(2).unwrap();
+        assert_eq!(c.peek(), Some(&1));
+        *c.peek_mut().unwrap() += 2;
+        assert_eq!(c.pop(), Ok(3));
+        assert_eq!(c.peek(), Some(&2));
+    }
+
+    #[test]
+    fn as_slices() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        assert_eq!(c.as_slices(), (&[][..], &[][..]));
+        for i in 0..3 {
+            p.push(i).unwrap();
+        }
+        assert_eq!(c.as_slices(), (&[0, 1, 2][..], &[][..]));
+        c.pop().unwrap();
+        c.pop().unwrap();
+        for i in 3..6 {
+            p.push(i).unwrap();
+        }
+        assert_eq!(c.as_slices(), (&[2, 3][..], &[4, 5][..]));
+    }
+
+    #[test]
+    fn snapshot() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        for i in 0..4 {
+            p.try_push(i).unwrap();
+        }
+        c.try_pop().unwrap();
+        p.try_push(4).unwrap();
+        assert_eq!(c.snapshot(), vec![1, 2, 3, 4]);
+        assert_eq!(c.len(), 4);
+    }
+
+    #[test]
+    fn split_threads() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let t = thread::spawn(move || {
+            for i in 0..1000 {
+                p.push(i).unwrap();
+            }
+        });
+        for i in 0..1000 {
+            assert_eq!(c.pop(), Ok(i));
+        }
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn blocked_push_wakes() {
+        let (mut p, mut c) = RingBuffer::new(1).split();
+        p.push(0).unwrap();
+        let t = thread::spawn(move || {
+            p.push(1).unwrap();
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(c.pop(), Ok(0));
+        assert_eq!(c.pop(), Ok(1));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn dropped_producer_disconnects() {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        p.push(1).unwrap();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            p.push(2).unwrap();
+        });
+        assert_eq!(c.read(3), vec![1, 2]);
+        t.join().unwrap();
+        assert!(c.is_abandoned());
+        assert_eq!(c.pop(), Err(PopError::Disconnected));
+    }
+
+    #[test]
+    fn dropped_consumer_disconnects() {
+        let (mut p, c) = RingBuffer::new(1).split();
+        p.push(1).unwrap();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(c);
+        });
+        assert_eq!(p.push(2), Err(PushError::Disconnected(2)));
+        t.join().unwrap();
+        assert!(p.is_abandoned());
+        assert_eq!(p.try_push(3), Err(PushError::Disconnected(3)));
+    }
+
+    #[test]
+    fn timeouts() {
+        let (mut p, mut c) = RingBuffer::new(1).split();
+        let timeout = Duration::from_millis(20);
+        assert_eq!(c.pop_timeout(timeout), Err(PopTimeoutError::Timeout));
+        assert_eq!(p.push_timeout(1, timeout), Ok(()));
+        assert_eq!(p.push_timeout(2, timeout), Err(PushTimeoutError::Timeout(2)));
+        assert_eq!(c.pop_timeout(timeout), Ok(1));
+        drop(p);
+        assert_eq!(c.pop_timeout(timeout), Err(PopTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn shared_deadline() {
+        let (mut p, mut c) = RingBuffer::new(1).split();
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert_eq!(p.push_deadline(1, deadline), Ok(()));
+        assert_eq!(p.push_deadline(2, deadline), Err(PushTimeoutError::Timeout(2)));
+        assert!(Instant::now() >= deadline);
+        assert_eq!(c.pop_deadline(deadline), Ok(1));
+        assert_eq!(c.pop_deadline(deadline), Err(PopTimeoutError::Timeout));
+    }
+
+    #[test]
+    fn pop_timeout_wakes() {
+        let (mut p, mut c) = RingBuffer::new(1).split();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            p.push(1).unwrap();
+        });
+        assert_eq!(c.pop_timeout(Duration::from_secs(10)), Ok(1));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn close() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let t = thread::spawn(move || {
+            for i in 0..3 {
+                p.push(i).unwrap();
+            }
+            p.close();
+            assert_eq!(p.try_push(3), Err(PushError::Disconnected(3)));
+            p
+        });
+        for i in 0..3 {
+            assert_eq!(c.pop(), Ok(i));
+        }
+        assert_eq!(c.pop(), Err(PopError::Closed));
+        assert!(c.is_closed());
+        assert_eq!(c.pop_timeout(Duration::from_millis(1)), Err(PopTimeoutError::Closed));
+        drop(t.join().unwrap());
+        let (p, c) = RingBuffer::<u8>::new(1).split();
+        drop(p);
+        assert!(c.is_abandoned() && !c.is_closed());
+    }
+
+    #[test]
+    fn panicking_callbacks() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        struct Counted(Arc<AtomicUsize>, bool);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                if self.1 {
+                    panic!("dropping");
+                }
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let items = (0..3).map(|i| if i < 2 { Counted(drops.clone(), false) } else { panic!() });
+        assert!(catch_unwind(AssertUnwindSafe(|| p.push_all(items))).is_err());
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+        assert!(c.is_empty());
+
+        p.try_push(Counted(drops.clone(), true)).ok().unwrap();
+        assert!(catch_unwind(AssertUnwindSafe(|| c.pop_with(|_| panic!()))).is_err());
+        assert_eq!((c.len(), drops.load(Ordering::Relaxed)), (1, 2));
+        // Dropped exactly once though its drop panics.
+        assert!(catch_unwind(AssertUnwindSafe(|| c.pop_with(|_| ()))).is_err());
+        assert_eq!((c.len(), drops.load(Ordering::Relaxed)), (0, 3));
+
+        p.try_push(Counted(drops.clone(), true)).ok().unwrap();
+        assert!(catch_unwind(AssertUnwindSafe(|| c.read_chunk(1).commit(1))).is_err());
+        assert_eq!((c.len(), drops.load(Ordering::Relaxed)), (0, 4));
+        drop((p, c));
+        assert_eq!(drops.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn panicking_clone() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        struct Flaky(Arc<AtomicUsize>, bool);
+
+        impl Clone for Flaky {
+            fn clone(&self) -> Flaky {
+                assert!(!self.1, "cloning");
+                Flaky(self.0.clone(), false)
+            }
+        }
+
+        impl Drop for Flaky {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        p.try_write(&[Flaky(drops.clone(), false), Flaky(drops.clone(), false)]);
+        c.try_read(2);
+        assert_eq!(drops.load(Ordering::Relaxed), 4);
+        // The clones written on both sides of the wrap before the panic are dropped.
+        let items: Vec<_> = (0..4).map(|i| Flaky(drops.clone(), i == 3)).collect();
+        assert!(catch_unwind(AssertUnwindSafe(|| p.try_write(&items))).is_err());
+        assert_eq!(drops.load(Ordering::Relaxed), 7);
+        assert!(c.is_empty());
+        drop((items, p, c));
+        assert_eq!(drops.load(Ordering::Relaxed), 11);
+    }
+
+    #[test]
+    fn drops_remaining_items() {
+        struct Counted(Arc<AtomicUsize>);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut p, mut c) = RingBuffer::new(3).split();
+        for _ in 0..3 {
+            p.push(Counted(drops.clone())).unwrap();
+        }
+        c.pop().unwrap();
+        c.pop().unwrap();
+        // Wrap around so the initialized slots aren't a prefix of the storage.
+        p.push(Counted(drops.clone())).unwrap();
+        p.push(Counted(drops.clone())).unwrap();
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+        drop(p);
+        drop(c);
+        assert_eq!(drops.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn capacity_rounds_up() {
+        assert_eq!(RingBuffer::<u8>::new(3).capacity(), 4);
+        assert_eq!(RingBuffer::<u8>::new(4).capacity(), 4);
+        let (p, c) = RingBuffer::<u8>::new(5).split();
+        assert_eq!(p.capacity(), 8);
+        assert_eq!(c.capacity(), 8);
+    }
+
+    #[test]
+    fn read_into() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let mut buf = [0; 3];
+        assert_eq!(c.read_into(&mut buf), 0);
+        p.try_write(&[1, 2]);
+        assert_eq!(c.read_into(&mut buf), 2);
+        assert_eq!(buf, [1, 2, 0]);
+        let t = thread::spawn(move || {
+            for i in 0..10 {
+                p.push(i).unwrap();
+            }
+        });
+        let mut buf = [0; 6];
+        assert_eq!(c.read_exact_into(&mut buf), Ok(()));
+        assert_eq!(buf, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(c.read_exact_into(&mut buf), Err(Disconnected));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn bulk_wraps() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let mut buf = [0u32; 4];
+        p.try_write(&[1, 2, 3]);
+        assert_eq!(c.read_into(&mut buf[..2]), 2);
+        assert_eq!(p.try_write(&[4, 5, 6, 7]), 3);
+        assert_eq!(c.read_into(&mut buf), 4);
+        assert_eq!(buf, [3, 4, 5, 6]);
+        // Items which need dropping take the item by item path.
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let strings: Vec<String> = (0..6).map(|i| i.to_string()).collect();
+        p.try_write(&strings[..3]);
+        let mut buf = vec![String::new(); 4];
+        assert_eq!(c.read_into(&mut buf[..2]), 2);
+        assert_eq!(p.try_write(&strings[3..]), 3);
+        assert_eq!(c.read_into(&mut buf), 4);
+        assert_eq!(buf, &strings[2..]);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn pop_if() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        assert_eq!(c.pop_if(|_| true), None);
+        p.try_write(&[1, 2, 3, 4]);
+        assert_eq!(c.pop_if(|&i| i == 1), Some(1));
+        assert_eq!(c.pop_if(|&i| i == 1), None);
+        assert_eq!(c.pop_while(|&i| i < 4).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(c.try_pop(), Some(4));
+    }
+
+    #[test]
+    fn try_new() {
+        assert_eq!(RingBuffer::<u8>::try_new(5).map(|rb| rb.capacity()), Ok(8));
+        assert_eq!(RingBuffer::<u8>::try_new(0).err(), Some(NewError::ZeroCapacity));
+        assert_eq!(RingBuffer::<u8>::try_new(usize::MAX).err(), Some(NewError::TooLarge));
+        let huge = RingBuffer::<[u8; 1 << 16]>::try_new(::index::MAX_SIZE);
+        assert_eq!(huge.err(), Some(NewError::AllocFailed));
+    }
+
+    #[test]
+    #[cfg(feature = "allocator_api")]
+    fn new_in() {
+        use std::alloc::{AllocError, Allocator, Global, Layout};
+        use std::ptr::NonNull;
+
+        // Counts the bytes it currently has handed out.
+        struct Tracked(Arc<AtomicUsize>);
+
+        unsafe impl Allocator for Tracked {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.fetch_add(layout.size(), Ordering::SeqCst);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.0.fetch_sub(layout.size(), Ordering::SeqCst);
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        let used = Arc::new(AtomicUsize::new(0));
+        let (mut p, mut c) = RingBuffer::new_in(3, Tracked(used.clone())).split();
+        assert_eq!(used.load(Ordering::SeqCst), 4 * 8);
+        p.try_push(1u64).unwrap();
+        assert_eq!(c.try_pop(), Some(1));
+        drop((p, c));
+        assert_eq!(used.load(Ordering::SeqCst), 0);
+        assert!(RingBuffer::<u8>::try_new_in(0, Global).is_err());
+    }
+
+    #[test]
+    fn zero_sized() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Token;
+
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        // The storage of a zero sized item is never allocated, however large the capacity.
+        let rb = RingBuffer::<()>::try_new(::index::MAX_SIZE).unwrap();
+        assert_eq!(rb.capacity(), ::index::MAX_SIZE);
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        p.try_push(Token).unwrap();
+        p.try_push(Token).unwrap();
+        assert!(p.try_push(Token).is_err());
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+        assert_eq!(c.len(), 2);
+        drop(c.try_pop().unwrap());
+        assert_eq!(c.try_read(4).len(), 1);
+        drop((p, c));
+        assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+
+        // Capacity only signaling between threads.
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let t = thread::spawn(move || {
+            for _ in 0..10_000 {
+                p.push(()).unwrap();
+            }
+        });
+        for _ in 0..10_000 {
+            assert_eq!(c.pop(), Ok(()));
+        }
+        t.join().unwrap();
+        assert_eq!(c.pop(), Err(PopError::Disconnected));
+    }
+
+    #[test]
+    #[cfg(feature = "strict-ordering")]
+    fn strict_ordering() {
+        use std::panic;
+
+        assert_eq!(::sync::atomic::Ordering::Relaxed, Ordering::SeqCst);
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        p.try_push(1).unwrap();
+        assert_eq!(c.try_pop(), Some(1));
+        // Positions further apart than the capacity can only come from a broken ordering.
+        assert!(panic::catch_unwind(|| ::index::distance(3, 0, 2)).is_err());
+    }
+
+    #[test]
+    fn with_alignment() {
+        let rb = RingBuffer::with_alignment(4, 4096);
+        assert_eq!(rb.items.slot(0) as usize % 4096, 0);
+        let (mut p, mut c) = rb.split();
+        p.try_push([1f32; 16]).unwrap();
+        assert_eq!(c.try_pop(), Some([1f32; 16]));
+        let rb = RingBuffer::<()>::with_alignment(2, 64);
+        assert_eq!(rb.items.slot(0) as usize % 64, 0);
+    }
+
+    #[test]
+    #[cfg(all(feature = "hugepages", unix))]
+    fn huge_pages() {
+        use std::rc::Rc;
+
+        let rb = RingBuffer::with_huge_pages(1000).unwrap();
+        assert_eq!(rb.capacity(), 1024);
+        let items = rb.items.slot(0);
+        assert_eq!(items as usize % (2 << 20), 0);
+        let (mut p, mut c) = rb.split();
+        for i in 0..2000 {
+            p.try_push(Rc::new(i)).unwrap();
+            assert_eq!(c.try_pop().map(|i| *i), Some(i));
+        }
+        p.try_push(Rc::new(0)).unwrap();
+    }
+
+    #[test]
+    #[cfg(all(feature = "numa", target_os = "linux"))]
+    fn on_node() {
+        use NumaNode;
+
+        let node = NumaNode::Current.id().unwrap();
+        let (mut p, mut c) = RingBuffer::on_node(3, NumaNode::Id(node)).unwrap().split();
+        assert_eq!(p.capacity(), 4);
+        for i in 0..10 {
+            p.try_push(i).unwrap();
+            assert_eq!(c.try_pop(), Some(i));
+        }
+        assert!(RingBuffer::<u8>::on_node(4, NumaNode::Id(1 << 20)).is_err());
+    }
+
+    #[test]
+    fn push_batch() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        p.try_push(0).unwrap();
+        p.push_batch(&[1, 2]).unwrap();
+        assert_eq!(p.try_push_batch(&[3, 4]), Err(PushError::Full(())));
+        assert_eq!(c.try_read(2), vec![0, 1]);
+        // The batch wraps around the end of the storage.
+        assert_eq!(p.try_push_batch(&[3, 4, 5]), Ok(()));
+        let mut rest = 6..10;
+        assert_eq!(p.push_all(&mut rest), 0);
+        assert_eq!(c.try_read(2), vec![2, 3]);
+        assert_eq!(p.push_all(&mut rest), 2);
+        assert_eq!(rest.next(), Some(8));
+        assert_eq!(c.try_read(8), vec![4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn batch_is_atomic() {
+        let (mut p, mut c) = RingBuffer::new(8).split();
+        let t = thread::spawn(move || {
+            for i in 0..1000 {
+                p.push_batch(&[i; 4]).unwrap();
+            }
+        });
+        let mut seen = 0;
+        while seen < 4000 {
+            let items = c.try_read(8);
+            // The consumer only ever sees whole batches.
+            assert_eq!(items.len() % 4, 0);
+            seen += items.len();
+        }
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn pop_with() {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        assert_eq!(c.pop_with(|big: &mut [u8; 4096]| big[0]), None);
+        p.try_push([3u8; 4096]).unwrap();
+        p.try_push([4u8; 4096]).unwrap();
+        let sum = c.pop_with(|big| big.iter().map(|&b| b as usize).sum::<usize>());
+        assert_eq!(sum, Some(3 * 4096));
+        assert_eq!(c.len(), 1);
+        assert_eq!(c.pop_with(|big| big[4095]), Some(4));
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn push_with() {
+        let (mut p, mut c) = RingBuffer::new(1).split();
+        assert!(p.push_with(|| [7u8; 4096]).is_ok());
+        let err = p.try_push_with(|| -> [u8; 4096] { panic!("built without a free slot") });
+        assert!(err.unwrap_err().is_full());
+        assert_eq!(c.try_pop().map(|big| big[4095]), Some(7));
+        unsafe {
+            p.try_push_with_slot(|slot| {
+                slot.write([1; 4096]);
+            }).ok().unwrap();
+        }
+        assert_eq!(c.try_pop().map(|big| big[0]), Some(1));
+        drop(c);
+        assert!(p.push_with(|| [0; 4096]).unwrap_err().is_disconnected());
+    }
+
+    #[test]
+    fn occupancy() {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        assert!(c.is_empty() && !c.is_full());
+        assert_eq!(p.slots_free(), 2);
+        p.try_push(1).unwrap();
+        assert!(!p.is_empty() && !p.is_full());
+        p.try_push(2).unwrap();
+        assert!(c.is_full());
+        assert_eq!(p.slots_free(), 0);
+        c.try_pop();
+        assert_eq!(c.slots_free(), 1);
+    }
+
+    #[test]
+    fn positions_stay_bounded() {
+        let rb = RingBuffer::new(3);
+        for i in 0..20 {
+            rb.push(i);
+            rb.push(i);
+            assert_eq!(rb.len(), 2);
+            assert_eq!(rb.try_read(2), vec![i, i]);
+            assert!(rb.write.pos.load(Ordering::Relaxed) < 2 * rb.capacity());
+            assert!(rb.read.pos.load(Ordering::Relaxed) < 2 * rb.capacity());
+        }
+    }
 }
@@ -0,0 +1,116 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Stream;
+
+use Consumer;
+
+/// Stream of the items of several consumers, in whatever order they arrive.
+///
+/// Polling registers the task with every consumer still waiting for items, so a push into any
+/// of them wakes it. The consumers are polled round robin, starting after the one which last
+/// yielded, so a busy one can't starve the others. A consumer whose producer is gone is dropped
+/// once drained, and the stream ends when none are left.
+pub struct Merge<T> {
+    consumers: Vec<Consumer<T>>,
+    next: usize,
+}
+
+impl<T> Merge<T> {
+    pub fn new<I: IntoIterator<Item = Consumer<T>>>(consumers: I) -> Merge<T> {
+        Merge { consumers: consumers.into_iter().collect(), next: 0 }
+    }
+
+    /// Adds another consumer to the stream.
+    pub fn push(&mut self, consumer: Consumer<T>) {
+        self.consumers.push(consumer);
+    }
+
+    /// Number of consumers whose items may still arrive.
+    pub fn len(&self) -> usize {
+        self.consumers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.consumers.is_empty()
+    }
+
+    /// Hands back the consumers not yet finished.
+    pub fn into_inner(self) -> Vec<Consumer<T>> {
+        self.consumers
+    }
+}
+
+impl<T> Stream for Merge<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        let mut polled = 0;
+        while polled < this.consumers.len() {
+            let i = (this.next + polled) % this.consumers.len();
+            match this.consumers[i].poll_pop(cx) {
+                Poll::Ready(Ok(item)) => {
+                    this.next = i + 1;
+                    return Poll::Ready(Some(item));
+                }
+                // Drained with the producer gone. The indices shift, so start over from the one
+                // moving into its place, polling the others again is harmless.
+                Poll::Ready(Err(_)) => {
+                    this.consumers.remove(i);
+                    this.next = i;
+                    polled = 0;
+                }
+                Poll::Pending => polled += 1,
+            }
+        }
+        if this.consumers.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::thread;
+    use {Merge, RingBuffer};
+
+    #[test]
+    fn merges() {
+        let mut merge = Merge::new(None);
+        let mut threads = Vec::new();
+        for n in 0..3 {
+            let (mut p, c) = RingBuffer::new(2).split();
+            merge.push(c);
+            threads.push(thread::spawn(move || {
+                for i in 0..100 {
+                    p.push(n * 100 + i).unwrap();
+                }
+            }));
+        }
+        assert_eq!(merge.len(), 3);
+        let mut items: Vec<_> = block_on(merge.collect());
+        items.sort();
+        assert_eq!(items, (0..300).collect::<Vec<_>>());
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn round_robin() {
+        let (mut p1, c1) = RingBuffer::new(4).split();
+        let (mut p2, c2) = RingBuffer::new(4).split();
+        for i in 0..2 {
+            p1.try_push(i).unwrap();
+            p2.try_push(10 + i).unwrap();
+        }
+        drop((p1, p2));
+        let merge = Merge::new(vec![c1, c2]);
+        assert_eq!(block_on(merge.collect::<Vec<_>>()), vec![0, 10, 1, 11]);
+    }
+}
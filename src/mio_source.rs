@@ -0,0 +1,64 @@
+use std::io;
+
+use mio::event::Source;
+use mio::unix::SourceFd;
+use mio::{Interest, Registry, Token};
+
+use Consumer;
+
+/// Registers the descriptor from `readiness_fd`, so the consumer gets a readable event once
+/// items arrive or the producer is dropped. Call `clear_readiness` before draining the buffer
+/// after each event.
+impl<T> Source for Consumer<T> {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest)
+                -> io::Result<()> {
+        SourceFd(&self.readiness_fd()?).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest)
+                  -> io::Result<()> {
+        SourceFd(&self.readiness_fd()?).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.readiness_fd()?).deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::RingBuffer;
+    use mio::{Events, Interest, Poll, Token};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn poll_consumer() {
+        let (mut p, mut c) = RingBuffer::new(8).split();
+        let mut poll = Poll::new().unwrap();
+        poll.registry().register(&mut c, Token(7), Interest::READABLE).unwrap();
+        let mut events = Events::with_capacity(4);
+        poll.poll(&mut events, Some(Duration::from_millis(10))).unwrap();
+        assert!(events.is_empty());
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                p.push(i).unwrap();
+            }
+        });
+        let mut next = 0;
+        while next < 100 || !c.is_abandoned() {
+            poll.poll(&mut events, None).unwrap();
+            for event in &events {
+                assert_eq!(event.token(), Token(7));
+                assert!(event.is_readable());
+            }
+            c.clear_readiness();
+            while let Some(item) = c.try_pop() {
+                assert_eq!(item, next);
+                next += 1;
+            }
+        }
+        t.join().unwrap();
+        poll.registry().deregister(&mut c).unwrap();
+    }
+}
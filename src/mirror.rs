@@ -0,0 +1,386 @@
+//! Byte ring buffer whose storage is mapped twice back to back.
+//!
+//! Byte `i` of the storage is also visible at `i + capacity`, so whatever is buffered or free
+//! can always be borrowed as one contiguous slice, even across the wrap point. Only available
+//! on unix and Windows with the `mirror` feature, Windows 10 version 1803 or later as it needs
+//! `VirtualAlloc2`.
+
+#[cfg(windows)]
+use std::ffi::c_void;
+use std::io;
+#[cfg(windows)]
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::AtomicUsize;
+use sync::atomic::Ordering;
+use std::sync::Arc;
+
+#[cfg(unix)]
+use libc;
+#[cfg(windows)]
+use windows_sys::Win32::Foundation as win;
+#[cfg(windows)]
+use windows_sys::Win32::System::Memory as memory;
+#[cfg(windows)]
+use windows_sys::Win32::System::SystemInformation as sysinfo;
+#[cfg(windows)]
+use windows_sys::Win32::System::Threading as threading;
+
+use cache_padded::CachePadded;
+use index;
+
+/// Lockfree SPSC byte ring buffer backed by a mirrored mapping.
+///
+/// The capacity is rounded up to a power of two multiple of the page size, of the allocation
+/// granularity on Windows.
+pub struct MirroredRingBuffer {
+    size: usize,
+    base: *mut u8,
+    write: CachePadded<AtomicUsize>,
+    read: CachePadded<AtomicUsize>,
+}
+
+unsafe impl Send for MirroredRingBuffer { }
+// Only the split halves can touch the bytes.
+unsafe impl Sync for MirroredRingBuffer { }
+
+/// Writing half of a split `MirroredRingBuffer`.
+pub struct Producer {
+    buffer: Arc<MirroredRingBuffer>,
+}
+
+/// Reading half of a split `MirroredRingBuffer`.
+pub struct Consumer {
+    buffer: Arc<MirroredRingBuffer>,
+}
+
+impl MirroredRingBuffer {
+    pub fn new(size: usize) -> io::Result<MirroredRingBuffer> {
+        let size = index::capacity(size.max(granularity()));
+        let base = unsafe { map_mirrored(size)? };
+        Ok(MirroredRingBuffer {
+            size,
+            base,
+            write: CachePadded::new(AtomicUsize::new(0)),
+            read: CachePadded::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Number of bytes the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Splits the buffer into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer, Consumer) {
+        let buffer = Arc::new(self);
+        (Producer { buffer: buffer.clone() }, Consumer { buffer })
+    }
+
+    fn len(&self) -> usize {
+        let read_pos = self.read.load(Ordering::Acquire);
+        let write_pos = self.write.load(Ordering::Acquire);
+        index::distance(write_pos, read_pos, self.size)
+    }
+
+    /// `len` bytes starting at position `pos`, running into the mirror if they wrap.
+    unsafe fn bytes(&self, pos: usize, len: usize) -> *mut u8 {
+        debug_assert!(len <= self.size);
+        self.base.add(index::index(pos, self.size))
+    }
+}
+
+impl Drop for MirroredRingBuffer {
+    fn drop(&mut self) {
+        unsafe { unmap_mirrored(self.base, self.size) };
+    }
+}
+
+/// What the size of each half has to be a multiple of.
+#[cfg(unix)]
+fn granularity() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Maps `size` bytes of anonymous shared memory twice in a row.
+#[cfg(unix)]
+unsafe fn map_mirrored(size: usize) -> io::Result<*mut u8> {
+    let fd = anonymous_fd()?;
+    let result = map_fd_twice(fd, size);
+    libc::close(fd);
+    result
+}
+
+#[cfg(unix)]
+unsafe fn map_fd_twice(fd: libc::c_int, size: usize) -> io::Result<*mut u8> {
+    if libc::ftruncate(fd, size as libc::off_t) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Reserve both halves first so nothing else can end up in between.
+    let base = libc::mmap(ptr::null_mut(), 2 * size, libc::PROT_NONE,
+                          libc::MAP_PRIVATE | libc::MAP_ANON, -1, 0);
+    if base == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    for half in 0..2 {
+        let addr = (base as *mut u8).add(half * size) as *mut libc::c_void;
+        let mapped = libc::mmap(addr, size, libc::PROT_READ | libc::PROT_WRITE,
+                                libc::MAP_SHARED | libc::MAP_FIXED, fd, 0);
+        if mapped == libc::MAP_FAILED {
+            let err = io::Error::last_os_error();
+            libc::munmap(base, 2 * size);
+            return Err(err);
+        }
+    }
+    Ok(base as *mut u8)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn anonymous_fd() -> io::Result<libc::c_int> {
+    let name = b"ring-buffer\0".as_ptr() as *const libc::c_char;
+    let fd = libc::memfd_create(name, libc::MFD_CLOEXEC);
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+unsafe fn anonymous_fd() -> io::Result<libc::c_int> {
+    use std::ffi::CString;
+    use std::process;
+    use std::sync::atomic::AtomicUsize;
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let name = format!("/ring-buffer-{}-{}", process::id(), id);
+    let name = CString::new(name).unwrap();
+    let fd = libc::shm_open(name.as_ptr(), libc::O_RDWR | libc::O_CREAT | libc::O_EXCL, 0o600);
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Only the descriptor is needed, the name can go right away.
+    libc::shm_unlink(name.as_ptr());
+    Ok(fd)
+}
+
+#[cfg(unix)]
+unsafe fn unmap_mirrored(base: *mut u8, size: usize) {
+    libc::munmap(base as *mut libc::c_void, 2 * size);
+}
+
+/// Views of a section have to start at a multiple of the allocation granularity.
+#[cfg(windows)]
+fn granularity() -> usize {
+    let mut info: sysinfo::SYSTEM_INFO = unsafe { mem::zeroed() };
+    unsafe { sysinfo::GetSystemInfo(&mut info) };
+    info.dwAllocationGranularity as usize
+}
+
+/// Maps a section of `size` bytes backed by the paging file twice in a row.
+#[cfg(windows)]
+unsafe fn map_mirrored(size: usize) -> io::Result<*mut u8> {
+    let len = size as u64;
+    let section = memory::CreateFileMappingW(win::INVALID_HANDLE_VALUE, ptr::null(),
+                                             memory::PAGE_READWRITE, (len >> 32) as u32,
+                                             len as u32, ptr::null());
+    if section.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let result = map_section_twice(section, size);
+    // The views keep the section alive.
+    win::CloseHandle(section);
+    result
+}
+
+#[cfg(windows)]
+unsafe fn map_section_twice(section: win::HANDLE, size: usize) -> io::Result<*mut u8> {
+    let process = threading::GetCurrentProcess();
+    // Reserve both halves first so nothing else can end up in between, as a placeholder split
+    // in two for the views to replace.
+    let base = memory::VirtualAlloc2(process, ptr::null(), 2 * size,
+                                     memory::MEM_RESERVE | memory::MEM_RESERVE_PLACEHOLDER,
+                                     memory::PAGE_NOACCESS, ptr::null_mut(), 0);
+    if base.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let split = memory::MEM_RELEASE | memory::MEM_PRESERVE_PLACEHOLDER;
+    if memory::VirtualFree(base, size, split) == 0 {
+        let err = io::Error::last_os_error();
+        memory::VirtualFree(base, 0, memory::MEM_RELEASE);
+        return Err(err);
+    }
+    for half in 0..2 {
+        let addr = (base as *mut u8).add(half * size) as *mut c_void;
+        let view = memory::MapViewOfFile3(section, process, addr, 0, size,
+                                          memory::MEM_REPLACE_PLACEHOLDER, memory::PAGE_READWRITE,
+                                          ptr::null_mut(), 0);
+        if view.Value.is_null() {
+            let err = io::Error::last_os_error();
+            // The halves before this one are views already, the others still placeholders.
+            for i in 0..2 {
+                let at = (base as *mut u8).add(i * size) as *mut c_void;
+                if i < half {
+                    memory::UnmapViewOfFile(memory::MEMORY_MAPPED_VIEW_ADDRESS { Value: at });
+                } else {
+                    memory::VirtualFree(at, 0, memory::MEM_RELEASE);
+                }
+            }
+            return Err(err);
+        }
+    }
+    Ok(base as *mut u8)
+}
+
+/// Unmapping a view which replaced a placeholder frees its addresses as well.
+#[cfg(windows)]
+unsafe fn unmap_mirrored(base: *mut u8, size: usize) {
+    for half in 0..2 {
+        let view = base.add(half * size) as *mut c_void;
+        memory::UnmapViewOfFile(memory::MEMORY_MAPPED_VIEW_ADDRESS { Value: view });
+    }
+}
+
+impl Producer {
+    /// Borrows every free byte as one slice, fill a prefix of it and `commit` it.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        let buffer = &*self.buffer;
+        let write_pos = buffer.write.load(Ordering::Relaxed);
+        let free = buffer.size - buffer.len();
+        unsafe { slice::from_raw_parts_mut(buffer.bytes(write_pos, free), free) }
+    }
+
+    /// Publishes the first `n` bytes of `as_mut_slice`.
+    ///
+    /// # Panics
+    ///
+    /// If fewer than `n` bytes are free.
+    pub fn commit(&mut self, n: usize) {
+        let buffer = &*self.buffer;
+        assert!(n <= buffer.size - buffer.len(), "committing more bytes than are free");
+        let write_pos = buffer.write.load(Ordering::Relaxed);
+        buffer.write.store(index::advance(write_pos, n, buffer.size), Ordering::Release);
+    }
+
+    /// Copies as much of `data` as fits, returns the number of bytes written.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let n = {
+            let free = self.as_mut_slice();
+            let n = free.len().min(data.len());
+            free[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        self.commit(n);
+        n
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.size
+    }
+}
+
+impl Consumer {
+    /// Borrows every buffered byte as one slice.
+    pub fn as_slice(&self) -> &[u8] {
+        let buffer = &*self.buffer;
+        let read_pos = buffer.read.load(Ordering::Relaxed);
+        let len = buffer.len();
+        unsafe { slice::from_raw_parts(buffer.bytes(read_pos, len), len) }
+    }
+
+    /// Releases the first `n` bytes of `as_slice` back to the producer.
+    ///
+    /// # Panics
+    ///
+    /// If fewer than `n` bytes are buffered.
+    pub fn consume(&mut self, n: usize) {
+        let buffer = &*self.buffer;
+        assert!(n <= buffer.len(), "consuming more bytes than are buffered");
+        let read_pos = buffer.read.load(Ordering::Relaxed);
+        buffer.read.store(index::advance(read_pos, n, buffer.size), Ordering::Release);
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MirroredRingBuffer;
+    use std::thread;
+
+    #[test]
+    fn contiguous_across_wrap() {
+        let rb = MirroredRingBuffer::new(1).unwrap();
+        let cap = rb.capacity();
+        assert!(cap.is_power_of_two());
+        let (mut p, mut c) = rb.split();
+        let data: Vec<u8> = (0..cap).map(|i| i as u8).collect();
+        assert_eq!(p.write(&data[..cap - 2]), cap - 2);
+        c.consume(cap - 4);
+        assert_eq!(p.write(&[1, 2, 3, 4]), 4);
+        assert_eq!(c.as_slice(), &[data[cap - 4], data[cap - 3], 1, 2, 3, 4][..]);
+        assert_eq!(p.as_mut_slice().len(), cap - 6);
+    }
+
+    #[test]
+    fn threads() {
+        let (mut p, mut c) = MirroredRingBuffer::new(4096).unwrap().split();
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let expected = data.clone();
+        let t = thread::spawn(move || {
+            let mut rest = &data[..];
+            while !rest.is_empty() {
+                let n = p.write(&rest[..rest.len().min(1000)]);
+                rest = &rest[n..];
+                thread::yield_now();
+            }
+        });
+        let mut out = Vec::new();
+        while out.len() < expected.len() {
+            let n = {
+                let buffered = c.as_slice();
+                out.extend_from_slice(buffered);
+                buffered.len()
+            };
+            c.consume(n);
+            thread::yield_now();
+        }
+        assert_eq!(out, expected);
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,95 @@
+//! Loom models of the SPSC `RingBuffer`, run with
+//! `cargo test --release --features loom --lib model`.
+//!
+//! Every interleaving of the two threads is explored, including the stale values weaker
+//! orderings would let them see, and each slot access is checked to happen after the access
+//! of the other side it depends on.
+
+use loom::sync::Arc;
+use loom::thread;
+
+use RingBuffer;
+
+/// Spins on `op` until it succeeds, yielding so loom schedules the other thread.
+fn until<T, F: FnMut() -> Option<T>>(mut op: F) -> T {
+    loop {
+        if let Some(value) = op() {
+            return value;
+        }
+        thread::yield_now();
+    }
+}
+
+#[test]
+fn push_pop() {
+    loom::model(|| {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        let t = thread::spawn(move || {
+            for i in 0..3 {
+                until(|| p.try_push(i).err().map_or(Some(()), |_| None));
+            }
+        });
+        for i in 0..3 {
+            assert_eq!(until(|| c.try_pop()), i);
+        }
+        t.join().unwrap();
+    });
+}
+
+#[test]
+fn batches() {
+    loom::model(|| {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        let t = thread::spawn(move || {
+            let mut sent = 0;
+            while sent < 3 {
+                sent += p.try_write(&[sent, sent + 1][..(3 - sent).min(2)]);
+                thread::yield_now();
+            }
+        });
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            let mut buf = [0; 2];
+            let n = c.read_into(&mut buf);
+            received.extend_from_slice(&buf[..n]);
+            thread::yield_now();
+        }
+        assert_eq!(received, vec![0, 1, 2]);
+        t.join().unwrap();
+    });
+}
+
+#[test]
+fn drops_once() {
+    loom::model(|| {
+        let item = Arc::new(());
+        let (mut p, mut c) = RingBuffer::new(1).split();
+        let sent = item.clone();
+        let t = thread::spawn(move || {
+            let _ = p.try_push(sent);
+        });
+        drop(c.try_pop());
+        t.join().unwrap();
+        drop(c);
+        // Whether or not the pop saw it, the item was dropped exactly once.
+        assert_eq!(Arc::strong_count(&item), 1);
+    });
+}
+
+#[test]
+fn in_place() {
+    loom::model(|| {
+        let (mut p, mut c) = RingBuffer::new(1).split();
+        let t = thread::spawn(move || {
+            for i in 0..2 {
+                until(|| p.try_push(i).err().map_or(Some(()), |_| None));
+            }
+        });
+        // The single slot is reused right away, so each side's accesses of it must be ordered
+        // after the other's through the fences alone.
+        until(|| c.peek_mut().map(|item| *item += 10));
+        assert_eq!(c.pop_with(|item| *item), Some(10));
+        assert_eq!(until(|| c.pop_with(|item| *item)), 1);
+        t.join().unwrap();
+    });
+}
@@ -0,0 +1,222 @@
+use sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+use alloc::vec::Vec;
+
+use cache_padded::CachePadded;
+use error::PushError;
+use index;
+use slot::{self, Slot};
+#[cfg(feature = "std")]
+use wait::WaitStrategy;
+use waiter::Waiter;
+
+/// Lockfree bounded MPMC queue.
+///
+/// This is Dmitry Vyukov's bounded queue, both positions are claimed by CAS and each slot's
+/// stamp tells whether it is ready for the producer or the consumer of the current lap. Unlike
+/// the other buffers it is `Sync`, share it behind an `Arc`.
+pub struct MpmcRingBuffer<T> {
+    size: usize,
+    slots: Vec<Slot<T>>,
+    write_pos: CachePadded<AtomicUsize>,
+    read_pos: CachePadded<AtomicUsize>,
+    push_waiter: Waiter,
+    pop_waiter: Waiter,
+}
+
+unsafe impl<T: Send> Send for MpmcRingBuffer<T>{ }
+unsafe impl<T: Send> Sync for MpmcRingBuffer<T>{ }
+
+impl<T> MpmcRingBuffer<T> {
+    /// Creates a buffer holding at least `size` items, the capacity is rounded up to a power of
+    /// two.
+    pub fn new(size: usize) -> MpmcRingBuffer<T> {
+        let size = index::capacity(size);
+        let slots = (0..size).map(Slot::new).collect();
+        MpmcRingBuffer {
+            size,
+            slots,
+            write_pos: CachePadded::new(AtomicUsize::new(0)),
+            read_pos: CachePadded::new(AtomicUsize::new(0)),
+            push_waiter: Waiter::new(),
+            pop_waiter: Waiter::new(),
+        }
+    }
+
+    /// Like `new` but blocked threads wait according to `strategy` instead of parking.
+    #[cfg(feature = "std")]
+    pub fn with_strategy<S: WaitStrategy + 'static>(size: usize, strategy: S) -> MpmcRingBuffer<T> {
+        let strategy: Arc<dyn WaitStrategy> = Arc::new(strategy);
+        let mut rb = MpmcRingBuffer::new(size);
+        rb.push_waiter = Waiter::with_strategy(strategy.clone());
+        rb.pop_waiter = Waiter::with_strategy(strategy);
+        rb
+    }
+
+    #[cfg(feature = "std")]
+    pub fn push(&self, mut item: T) {
+        loop {
+            match self.try_push(item) {
+                Ok(()) => return,
+                Err(e) => item = e.into_inner(),
+            }
+            let write_pos = self.write_pos.load(Ordering::Relaxed);
+            self.push_waiter.wait(|| self.writable(write_pos));
+        }
+    }
+
+    /// Pushes an item unless the buffer is full, in which case it is handed back.
+    pub fn try_push(&self, item: T) -> Result<(), PushError<T>> {
+        let mut write_pos = self.write_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[write_pos & (self.size - 1)];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            let diff = stamp.wrapping_sub(write_pos) as isize;
+            if diff == 0 {
+                match self.write_pos.compare_exchange_weak(write_pos, write_pos.wrapping_add(1),
+                                                           Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        unsafe { slot.write(item); }
+                        slot.stamp.store(write_pos.wrapping_add(1), Ordering::Release);
+                        self.pop_waiter.notify();
+                        return Ok(());
+                    }
+                    Err(pos) => write_pos = pos,
+                }
+            } else if diff < 0 {
+                return Err(PushError::Full(item));
+            } else {
+                write_pos = self.write_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn pop(&self) -> T {
+        loop {
+            if let Some(item) = self.try_pop() {
+                return item;
+            }
+            let read_pos = self.read_pos.load(Ordering::Relaxed);
+            self.pop_waiter.wait(|| self.readable(read_pos));
+        }
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        let mut read_pos = self.read_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[read_pos & (self.size - 1)];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            let diff = stamp.wrapping_sub(read_pos.wrapping_add(1)) as isize;
+            if diff == 0 {
+                match self.read_pos.compare_exchange_weak(read_pos, read_pos.wrapping_add(1),
+                                                          Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        let item = unsafe { slot.take() };
+                        slot.stamp.store(read_pos.wrapping_add(self.size), Ordering::Release);
+                        self.push_waiter.notify();
+                        return Some(item);
+                    }
+                    Err(pos) => read_pos = pos,
+                }
+            } else if diff < 0 {
+                return None;
+            } else {
+                read_pos = self.read_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Whether waiting on the slot of `write_pos` is over, its last item was taken or another
+    /// producer claimed it.
+    #[cfg(feature = "std")]
+    fn writable(&self, write_pos: usize) -> bool {
+        let stamp = self.slots[write_pos & (self.size - 1)].stamp.load(Ordering::Acquire);
+        stamp.wrapping_sub(write_pos) as isize >= 0
+    }
+
+    /// Whether waiting on the slot of `read_pos` is over, it either holds its item or another
+    /// consumer took it. A claimed slot only counts once it is stamped, so there is no spinning
+    /// on a claim whose write or take is still in flight.
+    #[cfg(feature = "std")]
+    fn readable(&self, read_pos: usize) -> bool {
+        let stamp = self.slots[read_pos & (self.size - 1)].stamp.load(Ordering::Acquire);
+        stamp.wrapping_sub(read_pos.wrapping_add(1)) as isize >= 0
+    }
+
+    /// Number of claimed slots, this is only a snapshot while other threads are active.
+    pub fn len(&self) -> usize {
+        let read_pos = self.read_pos.load(Ordering::Acquire);
+        let write_pos = self.write_pos.load(Ordering::Acquire);
+        write_pos.wrapping_sub(read_pos).min(self.size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Number of items which can be pushed before the buffer is full.
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Number of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+}
+
+impl<T> Drop for MpmcRingBuffer<T> {
+    fn drop(&mut self) {
+        let read_pos = self.read_pos.load(Ordering::Relaxed);
+        let write_pos = self.write_pos.load(Ordering::Relaxed);
+        slot::drop_published(&mut self.slots, read_pos, write_pos);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use {MpmcRingBuffer, PushError};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn try_push_pop() {
+        let rb = MpmcRingBuffer::new(2);
+        assert_eq!(Ok(()), rb.try_push(1));
+        assert_eq!(Ok(()), rb.try_push(2));
+        assert_eq!(Err(PushError::Full(3)), rb.try_push(3));
+        assert_eq!(Some(1), rb.try_pop());
+        assert_eq!(Some(2), rb.try_pop());
+        assert_eq!(None, rb.try_pop());
+    }
+
+    #[test]
+    fn many_producers_consumers() {
+        let rb = Arc::new(MpmcRingBuffer::new(8));
+        let producers: Vec<_> = (0..4).map(|t| {
+            let rb = rb.clone();
+            thread::spawn(move || {
+                for i in 0..250 {
+                    rb.push(t * 250 + i);
+                }
+            })
+        }).collect();
+        let consumers: Vec<_> = (0..4).map(|_| {
+            let rb = rb.clone();
+            thread::spawn(move || (0..250).map(|_| rb.pop()).collect::<Vec<_>>())
+        }).collect();
+        for t in producers {
+            t.join().unwrap();
+        }
+        let mut seen: Vec<_> = consumers.into_iter().flat_map(|t| t.join().unwrap()).collect();
+        seen.sort();
+        assert_eq!(seen, (0..1000).collect::<Vec<_>>());
+        assert!(rb.is_empty());
+    }
+}
@@ -0,0 +1,380 @@
+use sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use sync::Arc;
+use alloc::vec::Vec;
+
+use cache_padded::CachePadded;
+#[cfg(feature = "std")]
+use error::Disconnected;
+use error::PushError;
+use index;
+use slot::{self, Slot};
+#[cfg(feature = "std")]
+use wait::WaitStrategy;
+use waiter::Waiter;
+
+/// Lockfree MPSC fixed size ring buffer.
+///
+/// Producers claim slots by CAS on the write position and mark them as published through a
+/// per slot stamp, so the consumer never observes a claimed but unwritten slot.
+pub struct MpscRingBuffer<T> {
+    size: usize,
+    slots: Vec<Slot<T>>,
+    write_pos: CachePadded<AtomicUsize>,
+    read_pos: CachePadded<AtomicUsize>,
+    push_waiter: Waiter,
+    pop_waiter: Waiter,
+    /// Live `Producer` handles, the last one to go abandons the buffer.
+    producers: AtomicUsize,
+    abandoned: AtomicBool,
+}
+
+unsafe impl<T: Send> Send for MpscRingBuffer<T>{ }
+
+/// Writing half of a split `MpscRingBuffer`, clone it to get more producers.
+pub struct Producer<T> {
+    buffer: Arc<MpscRingBuffer<T>>,
+}
+
+unsafe impl<T: Send> Send for Producer<T>{ }
+
+/// Reading half of a split `MpscRingBuffer`.
+pub struct Consumer<T> {
+    buffer: Arc<MpscRingBuffer<T>>,
+}
+
+unsafe impl<T: Send> Send for Consumer<T>{ }
+
+impl<T> MpscRingBuffer<T> {
+    /// Creates a buffer holding at least `size` items, the capacity is rounded up to a power of
+    /// two.
+    pub fn new(size: usize) -> MpscRingBuffer<T> {
+        let size = index::capacity(size);
+        let slots = (0..size).map(Slot::new).collect();
+        MpscRingBuffer {
+            size,
+            slots,
+            write_pos: CachePadded::new(AtomicUsize::new(0)),
+            read_pos: CachePadded::new(AtomicUsize::new(0)),
+            push_waiter: Waiter::new(),
+            pop_waiter: Waiter::new(),
+            producers: AtomicUsize::new(1),
+            abandoned: AtomicBool::new(false),
+        }
+    }
+
+    /// Like `new` but blocked threads wait according to `strategy` instead of parking.
+    #[cfg(feature = "std")]
+    pub fn with_strategy<S: WaitStrategy + 'static>(size: usize, strategy: S) -> MpscRingBuffer<T> {
+        let strategy: ::std::sync::Arc<dyn WaitStrategy> = ::std::sync::Arc::new(strategy);
+        let mut rb = MpscRingBuffer::new(size);
+        rb.push_waiter = Waiter::with_strategy(strategy.clone());
+        rb.pop_waiter = Waiter::with_strategy(strategy);
+        rb
+    }
+
+    #[cfg(feature = "std")]
+    pub fn push(&self, mut item: T) {
+        loop {
+            match self.try_push(item) {
+                Ok(()) => return,
+                Err(e) => item = e.into_inner(),
+            }
+            self.push_waiter.wait(|| self.slots_free() != 0);
+        }
+    }
+
+    /// Pushes an item unless the buffer is full, in which case it is handed back.
+    pub fn try_push(&self, item: T) -> Result<(), PushError<T>> {
+        let mut write_pos = self.write_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[write_pos & (self.size - 1)];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            let diff = stamp.wrapping_sub(write_pos) as isize;
+            if diff == 0 {
+                match self.write_pos.compare_exchange_weak(write_pos, write_pos.wrapping_add(1),
+                                                           Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        unsafe { slot.write(item); }
+                        slot.stamp.store(write_pos.wrapping_add(1), Ordering::Release);
+                        self.pop_waiter.notify();
+                        return Ok(());
+                    }
+                    Err(pos) => write_pos = pos,
+                }
+            } else if diff < 0 {
+                return Err(PushError::Full(item));
+            } else {
+                write_pos = self.write_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn pop(&self) -> T {
+        let read_pos = self.read_pos.load(Ordering::Relaxed);
+        let slot = &self.slots[read_pos & (self.size - 1)];
+        self.pop_waiter.wait(|| slot.stamp.load(Ordering::Acquire) == read_pos.wrapping_add(1));
+        self.take(read_pos)
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        let read_pos = self.read_pos.load(Ordering::Relaxed);
+        let slot = &self.slots[read_pos & (self.size - 1)];
+        if slot.stamp.load(Ordering::Acquire) != read_pos.wrapping_add(1) {
+            return None;
+        }
+        Some(self.take(read_pos))
+    }
+
+    fn take(&self, read_pos: usize) -> T {
+        let slot = &self.slots[read_pos & (self.size - 1)];
+        let item = unsafe { slot.take() };
+        slot.stamp.store(read_pos.wrapping_add(self.size), Ordering::Release);
+        self.read_pos.store(read_pos.wrapping_add(1), Ordering::Relaxed);
+        self.push_waiter.notify();
+        item
+    }
+
+    fn is_abandoned(&self) -> bool {
+        self.abandoned.load(Ordering::Acquire)
+    }
+
+    /// Number of claimed slots, including ones whose write is still in flight.
+    pub fn len(&self) -> usize {
+        let read_pos = self.read_pos.load(Ordering::Acquire);
+        let write_pos = self.write_pos.load(Ordering::Acquire);
+        write_pos.wrapping_sub(read_pos).min(self.size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Number of items which can be pushed before the buffer is full.
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Number of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Splits the buffer into a cloneable producer and a single consumer.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let buffer = Arc::new(self);
+        (Producer { buffer: buffer.clone() }, Consumer { buffer })
+    }
+}
+
+impl<T> Drop for MpscRingBuffer<T> {
+    fn drop(&mut self) {
+        let read_pos = self.read_pos.load(Ordering::Relaxed);
+        let write_pos = self.write_pos.load(Ordering::Relaxed);
+        slot::drop_published(&mut self.slots, read_pos, write_pos);
+    }
+}
+
+impl<T> Producer<T> {
+    /// Blocks until the item is pushed, failing once the consumer is gone.
+    #[cfg(feature = "std")]
+    pub fn push(&mut self, mut item: T) -> Result<(), PushError<T>> {
+        loop {
+            match self.try_push(item) {
+                Err(PushError::Full(e)) => item = e,
+                result => return result,
+            }
+            let buffer = &*self.buffer;
+            buffer.push_waiter.wait(|| buffer.slots_free() != 0 || buffer.is_abandoned());
+        }
+    }
+
+    pub fn try_push(&mut self, item: T) -> Result<(), PushError<T>> {
+        if self.is_abandoned() {
+            return Err(PushError::Disconnected(item));
+        }
+        self.buffer.try_push(item)
+    }
+
+    /// Whether the consumer has been dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.buffer.is_abandoned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+}
+
+impl<T> Clone for Producer<T> {
+    fn clone(&self) -> Producer<T> {
+        self.buffer.producers.fetch_add(1, Ordering::Relaxed);
+        Producer { buffer: self.buffer.clone() }
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        if self.buffer.producers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.buffer.abandoned.store(true, Ordering::Release);
+            self.buffer.pop_waiter.notify();
+        }
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Blocks until an item is available, failing once every producer is gone and the buffer
+    /// has been drained.
+    #[cfg(feature = "std")]
+    pub fn pop(&mut self) -> Result<T, Disconnected> {
+        let buffer = &*self.buffer;
+        let read_pos = buffer.read_pos.load(Ordering::Relaxed);
+        let slot = &buffer.slots[read_pos & (buffer.size - 1)];
+        buffer.pop_waiter.wait(|| {
+            slot.stamp.load(Ordering::Acquire) == read_pos.wrapping_add(1) || buffer.is_abandoned()
+        });
+        buffer.try_pop().ok_or(Disconnected)
+    }
+
+    pub fn try_pop(&mut self) -> Option<T> {
+        self.buffer.try_pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Whether every producer has been dropped, there may still be items left to pop.
+    pub fn is_abandoned(&self) -> bool {
+        self.buffer.is_abandoned()
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.buffer.abandoned.store(true, Ordering::Release);
+        self.buffer.push_waiter.notify();
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use {Disconnected, MpscRingBuffer, PushError};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn try_push_pop() {
+        let rb = MpscRingBuffer::new(2);
+        assert_eq!(Ok(()), rb.try_push(1));
+        assert_eq!(Ok(()), rb.try_push(2));
+        assert_eq!(Err(PushError::Full(3)), rb.try_push(3));
+        assert_eq!(Some(1), rb.try_pop());
+        assert_eq!(Some(2), rb.try_pop());
+        assert_eq!(None, rb.try_pop());
+    }
+
+    #[test]
+    fn wraps() {
+        let rb = MpscRingBuffer::new(3);
+        for i in 0..10 {
+            rb.push(i);
+            assert_eq!(rb.len(), 1);
+            assert_eq!(rb.pop(), i);
+        }
+    }
+
+    #[test]
+    fn drops_buffered_items() {
+        let item = Arc::new(());
+        let rb = MpscRingBuffer::new(1);
+        rb.push(item.clone());
+        drop(rb.pop());
+        rb.push(item.clone());
+        assert_eq!(Arc::strong_count(&item), 2);
+        drop(rb);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn many_producers() {
+        let (p, mut c) = MpscRingBuffer::new(4).split();
+        let threads: Vec<_> = (0..4).map(|t| {
+            let mut p = p.clone();
+            thread::spawn(move || {
+                for i in 0..250 {
+                    p.push(t * 250 + i).unwrap();
+                }
+            })
+        }).collect();
+        let mut seen: Vec<_> = (0..1000).map(|_| c.pop().unwrap()).collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        seen.sort();
+        assert_eq!(seen, (0..1000).collect::<Vec<_>>());
+        assert_eq!(c.try_pop(), None);
+    }
+
+    #[test]
+    fn dropped_producers_disconnect() {
+        let (mut p, mut c) = MpscRingBuffer::new(2).split();
+        let mut q = p.clone();
+        p.push(1).unwrap();
+        drop(p);
+        assert!(!c.is_abandoned());
+        let t = thread::spawn(move || {
+            q.push(2).unwrap();
+        });
+        assert_eq!(c.pop(), Ok(1));
+        assert_eq!(c.pop(), Ok(2));
+        t.join().unwrap();
+        assert!(c.is_abandoned());
+        assert_eq!(c.pop(), Err(Disconnected));
+    }
+
+    #[test]
+    fn dropped_consumer_disconnects() {
+        let (mut p, c) = MpscRingBuffer::new(1).split();
+        p.push(1).unwrap();
+        let t = thread::spawn(move || p.push(2));
+        drop(c);
+        assert_eq!(t.join().unwrap(), Err(PushError::Disconnected(2)));
+    }
+}
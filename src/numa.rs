@@ -0,0 +1,55 @@
+//! Binding the storage of a `RingBuffer` to a NUMA node, Linux only with the `numa` feature.
+
+use std::io;
+use std::ptr;
+
+use libc;
+
+/// Node `RingBuffer::on_node` binds the storage to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumaNode {
+    /// The node with this id, as numbered in `/sys/devices/system/node`.
+    Id(usize),
+    /// The node of the CPU the constructing thread runs on, for a buffer built on the thread
+    /// pinned next to the other side.
+    Current,
+}
+
+impl NumaNode {
+    /// The id of the node, resolving `Current` now.
+    pub fn id(self) -> io::Result<usize> {
+        match self {
+            NumaNode::Id(id) => Ok(id),
+            NumaNode::Current => {
+                let mut cpu: libc::c_uint = 0;
+                let mut node: libc::c_uint = 0;
+                let ret = unsafe {
+                    libc::syscall(libc::SYS_getcpu, &mut cpu, &mut node, ptr::null_mut::<u8>())
+                };
+                if ret != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(node as usize)
+            }
+        }
+    }
+}
+
+/// Binds the not yet touched pages of `len` bytes at `addr` to `node`. Kernels without NUMA
+/// support only have the one node, so there is nothing to bind.
+pub unsafe fn bind(addr: *mut u8, len: usize, node: usize) -> io::Result<()> {
+    let bits = 8 * std::mem::size_of::<libc::c_ulong>();
+    let mut mask = vec![0 as libc::c_ulong; node / bits + 1];
+    mask[node / bits] |= 1 << (node % bits);
+    // The kernel ignores the last bit of `maxnode`.
+    let maxnode = mask.len() * bits + 1;
+    let ret = libc::syscall(libc::SYS_mbind, addr, len, libc::MPOL_BIND, mask.as_ptr(), maxnode,
+                            0);
+    if ret != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ENOSYS) || node != 0 {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
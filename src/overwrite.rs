@@ -0,0 +1,259 @@
+use core::hint;
+use alloc::boxed::Box;
+use sync::Arc;
+
+use mpmc::MpmcRingBuffer;
+
+/// What pushing into a full buffer does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for the consumer to free a slot, only spinning without `std`.
+    Block,
+    /// Discard the item being pushed.
+    DropNewest,
+    /// Evict the oldest unread item to make room.
+    DropOldest,
+}
+
+/// SPSC ring buffer which by default never blocks the producer, pushing into a full buffer
+/// overwrites the oldest unread item. Use `with_policy` to queue or drop new items instead.
+///
+/// The producer evicts the oldest item by claiming it exactly like a pop would, so the consumer
+/// never sees a torn or repeated item, it just continues with the oldest one still buffered.
+/// Discarded items are handed back to the producer, or to the hook installed with `on_drop`.
+pub struct OverwritingRingBuffer<T> {
+    buffer: MpmcRingBuffer<T>,
+    policy: OverflowPolicy,
+    on_drop: Option<Box<dyn FnMut(T) + Send>>,
+}
+
+/// Writing half of a split `OverwritingRingBuffer`.
+pub struct Producer<T> {
+    buffer: Arc<MpmcRingBuffer<T>>,
+    policy: OverflowPolicy,
+    on_drop: Option<Box<dyn FnMut(T) + Send>>,
+}
+
+/// Reading half of a split `OverwritingRingBuffer`.
+pub struct Consumer<T> {
+    buffer: Arc<MpmcRingBuffer<T>>,
+}
+
+impl<T> OverwritingRingBuffer<T> {
+    /// Creates a buffer holding at least `size` items, the capacity is rounded up to a power of
+    /// two.
+    pub fn new(size: usize) -> OverwritingRingBuffer<T> {
+        OverwritingRingBuffer::with_policy(size, OverflowPolicy::DropOldest)
+    }
+
+    /// Like `new` but handles a full buffer according to `policy`.
+    pub fn with_policy(size: usize, policy: OverflowPolicy) -> OverwritingRingBuffer<T> {
+        OverwritingRingBuffer { buffer: MpmcRingBuffer::new(size), policy, on_drop: None }
+    }
+
+    /// Hands every item the policy discards to `hook` instead of returning it from `push`, to
+    /// count or log them.
+    pub fn on_drop<F: FnMut(T) + Send + 'static>(mut self, hook: F) -> OverwritingRingBuffer<T> {
+        self.on_drop = Some(Box::new(hook));
+        self
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// Number of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Splits the buffer into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let buffer = Arc::new(self.buffer);
+        let producer = Producer {
+            buffer: buffer.clone(),
+            policy: self.policy,
+            on_drop: self.on_drop,
+        };
+        (producer, Consumer { buffer })
+    }
+}
+
+impl<T> Producer<T> {
+    /// Pushes an item, handling a full buffer according to the policy. Returns the item which
+    /// was discarded if any, the pushed one for `DropNewest` or the evicted oldest one for
+    /// `DropOldest`. Always `None` when a hook was installed with `on_drop`.
+    pub fn push(&mut self, item: T) -> Option<T> {
+        let discarded = match self.policy {
+            OverflowPolicy::Block => {
+                self.push_blocking(item);
+                None
+            }
+            OverflowPolicy::DropNewest => self.buffer.try_push(item).err().map(|e| e.into_inner()),
+            OverflowPolicy::DropOldest => self.push_evicting(item),
+        };
+        match (discarded, self.on_drop.as_mut()) {
+            (Some(item), Some(hook)) => {
+                hook(item);
+                None
+            }
+            (discarded, _) => discarded,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn push_blocking(&self, item: T) {
+        self.buffer.push(item);
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn push_blocking(&self, mut item: T) {
+        loop {
+            match self.buffer.try_push(item) {
+                Ok(()) => return,
+                Err(e) => item = e.into_inner(),
+            }
+            hint::spin_loop();
+        }
+    }
+
+    fn push_evicting(&self, mut item: T) -> Option<T> {
+        let mut evicted = None;
+        loop {
+            match self.buffer.try_push(item) {
+                Ok(()) => return evicted,
+                Err(e) => item = e.into_inner(),
+            }
+            if evicted.is_none() {
+                evicted = self.buffer.try_pop();
+            }
+            if evicted.is_none() {
+                // The consumer claimed the oldest item and is about to free its slot.
+                hint::spin_loop();
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+}
+
+impl<T> Consumer<T> {
+    #[cfg(feature = "std")]
+    pub fn pop(&mut self) -> T {
+        self.buffer.pop()
+    }
+
+    pub fn try_pop(&mut self) -> Option<T> {
+        self.buffer.try_pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::OverflowPolicy;
+    use ::OverwritingRingBuffer;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn overwrites_oldest() {
+        let (mut p, mut c) = OverwritingRingBuffer::new(2).split();
+        assert_eq!(p.push(1), None);
+        assert_eq!(p.push(2), None);
+        assert_eq!(p.push(3), Some(1));
+        assert_eq!(p.push(4), Some(2));
+        assert_eq!(c.try_pop(), Some(3));
+        assert_eq!(p.push(5), None);
+        assert_eq!(c.try_pop(), Some(4));
+        assert_eq!(c.try_pop(), Some(5));
+        assert_eq!(c.try_pop(), None);
+    }
+
+    #[test]
+    fn drop_newest() {
+        let rb = OverwritingRingBuffer::with_policy(2, OverflowPolicy::DropNewest);
+        let (mut p, mut c) = rb.split();
+        assert_eq!(p.policy(), OverflowPolicy::DropNewest);
+        assert_eq!(p.push(1), None);
+        assert_eq!(p.push(2), None);
+        assert_eq!(p.push(3), Some(3));
+        assert_eq!(c.try_pop(), Some(1));
+        assert_eq!(c.try_pop(), Some(2));
+    }
+
+    #[test]
+    fn on_drop() {
+        let (tx, rx) = mpsc::channel();
+        let rb = OverwritingRingBuffer::new(2).on_drop(move |item| tx.send(item).unwrap());
+        let (mut p, mut c) = rb.split();
+        for i in 0..5 {
+            assert_eq!(p.push(i), None);
+        }
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(c.try_pop(), Some(3));
+    }
+
+    #[test]
+    fn block() {
+        let (mut p, mut c) = OverwritingRingBuffer::with_policy(2, OverflowPolicy::Block).split();
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                assert_eq!(p.push(i), None);
+            }
+        });
+        for i in 0..100 {
+            assert_eq!(c.pop(), i);
+        }
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn threads() {
+        let (mut p, mut c) = OverwritingRingBuffer::new(4).split();
+        let t = thread::spawn(move || {
+            let mut evicted = 0;
+            for i in 0..10_000 {
+                if p.push(i).is_some() {
+                    evicted += 1;
+                }
+            }
+            evicted
+        });
+        let mut popped = Vec::new();
+        while popped.last() != Some(&9_999) {
+            if let Some(item) = c.try_pop() {
+                popped.push(item);
+            }
+        }
+        let evicted = t.join().unwrap();
+        // Every item is either popped or evicted, and what is popped stays in order.
+        assert_eq!(popped.len() + evicted, 10_000);
+        assert!(popped.windows(2).all(|w| w[0] < w[1]));
+    }
+}
@@ -0,0 +1,676 @@
+//! Byte ring buffer stored in a memory mapped file, surviving restarts.
+//!
+//! The file starts with a header holding the capacity and both positions, followed by the
+//! bytes at offset `DATA_OFFSET`. Pushes and pops only move the positions in memory, the header
+//! on disk only moves on `flush`: the producer's syncs the bytes written since the last one
+//! before recording the new write position, the consumer's records the read position. A crash
+//! thus loses whatever was written after the producer last flushed and hands whatever was read
+//! after the consumer last flushed out again, so bytes are delivered at least once.
+//!
+//! The producer never overwrites bytes before the consumer flushed past them, so consumers
+//! which rarely flush hold back the producer. Both halves flush when dropped. The file is
+//! locked while open, a second `open` fails with `WouldBlock`. Only available on unix with the
+//! `persistent` feature.
+//!
+//! # Crash consistency
+//!
+//! The header records how many bytes were ever written and consumed rather than wrapping
+//! positions, `checkpoint` returns these offsets so the buffer can serve as a small write-ahead
+//! log whose records are identified by them. Once the producer's `flush` or `checkpoint`
+//! returns, every byte before its offset survives a crash of the process or the machine, and
+//! once the consumer's does, none of the bytes before its offset is handed out again. The
+//! header only changes during a flush, after the bytes it covers were synced, so the file on
+//! disk always holds the state of some flush. A failed flush may or may not have taken effect,
+//! until the next one succeeds.
+//!
+//! The header doesn't protect the bytes themselves against corruption on disk or a rogue
+//! writer. Writing them as records with `write_record` adds a CRC-32 to each, which
+//! `read_record` checks.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU64, AtomicUsize};
+use sync::atomic::Ordering;
+use std::sync::Arc;
+
+use libc;
+
+use cache_padded::CachePadded;
+use crc;
+use index;
+
+/// Identifies a file holding a buffer.
+const MAGIC: u64 = u64::from_le_bytes(*b"ringfile");
+
+/// Bumped whenever the layout of the file changes.
+const VERSION: u32 = 2;
+
+/// Offset of the bytes in the file, the header gets a page of its own.
+pub const DATA_OFFSET: usize = 4096;
+
+/// Length and checksum in front of every record.
+const RECORD_HEADER: usize = 8;
+
+/// Header at the start of the file, holding the offsets as of the last flushes.
+#[repr(C)]
+struct Header {
+    magic: u64,
+    version: u32,
+    _reserved: u32,
+    capacity: u64,
+    // Bytes ever written and consumed, their positions are these modulo twice the capacity.
+    written: AtomicU64,
+    consumed: AtomicU64,
+}
+
+/// Lockfree SPSC byte ring buffer in a file, see the module docs.
+///
+/// The capacity is rounded up to a power of two.
+pub struct PersistentRingBuffer {
+    size: usize,
+    base: *mut u8,
+    // Keeps the file locked until unmapped.
+    _file: File,
+    write: CachePadded<AtomicUsize>,
+    read: CachePadded<AtomicUsize>,
+    // The positions the header on disk is known to hold.
+    synced_write: AtomicUsize,
+    synced_read: AtomicUsize,
+}
+
+unsafe impl Send for PersistentRingBuffer { }
+// Only the split halves can touch the bytes.
+unsafe impl Sync for PersistentRingBuffer { }
+
+/// Writing half of a split `PersistentRingBuffer`.
+pub struct Producer {
+    buffer: Arc<PersistentRingBuffer>,
+    auto_flush: Option<usize>,
+    // Failure of an automatic flush, reported by the next explicit one.
+    error: Option<io::Error>,
+}
+
+/// Reading half of a split `PersistentRingBuffer`.
+pub struct Consumer {
+    buffer: Arc<PersistentRingBuffer>,
+}
+
+impl PersistentRingBuffer {
+    /// Opens the buffer in the file at `path`, resuming from the positions last flushed, or
+    /// creates it empty if the file doesn't exist or is empty. A file of the right size which is
+    /// all zero counts as empty too, it is what a crash during the creation leaves behind.
+    ///
+    /// Fails with `InvalidData` if the file holds something else or a buffer of another
+    /// capacity, and with `WouldBlock` if it is already open.
+    pub fn open<P: AsRef<Path>>(path: P, capacity: usize) -> io::Result<PersistentRingBuffer> {
+        let size = index::capacity(capacity);
+        let path = path.as_ref();
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false)
+            .open(path)?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let len = DATA_OFFSET + size;
+        let file_len = file.metadata()?.len();
+        if file_len == 0 {
+            file.set_len(len as u64)?;
+        } else if file_len != len as u64 {
+            return Err(invalid("file holds a buffer of a different capacity"));
+        }
+        let base = unsafe { map_file(&file, len)? };
+        let buffer = PersistentRingBuffer {
+            size,
+            base,
+            _file: file,
+            write: CachePadded::new(AtomicUsize::new(0)),
+            read: CachePadded::new(AtomicUsize::new(0)),
+            synced_write: AtomicUsize::new(0),
+            synced_read: AtomicUsize::new(0),
+        };
+        // The header is only written once the file was sized, a crash in between leaves no magic.
+        if buffer.header().magic == 0 {
+            unsafe {
+                (base as *mut Header).write(Header {
+                    magic: MAGIC,
+                    version: VERSION,
+                    _reserved: 0,
+                    capacity: size as u64,
+                    written: AtomicU64::new(0),
+                    consumed: AtomicU64::new(0),
+                });
+            }
+            buffer.sync_header()?;
+            buffer._file.sync_all()?;
+            sync_parent(path)?;
+        } else {
+            buffer.resume()?;
+        }
+        Ok(buffer)
+    }
+
+    /// Picks up the positions from an existing header.
+    fn resume(&self) -> io::Result<()> {
+        let header = self.header();
+        if header.magic != MAGIC {
+            return Err(invalid("file holds no buffer"));
+        }
+        if header.version != VERSION {
+            return Err(invalid("file holds a buffer with a different layout version"));
+        }
+        if header.capacity != self.size as u64 {
+            return Err(invalid("file holds a buffer of a different capacity"));
+        }
+        let written = header.written.load(Ordering::Relaxed);
+        let consumed = header.consumed.load(Ordering::Relaxed);
+        if consumed > written || written - consumed > self.size as u64 {
+            return Err(invalid("file holds corrupt offsets"));
+        }
+        let write = written % (2 * self.size as u64);
+        let read = consumed % (2 * self.size as u64);
+        self.write.store(write as usize, Ordering::Relaxed);
+        self.synced_write.store(write as usize, Ordering::Relaxed);
+        self.read.store(read as usize, Ordering::Relaxed);
+        self.synced_read.store(read as usize, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Number of bytes the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Splits the buffer into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer, Consumer) {
+        let buffer = Arc::new(self);
+        let producer = Producer { buffer: buffer.clone(), auto_flush: None, error: None };
+        (producer, Consumer { buffer })
+    }
+
+    fn len(&self) -> usize {
+        let read_pos = self.read.load(Ordering::Acquire);
+        let write_pos = self.write.load(Ordering::Acquire);
+        index::distance(write_pos, read_pos, self.size)
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.base as *const Header) }
+    }
+
+    fn data(&self) -> *mut u8 {
+        unsafe { self.base.add(DATA_OFFSET) }
+    }
+
+    /// The `len` bytes from position `pos` on, as the run up to the end of the storage and the
+    /// one wrapping around.
+    fn runs(&self, pos: usize, len: usize) -> ((usize, usize), (usize, usize)) {
+        let start = index::index(pos, self.size);
+        let first = len.min(self.size - start);
+        ((start, first), (0, len - first))
+    }
+
+    /// Copies `data` into the storage from position `pos` on, wrapping around its end.
+    ///
+    /// # Safety
+    ///
+    /// The bytes must be free.
+    unsafe fn copy_in(&self, pos: usize, data: &[u8]) {
+        let ((start, first), (_, second)) = self.runs(pos, data.len());
+        ptr::copy_nonoverlapping(data.as_ptr(), self.data().add(start), first);
+        ptr::copy_nonoverlapping(data[first..].as_ptr(), self.data(), second);
+    }
+
+    /// Writes the `len` bytes from offset `start` of the storage back to the file.
+    fn sync(&self, start: usize, len: usize) -> io::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        // msync wants the start of a page.
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let offset = DATA_OFFSET + start;
+        let page = offset & !(page_size - 1);
+        sync_range(unsafe { self.base.add(page) }, offset + len - page)
+    }
+
+    fn sync_header(&self) -> io::Result<()> {
+        sync_range(self.base, DATA_OFFSET)
+    }
+}
+
+unsafe fn map_file(file: &File, len: usize) -> io::Result<*mut u8> {
+    let ptr = libc::mmap(ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE,
+                         libc::MAP_SHARED, file.as_raw_fd(), 0);
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ptr as *mut u8)
+}
+
+/// Makes the directory entry of a newly created file durable.
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+fn sync_range(ptr: *mut u8, len: usize) -> io::Result<()> {
+    if unsafe { libc::msync(ptr as *mut libc::c_void, len, libc::MS_SYNC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Drop for PersistentRingBuffer {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.base as *mut libc::c_void, DATA_OFFSET + self.size); }
+    }
+}
+
+impl Producer {
+    /// Copies as much of `data` as fits, returns the number of bytes written.
+    ///
+    /// Bytes the consumer read but didn't flush past yet don't count as free.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let buffer = &*self.buffer;
+        let write_pos = buffer.write.load(Ordering::Relaxed);
+        let n = self.slots_free().min(data.len());
+        unsafe { buffer.copy_in(write_pos, &data[..n]) };
+        self.publish(write_pos, n);
+        n
+    }
+
+    /// Writes `record` framed with its length and checksum for `Consumer::read_record`, if all
+    /// of it fits, returns whether it was written.
+    ///
+    /// A record is published and flushed as a whole, never partially. The frame takes 8 bytes,
+    /// so buffers smaller than that can't hold any record.
+    ///
+    /// # Panics
+    ///
+    /// If the record doesn't fit even into an empty buffer.
+    pub fn write_record(&mut self, record: &[u8]) -> bool {
+        let buffer = &*self.buffer;
+        let max = buffer.size.checked_sub(RECORD_HEADER).expect("buffer too small for records");
+        assert!(record.len() <= max, "record larger than the buffer");
+        if self.slots_free() < RECORD_HEADER + record.len() {
+            return false;
+        }
+        let write_pos = buffer.write.load(Ordering::Relaxed);
+        let mut frame = [0; RECORD_HEADER];
+        frame[..4].copy_from_slice(&(record.len() as u32).to_le_bytes());
+        frame[4..].copy_from_slice(&crc::crc32(record).to_le_bytes());
+        unsafe {
+            buffer.copy_in(write_pos, &frame);
+            buffer.copy_in(index::advance(write_pos, RECORD_HEADER, buffer.size), record);
+        }
+        self.publish(write_pos, RECORD_HEADER + record.len());
+        true
+    }
+
+    /// Moves the write position past the `n` bytes copied in at `write_pos`, flushing if
+    /// enough piled up.
+    fn publish(&mut self, write_pos: usize, n: usize) {
+        let buffer = &*self.buffer;
+        let write_pos = index::advance(write_pos, n, buffer.size);
+        buffer.write.store(write_pos, Ordering::Release);
+        if let Some(every) = self.auto_flush {
+            let unsynced = index::distance(write_pos, buffer.synced_write.load(Ordering::Relaxed),
+                                           buffer.size);
+            if unsynced >= every {
+                if let Err(err) = self.checkpoint() {
+                    self.error = Some(err);
+                }
+            }
+        }
+    }
+
+    /// Flushes from within `write` once at least `every` bytes were written since the last
+    /// flush, or never for `None`. A failure is returned by the next `flush` or `checkpoint`.
+    ///
+    /// # Panics
+    ///
+    /// If `every` is zero.
+    pub fn set_auto_flush(&mut self, every: Option<usize>) {
+        assert!(every != Some(0), "flushing every zero bytes");
+        self.auto_flush = every;
+    }
+
+    /// Syncs the bytes written since the last flush to the file, then the header, so a restart
+    /// finds them.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.checkpoint().map(|_| ())
+    }
+
+    /// Like `flush` but returns the number of bytes ever written, all of which are durable now.
+    pub fn checkpoint(&mut self) -> io::Result<u64> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let buffer = &*self.buffer;
+        let header = buffer.header();
+        let write_pos = buffer.write.load(Ordering::Relaxed);
+        let synced = buffer.synced_write.load(Ordering::Relaxed);
+        let unsynced = index::distance(write_pos, synced, buffer.size);
+        let written = header.written.load(Ordering::Relaxed) + unsynced as u64;
+        if unsynced == 0 {
+            return Ok(written);
+        }
+        let (first, second) = buffer.runs(synced, unsynced);
+        buffer.sync(first.0, first.1)?;
+        buffer.sync(second.0, second.1)?;
+        header.written.store(written, Ordering::Relaxed);
+        buffer.sync_header()?;
+        buffer.synced_write.store(write_pos, Ordering::Release);
+        Ok(written)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of bytes which can be written, bytes consumed since the consumer last flushed
+    /// don't count.
+    pub fn slots_free(&self) -> usize {
+        let buffer = &*self.buffer;
+        let write_pos = buffer.write.load(Ordering::Relaxed);
+        let synced_read = buffer.synced_read.load(Ordering::Acquire);
+        buffer.size - index::distance(write_pos, synced_read, buffer.size)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.size
+    }
+}
+
+impl Drop for Producer {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl Consumer {
+    /// Borrows the buffered bytes, the second slice is only non-empty when they wrap around
+    /// the end of the storage.
+    pub fn as_slices(&self) -> (&[u8], &[u8]) {
+        let buffer = &*self.buffer;
+        let read_pos = buffer.read.load(Ordering::Relaxed);
+        let ((start, first), (_, second)) = buffer.runs(read_pos, buffer.len());
+        unsafe {
+            (slice::from_raw_parts(buffer.data().add(start), first),
+             slice::from_raw_parts(buffer.data(), second))
+        }
+    }
+
+    /// Releases the first `n` buffered bytes. They are only freed for the producer, and gone
+    /// after a restart, once the consumer flushes.
+    ///
+    /// # Panics
+    ///
+    /// If fewer than `n` bytes are buffered.
+    pub fn consume(&mut self, n: usize) {
+        let buffer = &*self.buffer;
+        assert!(n <= buffer.len(), "consuming more bytes than are buffered");
+        let read_pos = buffer.read.load(Ordering::Relaxed);
+        buffer.read.store(index::advance(read_pos, n, buffer.size), Ordering::Release);
+    }
+
+    /// Copies as many buffered bytes into `buf` as fit and consumes them, returns the number of
+    /// bytes read.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let n = {
+            let (head, tail) = self.as_slices();
+            let first = head.len().min(buf.len());
+            let second = tail.len().min(buf.len() - first);
+            buf[..first].copy_from_slice(&head[..first]);
+            buf[first..first + second].copy_from_slice(&tail[..second]);
+            first + second
+        };
+        self.consume(n);
+        n
+    }
+
+    /// Reads the next record written by `Producer::write_record` and consumes it, `None` if no
+    /// complete record is buffered.
+    ///
+    /// Fails with `InvalidData` if the record doesn't match its checksum, it is consumed all the
+    /// same, or if its length can't be right, then nothing is consumed.
+    pub fn read_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let (crc, record) = {
+            let (head, tail) = self.as_slices();
+            let buffered = head.len() + tail.len();
+            if buffered < RECORD_HEADER {
+                return Ok(None);
+            }
+            let mut bytes = head.iter().chain(tail).cloned();
+            let mut word = [0; 4];
+            let mut next_word = || {
+                for b in &mut word {
+                    *b = bytes.next().unwrap();
+                }
+                u32::from_le_bytes(word)
+            };
+            let len = next_word() as usize;
+            let crc = next_word();
+            if len > self.capacity().saturating_sub(RECORD_HEADER) {
+                return Err(invalid("record with an impossible length"));
+            }
+            if buffered < RECORD_HEADER + len {
+                return Ok(None);
+            }
+            (crc, bytes.take(len).collect::<Vec<u8>>())
+        };
+        self.consume(RECORD_HEADER + record.len());
+        if crc::crc32(&record) != crc {
+            return Err(invalid("record failed its checksum"));
+        }
+        Ok(Some(record))
+    }
+
+    /// Syncs the read position to the file, so a restart doesn't hand out the consumed bytes
+    /// again. Bytes the producer hasn't flushed yet stay unread on disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.checkpoint().map(|_| ())
+    }
+
+    /// Like `flush` but returns the number of bytes ever consumed as recorded on disk now.
+    pub fn checkpoint(&mut self) -> io::Result<u64> {
+        let buffer = &*self.buffer;
+        let header = buffer.header();
+        let mut read_pos = buffer.read.load(Ordering::Relaxed);
+        let synced = buffer.synced_read.load(Ordering::Relaxed);
+        let synced_write = buffer.synced_write.load(Ordering::Acquire);
+        // Recording a read position past the write position on disk would resume into garbage.
+        if index::distance(read_pos, synced, buffer.size)
+            > index::distance(synced_write, synced, buffer.size)
+        {
+            read_pos = synced_write;
+        }
+        let unsynced = index::distance(read_pos, synced, buffer.size);
+        let consumed = header.consumed.load(Ordering::Relaxed) + unsynced as u64;
+        if unsynced == 0 {
+            return Ok(consumed);
+        }
+        header.consumed.store(consumed, Ordering::Relaxed);
+        buffer.sync_header()?;
+        buffer.synced_read.store(read_pos, Ordering::Release);
+        Ok(consumed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.size
+    }
+}
+
+impl Drop for Consumer {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Consumer, PersistentRingBuffer, Producer};
+    use libc;
+    use std::env;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("ring-buffer-{}-{}", name, process::id()))
+    }
+
+    /// Abandons both halves like a crashing process would, without flushing.
+    fn crash(p: Producer, c: Consumer) {
+        unsafe { libc::flock(p.buffer._file.as_raw_fd(), libc::LOCK_UN) };
+        mem::forget((p, c));
+    }
+
+    #[test]
+    fn resumes() {
+        let path = path("resumes");
+        let (mut p, mut c) = PersistentRingBuffer::open(&path, 8).unwrap().split();
+        assert_eq!(p.write(b"hello world"), 8);
+        p.flush().unwrap();
+        let mut buf = [0; 6];
+        assert_eq!(c.read(&mut buf), 6);
+        // Not freed before the consumer flushed.
+        assert_eq!(p.write(b"abc"), 0);
+        c.flush().unwrap();
+        assert_eq!(p.write(b"abc"), 3);
+        assert_eq!(c.as_slices(), (&b"wo"[..], &b"abc"[..]));
+        drop((p, c));
+
+        let (mut p, mut c) = PersistentRingBuffer::open(&path, 8).unwrap().split();
+        assert_eq!(c.len(), 5);
+        assert_eq!(p.write(b"defg"), 3);
+        let mut buf = [0; 8];
+        assert_eq!(c.read(&mut buf), 8);
+        assert_eq!(&buf, b"woabcdef");
+        drop((p, c));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn checkpoints() {
+        let path = path("checkpoints");
+        let (mut p, mut c) = PersistentRingBuffer::open(&path, 8).unwrap().split();
+        p.set_auto_flush(Some(4));
+        assert_eq!(p.write(b"abc"), 3);
+        assert_eq!(p.checkpoint().unwrap(), 3);
+        assert_eq!(p.write(b"de"), 2);
+        assert_eq!(p.write(b"fg"), 2);
+        assert_eq!(p.write(b"h"), 1);
+        let mut buf = [0; 8];
+        assert_eq!(c.read(&mut buf), 8);
+        // The consumer can't record more than the producer made durable.
+        assert_eq!(c.checkpoint().unwrap(), 7);
+        crash(p, c);
+
+        // Auto flushed up to "g", "h" is lost.
+        let (mut p, mut c) = PersistentRingBuffer::open(&path, 8).unwrap().split();
+        assert!(c.is_empty());
+        assert_eq!(p.checkpoint().unwrap(), 7);
+        assert_eq!(p.write(b"ijk"), 3);
+        assert_eq!(p.checkpoint().unwrap(), 10);
+        c.consume(1);
+        crash(p, c);
+
+        // Consumed without a checkpoint, so handed out again.
+        let (p, c) = PersistentRingBuffer::open(&path, 8).unwrap().split();
+        assert_eq!(c.as_slices(), (&b"i"[..], &b"jk"[..]));
+        drop((p, c));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn records() {
+        let path = path("records");
+        let (mut p, mut c) = PersistentRingBuffer::open(&path, 32).unwrap().split();
+        assert!(p.write_record(b"hello"));
+        assert!(p.write_record(b"world!"));
+        assert!(!p.write_record(&[0; 16]));
+        assert_eq!(c.read_record().unwrap(), Some(b"hello".to_vec()));
+        p.flush().unwrap();
+        c.flush().unwrap();
+        // Wraps around the end of the storage.
+        assert!(p.write_record(b"again"));
+        unsafe { *c.buffer.data().add(8 + 5 + 8) ^= 1 };
+        let err = c.read_record().unwrap_err();
+        assert_eq!(err.to_string(), "record failed its checksum");
+        assert_eq!(c.read_record().unwrap(), Some(b"again".to_vec()));
+        assert_eq!(c.read_record().unwrap(), None);
+
+        p.flush().unwrap();
+        c.flush().unwrap();
+        assert_eq!(p.write(&[0xff; 9]), 9);
+        assert_eq!(c.read_record().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(c.len(), 9);
+        drop((p, c));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "too small for records")]
+    fn records_need_room_for_the_frame() {
+        let path = path("tiny-records");
+        let (mut p, _c) = PersistentRingBuffer::open(&path, 4).unwrap().split();
+        fs::remove_file(&path).unwrap();
+        p.write_record(b"");
+    }
+
+    #[test]
+    fn open_fails() {
+        let path = path("open-fails");
+        let rb = PersistentRingBuffer::open(&path, 16).unwrap();
+        let err = PersistentRingBuffer::open(&path, 16).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        drop(rb);
+        let err = PersistentRingBuffer::open(&path, 32).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        fs::write(&path, vec![1; super::DATA_OFFSET + 16]).unwrap();
+        let err = PersistentRingBuffer::open(&path, 16).err().unwrap();
+        assert_eq!(err.to_string(), "file holds no buffer");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn creation_interrupted() {
+        let path = path("creation-interrupted");
+        // Sized but the header never made it to disk.
+        fs::write(&path, vec![0; super::DATA_OFFSET + 16]).unwrap();
+        let (mut p, c) = PersistentRingBuffer::open(&path, 16).unwrap().split();
+        assert!(c.is_empty());
+        assert_eq!(p.write(b"abc"), 3);
+        p.flush().unwrap();
+        drop((p, c));
+        let (_, c) = PersistentRingBuffer::open(&path, 16).unwrap().split();
+        assert_eq!(c.len(), 3);
+        drop(c);
+        fs::remove_file(&path).unwrap();
+    }
+}
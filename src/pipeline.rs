@@ -0,0 +1,454 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use sync::atomic::Ordering;
+use std::sync::Arc;
+
+use cache_padded::CachePadded;
+use error::{Disconnected, PushError};
+use index;
+use waiter::Waiter;
+
+/// Fixed size ring buffer whose items pass through a chain of processing stages in place, in
+/// the style of the LMAX disruptor.
+///
+/// Every stage keeps its own cursor which acts as the barrier of the next one: a stage only
+/// sees items the stage before it has finished with, the first stage the items the producer
+/// published. The producer only reuses a slot once the last stage is done with it. Stages get
+/// the items by mutable reference, so each can leave its results in the item for the next.
+///
+/// Dropping the producer or a stage disconnects the stages after it once they have processed
+/// everything it handed on. Dropping a stage also disconnects the producer, as the slots behind
+/// it would never be freed.
+pub struct PipelineRingBuffer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Writing half of a split `PipelineRingBuffer`.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// One processing stage of a split `PipelineRingBuffer`.
+pub struct Stage<T> {
+    shared: Arc<Shared<T>>,
+    stage: usize,
+}
+
+struct Shared<T> {
+    size: usize,
+    // Unlike the other buffers items stay in their slot after the last stage is done with
+    // them, `Claim::get_mut` hands them out again to be reused in place. A claim can also be
+    // dropped half written, so whether a slot holds an item isn't implied by the cursors.
+    items: Box<[UnsafeCell<Option<T>>]>,
+    // The producer's position followed by the one of every stage.
+    cursors: Vec<CachePadded<AtomicUsize>>,
+    // `waiters[i]` is woken whenever `cursors[i]` moves.
+    waiters: Vec<Waiter>,
+    // `dropped[i]` is set once the handle moving `cursors[i]` is gone.
+    dropped: Vec<AtomicBool>,
+}
+
+// Every item is only ever accessed by the one handle currently owning its slot.
+unsafe impl<T: Send> Send for Shared<T> { }
+unsafe impl<T: Send> Sync for Shared<T> { }
+
+impl<T> Shared<T> {
+    fn stages(&self) -> usize {
+        self.cursors.len() - 1
+    }
+
+    /// Number of items published but not yet finished by the last stage.
+    fn len(&self) -> usize {
+        let done = self.cursors[self.stages()].load(Ordering::Acquire);
+        self.cursors[0].load(Ordering::Acquire).wrapping_sub(done)
+    }
+
+    /// Whether no item past `pos` can ever reach `stage`, because a handle before it is gone
+    /// and everything that handle passed on has arrived.
+    fn drained(&self, stage: usize, pos: usize) -> bool {
+        (0..=stage).any(|i| {
+            let dropped = self.dropped[i].load(Ordering::Acquire);
+            dropped && self.cursors[i].load(Ordering::Acquire) == pos
+        })
+    }
+
+    /// Whether a stage is gone, so the slots behind it are never freed.
+    fn broken(&self) -> bool {
+        self.dropped[1..].iter().any(|dropped| dropped.load(Ordering::Acquire))
+    }
+
+    fn abandon(&self, cursor: usize) {
+        self.dropped[cursor].store(true, Ordering::Release);
+        for waiter in &self.waiters {
+            waiter.notify();
+        }
+    }
+}
+
+impl<T> PipelineRingBuffer<T> {
+    /// Creates a buffer holding at least `size` items which pass through `stages` stages, the
+    /// capacity is rounded up to a power of two.
+    pub fn new(size: usize, stages: usize) -> PipelineRingBuffer<T> {
+        assert!(stages > 0, "a pipeline needs at least one stage");
+        let size = index::capacity(size);
+        PipelineRingBuffer {
+            shared: Arc::new(Shared {
+                size,
+                items: (0..size).map(|_| UnsafeCell::new(None)).collect(),
+                cursors: (0..=stages).map(|_| CachePadded::new(AtomicUsize::new(0))).collect(),
+                waiters: (0..=stages).map(|_| Waiter::new()).collect(),
+                dropped: (0..=stages).map(|_| AtomicBool::new(false)).collect(),
+            }),
+        }
+    }
+
+    /// Number of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.shared.size
+    }
+
+    /// Splits the buffer into a producer and its stages in pipeline order, each of which can be
+    /// used from a separate thread.
+    pub fn split(self) -> (Producer<T>, Vec<Stage<T>>) {
+        let stages = (0..self.shared.stages())
+            .map(|stage| Stage { shared: self.shared.clone(), stage })
+            .collect();
+        (Producer { shared: self.shared }, stages)
+    }
+}
+
+impl<T> Producer<T> {
+    /// Pushes an item, waiting for the last stage to free a slot if the buffer is full. Fails
+    /// once a stage is gone.
+    pub fn push(&mut self, item: T) -> Result<(), PushError<T>> {
+        let shared = &*self.shared;
+        let gate = &shared.cursors[shared.stages()];
+        let write_pos = shared.cursors[0].load(Ordering::Relaxed);
+        shared.waiters[shared.stages()].wait(|| {
+            write_pos.wrapping_sub(gate.load(Ordering::Acquire)) != shared.size || shared.broken()
+        });
+        if shared.broken() {
+            return Err(PushError::Disconnected(item));
+        }
+        self.put(write_pos, item);
+        Ok(())
+    }
+
+    pub fn try_push(&mut self, item: T) -> Result<(), PushError<T>> {
+        if self.shared.broken() {
+            return Err(PushError::Disconnected(item));
+        }
+        let write_pos = self.shared.cursors[0].load(Ordering::Relaxed);
+        if self.shared.len() == self.shared.size {
+            return Err(PushError::Full(item));
+        }
+        self.put(write_pos, item);
+        Ok(())
+    }
+
+    /// Claims the next `n` sequences, waiting for the last stage to free as many slots. They are
+    /// only handed to the first stage once the claim is published. Fails once a stage is gone.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is larger than the buffer.
+    pub fn claim(&mut self, n: usize) -> Result<Claim<'_, T>, Disconnected> {
+        assert!(n <= self.shared.size, "claiming more slots than the buffer holds");
+        let shared = &*self.shared;
+        let gate = &shared.cursors[shared.stages()];
+        let start = shared.cursors[0].load(Ordering::Relaxed);
+        shared.waiters[shared.stages()].wait(|| {
+            shared.size - start.wrapping_sub(gate.load(Ordering::Acquire)) >= n || shared.broken()
+        });
+        if shared.broken() {
+            return Err(Disconnected);
+        }
+        Ok(Claim { producer: self, start, len: n })
+    }
+
+    /// Like `claim` but returns `None` if fewer than `n` slots are free.
+    pub fn try_claim(&mut self, n: usize) -> Option<Claim<'_, T>> {
+        let start = self.shared.cursors[0].load(Ordering::Relaxed);
+        if self.shared.size - self.shared.len() < n {
+            return None;
+        }
+        Some(Claim { producer: self, start, len: n })
+    }
+
+    /// Sequence the next pushed or claimed item gets. Sequences count up from 0, wrapping
+    /// around on overflow.
+    pub fn sequence(&self) -> usize {
+        self.shared.cursors[0].load(Ordering::Relaxed)
+    }
+
+    fn put(&mut self, write_pos: usize, item: T) {
+        let shared = &*self.shared;
+        unsafe { *shared.items[write_pos & (shared.size - 1)].get() = Some(item) };
+        shared.cursors[0].store(write_pos.wrapping_add(1), Ordering::Release);
+        shared.waiters[0].notify();
+    }
+
+    /// Number of items which haven't passed the last stage yet.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.size
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.shared.abandon(0);
+    }
+}
+
+impl<T> Stage<T> {
+    /// Runs `f` on every item the previous stage has finished with, waiting for one if there
+    /// are none. Returns how many items were processed, failing once the producer or a stage
+    /// before this one is gone and every item it handed on has been processed.
+    pub fn process<F: FnMut(&mut T)>(&mut self, f: F) -> Result<usize, Disconnected> {
+        let shared = &*self.shared;
+        let barrier = &shared.cursors[self.stage];
+        let read_pos = shared.cursors[self.stage + 1].load(Ordering::Relaxed);
+        shared.waiters[self.stage].wait(|| {
+            barrier.load(Ordering::Acquire) != read_pos || shared.drained(self.stage, read_pos)
+        });
+        // The wait only ends without new items once nothing more can arrive.
+        match self.try_process(f) {
+            0 => Err(Disconnected),
+            n => Ok(n),
+        }
+    }
+
+    /// Runs `f` on every item the previous stage has finished with and hands them on to the
+    /// next stage with one store. Returns how many items were processed.
+    pub fn try_process<F: FnMut(&mut T)>(&mut self, mut f: F) -> usize {
+        let shared = &*self.shared;
+        let cursor = &shared.cursors[self.stage + 1];
+        let start = cursor.load(Ordering::Relaxed);
+        let end = shared.cursors[self.stage].load(Ordering::Acquire);
+        let mut pos = start;
+        while pos != end {
+            let item = unsafe { &mut *shared.items[pos & (shared.size - 1)].get() };
+            let item = item.as_mut().expect("published slot without an item");
+            f(item);
+            pos = pos.wrapping_add(1);
+        }
+        if pos != start {
+            cursor.store(pos, Ordering::Release);
+            shared.waiters[self.stage + 1].notify();
+        }
+        pos.wrapping_sub(start)
+    }
+
+    /// Sequence of the next item this stage processes.
+    pub fn sequence(&self) -> usize {
+        self.shared.cursors[self.stage + 1].load(Ordering::Relaxed)
+    }
+
+    /// Position of this stage in the pipeline, starting at 0.
+    pub fn index(&self) -> usize {
+        self.stage
+    }
+
+    /// Number of items this stage can process right now.
+    pub fn len(&self) -> usize {
+        let shared = &*self.shared;
+        let end = shared.cursors[self.stage].load(Ordering::Acquire);
+        end.wrapping_sub(shared.cursors[self.stage + 1].load(Ordering::Relaxed))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Stage<T> {
+    fn drop(&mut self) {
+        self.shared.abandon(self.stage + 1);
+    }
+}
+
+/// Consecutive sequences claimed with `Producer::claim`. Their slots can be filled in any order
+/// and are handed to the first stage together by `publish`, dropping the claim without
+/// publishing gives the sequences back.
+pub struct Claim<'a, T: 'a> {
+    producer: &'a mut Producer<T>,
+    start: usize,
+    len: usize,
+}
+
+impl<'a, T> Claim<'a, T> {
+    /// First claimed sequence.
+    pub fn first(&self) -> usize {
+        self.start
+    }
+
+    /// Number of claimed sequences.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Stores `item` in the slot of `sequence`, dropping whatever it held.
+    ///
+    /// # Panics
+    ///
+    /// If `sequence` isn't part of the claim.
+    pub fn write(&mut self, sequence: usize, item: T) {
+        *self.slot(sequence) = Some(item);
+    }
+
+    /// Borrows the item the slot of `sequence` still holds from the last time around the
+    /// buffer, to reuse it in place instead of writing a new one. `None` if the slot was never
+    /// written.
+    ///
+    /// # Panics
+    ///
+    /// If `sequence` isn't part of the claim.
+    pub fn get_mut(&mut self, sequence: usize) -> Option<&mut T> {
+        self.slot(sequence).as_mut()
+    }
+
+    fn slot(&mut self, sequence: usize) -> &mut Option<T> {
+        let offset = sequence.wrapping_sub(self.start);
+        assert!(offset < self.len, "sequence {} isn't claimed", sequence);
+        let shared = &*self.producer.shared;
+        unsafe { &mut *shared.items[sequence & (shared.size - 1)].get() }
+    }
+
+    /// Hands all claimed sequences to the first stage with one store.
+    ///
+    /// # Panics
+    ///
+    /// If a claimed slot was never written.
+    pub fn publish(mut self) {
+        for offset in 0..self.len {
+            let sequence = self.start.wrapping_add(offset);
+            assert!(self.slot(sequence).is_some(), "sequence {} was never written", sequence);
+        }
+        let shared = &*self.producer.shared;
+        shared.cursors[0].store(self.start.wrapping_add(self.len), Ordering::Release);
+        shared.waiters[0].notify();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Disconnected, PipelineRingBuffer, PushError};
+    use std::thread;
+
+    #[test]
+    fn stages_are_gated() {
+        let (mut p, mut stages) = PipelineRingBuffer::new(2, 2).split();
+        let (mut a, mut b) = (stages.remove(0), stages.remove(0));
+        assert_eq!(b.index(), 1);
+        p.try_push(1).unwrap();
+        p.try_push(2).unwrap();
+        assert_eq!(p.try_push(3), Err(PushError::Full(3)));
+        // The second stage sees nothing before the first one is done.
+        assert_eq!(b.try_process(|_| panic!("ran ahead of the first stage")), 0);
+        assert_eq!(a.try_process(|i| *i *= 10), 2);
+        assert_eq!(p.try_push(3), Err(PushError::Full(3)));
+        let mut seen = Vec::new();
+        assert_eq!(b.try_process(|i| seen.push(*i)), 2);
+        assert_eq!(seen, vec![10, 20]);
+        assert_eq!(p.try_push(3), Ok(()));
+        assert_eq!((a.len(), b.len()), (1, 0));
+    }
+
+    #[test]
+    fn claim() {
+        let (mut p, mut stages) = PipelineRingBuffer::new(4, 1).split();
+        let mut stage = stages.remove(0);
+        p.try_push(0).unwrap();
+        {
+            let mut claim = p.claim(3).unwrap();
+            assert_eq!((claim.first(), claim.len()), (1, 3));
+            // Filled out of order, nothing is visible before publishing.
+            claim.write(3, 30);
+            claim.write(1, 10);
+            claim.write(2, 20);
+            assert_eq!(stage.len(), 1);
+            claim.publish();
+        }
+        assert!(p.try_claim(1).is_none());
+        let mut seen = Vec::new();
+        assert_eq!(stage.try_process(|i| seen.push(*i)), 4);
+        assert_eq!(seen, vec![0, 10, 20, 30]);
+        assert_eq!((p.sequence(), stage.sequence()), (4, 4));
+        // Slots still hold the items from the last lap.
+        let mut claim = p.try_claim(2).unwrap();
+        *claim.get_mut(4).unwrap() += 1;
+        claim.write(5, 50);
+        claim.publish();
+        seen.clear();
+        stage.try_process(|i| seen.push(*i));
+        assert_eq!(seen, vec![1, 50]);
+    }
+
+    #[test]
+    #[should_panic(expected = "never written")]
+    fn publish_unwritten() {
+        let (mut p, _stages) = PipelineRingBuffer::<u32>::new(4, 1).split();
+        let mut claim = p.claim(2).unwrap();
+        claim.write(0, 1);
+        claim.publish();
+    }
+
+    #[test]
+    fn dropped_producer_drains_stages() {
+        let (mut p, mut stages) = PipelineRingBuffer::new(4, 2).split();
+        let (mut a, mut b) = (stages.remove(0), stages.remove(0));
+        p.push(1).unwrap();
+        p.push(2).unwrap();
+        drop(p);
+        let t = thread::spawn(move || (b.process(|_| ()), b.process(|_| ())));
+        assert_eq!(a.process(|_| ()), Ok(2));
+        assert_eq!(a.process(|_| ()), Err(Disconnected));
+        assert_eq!(t.join().unwrap(), (Ok(2), Err(Disconnected)));
+    }
+
+    #[test]
+    fn dropped_stage_disconnects_producer() {
+        let (mut p, mut stages) = PipelineRingBuffer::new(1, 2).split();
+        p.push(1).unwrap();
+        let t = thread::spawn(move || p.push(2));
+        drop(stages.remove(1));
+        assert_eq!(t.join().unwrap(), Err(PushError::Disconnected(2)));
+    }
+
+    #[test]
+    fn threads() {
+        let (mut p, stages) = PipelineRingBuffer::new(8, 3).split();
+        let threads: Vec<_> = stages.into_iter().map(|mut stage| {
+            thread::spawn(move || {
+                let index = stage.index() as u32;
+                let mut processed = 0;
+                while processed < 1000 {
+                    processed += stage.process(|item: &mut (u32, u32)| {
+                        // Every earlier stage has already bumped the item.
+                        assert_eq!(item.1, item.0 + index);
+                        item.1 += 1;
+                    }).unwrap();
+                }
+            })
+        }).collect();
+        for i in 0..1000 {
+            p.push((i, i)).unwrap();
+        }
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert!(p.is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+//! Python classes wrapping producers, for feeding a Rust consumer from Python code.
+//!
+//! Split a buffer in Rust, keep the consumer and hand the producer to Python wrapped in a
+//! `BytesProducer` (for a `Producer<u8>`) or an `ObjectProducer` (for a `Producer<Py<PyAny>>`),
+//! e.g. `Py::new(py, BytesProducer::from(producer))` passed to a script's function. Its blocking
+//! `write` and `push` release the GIL while the buffer is full, so a Python thread producing
+//! faster than the consumer keeps up is held back without stalling the other Python threads.
+//! They wake up every `POLL` to check for signals, a `KeyboardInterrupt` aborts them, and raise
+//! `BrokenPipeError` once the consumer is gone or the producer closed.
+//!
+//! The classes make up the `ring_buffer` module, registered with
+//! `pyo3::append_to_inittab!(ring_buffer)` when embedding Python or added to an extension
+//! module with `wrap_pymodule!`. Python threads may share a producer, but the bytes of writes
+//! from several threads at once may interleave. Needs the `python` feature.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use pyo3::exceptions::PyBrokenPipeError;
+use pyo3::prelude::*;
+
+use {Producer, PushError, PushTimeoutError};
+
+/// How long a blocked `write` or `push` waits with the GIL released between signal checks.
+pub const POLL: Duration = Duration::from_millis(100);
+
+/// A `Producer<u8>` usable from Python.
+#[pyclass(frozen, module = "ring_buffer")]
+pub struct BytesProducer {
+    producer: Mutex<Producer<u8>>,
+}
+
+impl From<Producer<u8>> for BytesProducer {
+    fn from(producer: Producer<u8>) -> BytesProducer {
+        BytesProducer { producer: Mutex::new(producer) }
+    }
+}
+
+#[pymethods]
+impl BytesProducer {
+    /// Writes all of `data`, blocking while the buffer is full.
+    fn write(&self, py: Python<'_>, data: &[u8]) -> PyResult<()> {
+        let mut rest = data;
+        while !rest.is_empty() {
+            rest = match py.detach(|| self.write_some(rest)) {
+                Some(rest) => rest,
+                None => return Err(disconnected()),
+            };
+            py.check_signals()?;
+        }
+        Ok(())
+    }
+
+    /// Writes as much of `data` as fits without blocking, returns the number of bytes written.
+    fn try_write(&self, py: Python<'_>, data: &[u8]) -> PyResult<usize> {
+        // Another thread's blocking `write` may hold the lock for up to `POLL`.
+        let (n, abandoned) = py.detach(|| {
+            let mut producer = self.producer.lock().unwrap();
+            (producer.try_write(data), producer.is_abandoned())
+        });
+        match n {
+            0 if !data.is_empty() && abandoned => Err(disconnected()),
+            n => Ok(n),
+        }
+    }
+
+    /// Ends the stream, the consumer sees the producer gone once it read the bytes written.
+    fn close(&self, py: Python<'_>) {
+        py.detach(|| self.producer.lock().unwrap().close());
+    }
+}
+
+impl BytesProducer {
+    /// Writes a chunk of `data` or waits up to `POLL` for space, returns what is left to write
+    /// or `None` if the consumer is gone.
+    fn write_some<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        let mut producer = self.producer.lock().unwrap();
+        match producer.try_write(data) {
+            0 => match producer.push_timeout(data[0], POLL) {
+                Ok(()) => Some(&data[1..]),
+                Err(PushTimeoutError::Timeout(_)) => Some(data),
+                Err(PushTimeoutError::Disconnected(_)) => None,
+            },
+            n => Some(&data[n..]),
+        }
+    }
+}
+
+/// A `Producer<Py<PyAny>>` usable from Python.
+#[pyclass(frozen, module = "ring_buffer")]
+pub struct ObjectProducer {
+    producer: Mutex<Producer<Py<PyAny>>>,
+}
+
+impl From<Producer<Py<PyAny>>> for ObjectProducer {
+    fn from(producer: Producer<Py<PyAny>>) -> ObjectProducer {
+        ObjectProducer { producer: Mutex::new(producer) }
+    }
+}
+
+#[pymethods]
+impl ObjectProducer {
+    /// Pushes `item`, blocking while the buffer is full.
+    fn push(&self, py: Python<'_>, item: Py<PyAny>) -> PyResult<()> {
+        let mut item = item;
+        loop {
+            match py.detach(|| self.producer.lock().unwrap().push_timeout(item, POLL)) {
+                Ok(()) => return Ok(()),
+                Err(PushTimeoutError::Timeout(back)) => item = back,
+                Err(PushTimeoutError::Disconnected(_)) => return Err(disconnected()),
+            }
+            py.check_signals()?;
+        }
+    }
+
+    /// Pushes `item` unless the buffer is full, returns whether it was pushed.
+    fn try_push(&self, py: Python<'_>, item: Py<PyAny>) -> PyResult<bool> {
+        match py.detach(|| self.producer.lock().unwrap().try_push(item)) {
+            Ok(()) => Ok(true),
+            Err(PushError::Full(_)) => Ok(false),
+            Err(PushError::Disconnected(_)) => Err(disconnected()),
+        }
+    }
+
+    /// Ends the stream, the consumer sees the producer gone once it popped the items pushed.
+    fn close(&self, py: Python<'_>) {
+        py.detach(|| self.producer.lock().unwrap().close());
+    }
+}
+
+fn disconnected() -> PyErr {
+    PyBrokenPipeError::new_err("ring buffer consumer is gone or the buffer was closed")
+}
+
+/// The module holding `BytesProducer` and `ObjectProducer`.
+#[pymodule]
+pub fn ring_buffer(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<BytesProducer>()?;
+    module.add_class::<ObjectProducer>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BytesProducer, ObjectProducer};
+    use pyo3::exceptions::PyBrokenPipeError;
+    use pyo3::ffi::c_str;
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+    use std::thread;
+    use RingBuffer;
+
+    #[test]
+    fn feeds_consumer() {
+        Python::initialize();
+        let (producer, mut consumer) = RingBuffer::new(4).split();
+        let reader = thread::spawn(move || consumer.read(64));
+        Python::attach(|py| {
+            let locals = PyDict::new(py);
+            locals.set_item("p", Py::new(py, BytesProducer::from(producer)).unwrap()).unwrap();
+            let script = c_str!("for i in range(8): p.write(b'abcdefgh')\np.close()");
+            py.run(script, None, Some(&locals)).unwrap();
+        });
+        assert_eq!(reader.join().unwrap(), b"abcdefgh".repeat(8));
+
+        let (producer, consumer) = RingBuffer::<Py<PyAny>>::new(2).split();
+        drop(consumer);
+        Python::attach(|py| {
+            let producer = Py::new(py, ObjectProducer::from(producer)).unwrap();
+            let err = producer.call_method1(py, "push", (1,)).unwrap_err();
+            assert!(err.is_instance_of::<PyBrokenPipeError>(py));
+        });
+    }
+
+    #[test]
+    fn close_blocked_writer() {
+        Python::initialize();
+        let (producer, consumer) = RingBuffer::new(4).split();
+        let producer = Python::attach(|py| Py::new(py, BytesProducer::from(producer)).unwrap());
+        let writer = {
+            let producer = Python::attach(|py| producer.clone_ref(py));
+            thread::spawn(move || Python::attach(|py| {
+                let err = producer.call_method1(py, "write", (&b"abcdefgh"[..],)).unwrap_err();
+                err.is_instance_of::<PyBrokenPipeError>(py)
+            }))
+        };
+        Python::attach(|py| {
+            while consumer.len() < 4 {
+                py.detach(thread::yield_now);
+            }
+            // The blocked writer holds the lock, it is waited for without the GIL.
+            let n = producer.call_method1(py, "try_write", (&b"x"[..],)).unwrap();
+            assert_eq!(n.extract::<usize>(py).unwrap(), 0);
+            producer.call_method0(py, "close").unwrap();
+        });
+        assert!(writer.join().unwrap());
+    }
+}
@@ -0,0 +1,181 @@
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+
+use libc;
+
+use Consumer;
+
+/// Descriptor which turns readable when it is signalled, an eventfd on Linux and a pipe
+/// elsewhere.
+pub struct Readiness {
+    read_fd: RawFd,
+    write_fd: RawFd,
+}
+
+impl Readiness {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn new() -> io::Result<Readiness> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Readiness { read_fd: fd, write_fd: fd })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn new() -> io::Result<Readiness> {
+        let mut fds = [0; 2];
+        unsafe {
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let readiness = Readiness { read_fd: fds[0], write_fd: fds[1] };
+            for &fd in &fds {
+                if libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK) != 0
+                   || libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(readiness)
+        }
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.read_fd
+    }
+
+    /// Makes the descriptor readable. A full pipe or counter is already readable, so failures
+    /// are ignored.
+    pub fn signal(&self) {
+        let one = 1u64.to_ne_bytes();
+        // An eventfd takes a whole counter, a pipe a single byte.
+        let len = if self.read_fd == self.write_fd { one.len() } else { 1 };
+        unsafe { libc::write(self.write_fd, one.as_ptr() as *const libc::c_void, len); }
+    }
+
+    /// Drains the descriptor so it is no longer readable.
+    fn clear(&self) {
+        let mut buf = [0u8; 64];
+        loop {
+            let n = unsafe {
+                libc::read(self.read_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+            };
+            // An eventfd is drained by one read, on a pipe a short read means it is empty.
+            if n <= 0 || self.read_fd == self.write_fd || (n as usize) < buf.len() {
+                break;
+            }
+        }
+    }
+}
+
+impl Drop for Readiness {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read_fd);
+            if self.write_fd != self.read_fd {
+                libc::close(self.write_fd);
+            }
+        }
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Returns a descriptor which turns readable once items arrive in the empty buffer or the
+    /// producer is dropped, to multiplex the consumer in an epoll or kqueue loop. It is created
+    /// on the first call and closed with the buffer.
+    ///
+    /// The descriptor stays readable until `clear_readiness` is called, do that before draining
+    /// the buffer with `try_pop` so items arriving afterwards signal it again.
+    pub fn readiness_fd(&self) -> io::Result<RawFd> {
+        let buffer = &*self.buffer;
+        if let Some(readiness) = buffer.readiness.get() {
+            return Ok(readiness.fd());
+        }
+        let readiness = Arc::new(Readiness::new()?);
+        let fd = readiness.fd();
+        buffer.readiness.get_or_init(|| readiness.clone());
+        buffer.pop_waiter.register_readiness(&readiness);
+        if !buffer.is_empty() || self.is_abandoned() {
+            readiness.signal();
+        }
+        Ok(fd)
+    }
+
+    /// Resets the descriptor from `readiness_fd` so it only turns readable again once more
+    /// items arrive.
+    pub fn clear_readiness(&self) {
+        if let Some(readiness) = self.buffer.readiness.get() {
+            readiness.clear();
+            self.buffer.pop_waiter.register_readiness(readiness);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::RingBuffer;
+    use libc;
+    use std::thread;
+
+    fn readable(fd: i32) -> bool {
+        let mut pollfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+        unsafe { libc::poll(&mut pollfd, 1, 0) == 1 }
+    }
+
+    fn wait_readable(fd: i32) {
+        let mut pollfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+        assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 5000) }, 1);
+    }
+
+    #[test]
+    fn signals_once_items_arrive() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let fd = c.readiness_fd().unwrap();
+        assert_eq!(c.readiness_fd().unwrap(), fd);
+        assert!(!readable(fd));
+        p.try_push(1).unwrap();
+        assert!(readable(fd));
+        // Still readable while the buffer isn't drained.
+        p.try_push(2).unwrap();
+        c.clear_readiness();
+        assert!(!readable(fd));
+        assert_eq!(c.try_read(4), vec![1, 2]);
+        p.try_push(3).unwrap();
+        assert!(readable(fd));
+    }
+
+    #[test]
+    fn already_buffered_and_disconnect() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        p.try_push(1).unwrap();
+        let fd = c.readiness_fd().unwrap();
+        assert!(readable(fd));
+        c.clear_readiness();
+        assert_eq!(c.try_pop(), Some(1));
+        drop(p);
+        assert!(readable(fd));
+        assert!(c.is_abandoned());
+    }
+
+    #[test]
+    fn threads() {
+        let (mut p, mut c) = RingBuffer::new(8).split();
+        let fd = c.readiness_fd().unwrap();
+        let t = thread::spawn(move || {
+            for i in 0..1000 {
+                p.push(i).unwrap();
+            }
+        });
+        let mut next = 0;
+        while next < 1000 {
+            wait_readable(fd);
+            c.clear_readiness();
+            while let Some(item) = c.try_pop() {
+                assert_eq!(item, next);
+                next += 1;
+            }
+        }
+        t.join().unwrap();
+    }
+}
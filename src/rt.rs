@@ -0,0 +1,175 @@
+//! Checking that the hot path of the SPSC `RingBuffer` stays real time safe.
+//!
+//! Every push and pop ends in publishing the new write position or releasing slots, which
+//! wakes the other side if it is blocked. With the `rt-safe` feature and debug assertions those
+//! steps run inside a `Section`, and `CheckedAlloc` aborts on any allocation made inside one.
+//! Without either the sections compile to nothing.
+
+#[cfg(feature = "rt-safe")]
+use std::alloc::{GlobalAlloc, Layout, System};
+#[cfg(all(feature = "rt-safe", debug_assertions))]
+use std::cell::Cell;
+#[cfg(all(feature = "rt-safe", debug_assertions))]
+use std::io::{self, Write};
+#[cfg(all(feature = "rt-safe", debug_assertions))]
+use std::process;
+
+#[cfg(all(feature = "rt-safe", debug_assertions))]
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Marks the buffer's own work on the hot path for as long as it lives.
+pub struct Section {
+    _private: (),
+}
+
+impl Section {
+    #[inline]
+    pub fn enter() -> Section {
+        #[cfg(all(feature = "rt-safe", debug_assertions))]
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        Section { _private: () }
+    }
+}
+
+/// Runs `f` outside of any section, for code the buffer calls out to such as wakers.
+#[cfg(feature = "std")]
+#[inline]
+pub fn outside<R, F: FnOnce() -> R>(f: F) -> R {
+    #[cfg(all(feature = "rt-safe", debug_assertions))]
+    {
+        let depth = DEPTH.with(|depth| depth.replace(0));
+        let _restore = Restore(depth);
+        f()
+    }
+    #[cfg(not(all(feature = "rt-safe", debug_assertions)))]
+    f()
+}
+
+/// Restores the depth `outside` saved, even if its closure panics.
+#[cfg(all(feature = "rt-safe", debug_assertions))]
+struct Restore(usize);
+
+#[cfg(all(feature = "rt-safe", debug_assertions))]
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let depth = self.0;
+        DEPTH.with(|d| d.set(depth));
+    }
+}
+
+#[cfg(all(feature = "rt-safe", debug_assertions))]
+impl Drop for Section {
+    #[inline]
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Global allocator which aborts the process when a buffer allocates or frees memory on its
+/// hot path, install it in debug builds of a real time application or its tests:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOC: ring_buffer::CheckedAlloc = ring_buffer::CheckedAlloc(std::alloc::System);
+/// ```
+///
+/// The pushes and pops of a `RingBuffer` which don't block, `try_push`, `try_write`,
+/// `try_push_batch`, `try_pop`, `read_into` and both kinds of chunks among them, never allocate,
+/// take a lock which can block or panic. They only make syscalls for waking the other side if
+/// it is parked in a blocking operation, have it wait with a `WaitStrategy` which doesn't park
+/// to rule that out. Async tasks waiting on the other side are woken from the hot path as
+/// well, with whatever their waker does. Cloning and dropping items, the iterators and closures
+/// passed in and the `metrics` and `tracing` features aren't covered.
+///
+/// Only checks with debug assertions, otherwise it is just `A`.
+#[cfg(feature = "rt-safe")]
+pub struct CheckedAlloc<A = System>(pub A);
+
+#[cfg(feature = "rt-safe")]
+impl<A> CheckedAlloc<A> {
+    #[inline]
+    fn check(&self) {
+        #[cfg(debug_assertions)]
+        {
+            // Threads past their thread locals' destruction can't be in a section.
+            if DEPTH.try_with(|depth| depth.get()).unwrap_or(0) != 0 {
+                let _ = io::stderr().write_all(b"ring buffer allocated on its hot path\n");
+                process::abort();
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rt-safe")]
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CheckedAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.check();
+        self.0.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.check();
+        self.0.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.check();
+        self.0.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.check();
+        self.0.realloc(ptr, layout, new_size)
+    }
+}
+
+/// With the feature the whole test binary runs on the checked allocator, so every test pushing
+/// or popping checks the hot path too.
+#[cfg(all(test, feature = "rt-safe"))]
+mod tests {
+    use super::CheckedAlloc;
+    use std::alloc::System;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Arc;
+    use std::task::{Context, Wake, Waker};
+    use std::thread;
+    use RingBuffer;
+
+    #[global_allocator]
+    static ALLOC: CheckedAlloc = CheckedAlloc(System);
+
+    #[test]
+    fn wakes_without_allocating() {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        let t = thread::spawn(move || (0..1000).map(|_| c.pop().unwrap()).sum::<usize>());
+        for i in 0..1000 {
+            while p.try_push(i).is_err() {
+                thread::yield_now();
+            }
+        }
+        assert_eq!(t.join().unwrap(), 999 * 1000 / 2);
+    }
+
+    struct Panics;
+
+    impl Wake for Panics {
+        fn wake(self: Arc<Self>) {
+            panic!("waker panicked");
+        }
+    }
+
+    #[test]
+    fn survives_panicking_waker() {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        let waker = Waker::from(Arc::new(Panics));
+        assert!(c.poll_pop_ready(&mut Context::from_waker(&waker)).is_pending());
+        // Woken under the waiter's lock, which the panic poisons.
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| p.try_push(1))).is_err());
+        assert_eq!(c.try_pop(), Some(1));
+        assert!(c.poll_pop_ready(&mut Context::from_waker(Waker::noop())).is_pending());
+        p.try_push(2).unwrap();
+        assert_eq!(c.pop(), Ok(2));
+    }
+}
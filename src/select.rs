@@ -0,0 +1,189 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use waiter::Waiter;
+use {Consumer, Producer, RingBuffer};
+
+use sync::Arc;
+use sync::atomic::Ordering;
+
+/// Waits on several buffers at once, for a thread serving more than one of them.
+///
+/// Consumers are added with `recv` and producers with `send`, each getting the index `ready`
+/// returns once that operation can go ahead: a consumer has an item, a producer a free slot,
+/// or the other half is gone. Since only the thread holding a half can undo that, the
+/// operation is sure to succeed right after. When several are ready the first one added wins,
+/// so a half whose other side is gone, which stays ready for good, is best left out of the next
+/// `Select`.
+///
+/// ```
+/// use ring_buffer::{RingBuffer, Select};
+///
+/// let (mut p1, mut c1) = RingBuffer::new(2).split();
+/// let (_p2, mut c2) = RingBuffer::<u32>::new(2).split();
+/// p1.try_push(1).unwrap();
+///
+/// let mut select = Select::new();
+/// let first = select.recv(&c1);
+/// select.recv(&c2);
+/// assert_eq!(select.ready(), first);
+/// assert_eq!(c1.try_pop(), Some(1));
+/// ```
+pub struct Select<'a> {
+    sources: Vec<Box<dyn Source + 'a>>,
+}
+
+/// One registered operation.
+trait Source {
+    fn ready(&self) -> bool;
+    fn waiter(&self) -> &Waiter;
+}
+
+struct Readable<T>(Arc<RingBuffer<T>>);
+
+struct Writable<T>(Arc<RingBuffer<T>>);
+
+impl<T> Source for Readable<T> {
+    fn ready(&self) -> bool {
+        !self.0.is_empty() || self.0.abandoned.load(Ordering::Acquire)
+    }
+
+    fn waiter(&self) -> &Waiter {
+        &self.0.pop_waiter
+    }
+}
+
+impl<T> Source for Writable<T> {
+    fn ready(&self) -> bool {
+        self.0.slots_free() != 0 || self.0.abandoned.load(Ordering::Acquire)
+    }
+
+    fn waiter(&self) -> &Waiter {
+        &self.0.push_waiter
+    }
+}
+
+impl<'a> Select<'a> {
+    pub fn new() -> Select<'a> {
+        Select { sources: Vec::new() }
+    }
+
+    /// Adds waiting for `consumer` to have an item, returns the index of the operation.
+    pub fn recv<T: 'a>(&mut self, consumer: &Consumer<T>) -> usize {
+        self.sources.push(Box::new(Readable(consumer.buffer.clone())));
+        self.sources.len() - 1
+    }
+
+    /// Adds waiting for `producer` to have a free slot, returns the index of the operation.
+    pub fn send<T: 'a>(&mut self, producer: &Producer<T>) -> usize {
+        self.sources.push(Box::new(Writable(producer.buffer.clone())));
+        self.sources.len() - 1
+    }
+
+    /// Index of an operation which can go ahead right now, if there is one.
+    pub fn try_ready(&self) -> Option<usize> {
+        self.sources.iter().position(|source| source.ready())
+    }
+
+    /// Blocks until one of the operations can go ahead and returns its index.
+    ///
+    /// # Panics
+    ///
+    /// If no operation was added.
+    pub fn ready(&self) -> usize {
+        self.ready_until(None).unwrap()
+    }
+
+    /// Like `ready` but gives up once `timeout` has elapsed.
+    pub fn ready_timeout(&self, timeout: Duration) -> Option<usize> {
+        self.ready_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Like `ready` but gives up once `deadline` has passed.
+    pub fn ready_deadline(&self, deadline: Instant) -> Option<usize> {
+        self.ready_until(Some(deadline))
+    }
+
+    fn ready_until(&self, deadline: Option<Instant>) -> Option<usize> {
+        assert!(!self.sources.is_empty(), "selecting without any operation");
+        if let Some(i) = self.try_ready() {
+            return Some(i);
+        }
+        let current = thread::current();
+        let ready = loop {
+            // Registered everywhere before checking, so a push or pop on any buffer after the
+            // check unparks us.
+            for source in &self.sources {
+                source.waiter().register_thread(&current);
+            }
+            if let Some(i) = self.try_ready() {
+                break Some(i);
+            }
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break None;
+                    }
+                    thread::park_timeout(deadline - now);
+                }
+                None => thread::park(),
+            }
+        };
+        for source in &self.sources {
+            source.waiter().unregister_thread(&current);
+        }
+        ready
+    }
+}
+
+impl<'a> Default for Select<'a> {
+    fn default() -> Select<'a> {
+        Select::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use {RingBuffer, Select};
+
+    #[test]
+    fn ready() {
+        let (mut p1, mut c1) = RingBuffer::new(2).split();
+        let (mut p2, mut c2) = RingBuffer::new(2).split();
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                if i % 2 == 0 { p1.push(i).unwrap() } else { p2.push(i).unwrap() }
+            }
+            (p1, p2)
+        });
+        let mut select = Select::new();
+        select.recv(&c1);
+        select.recv(&c2);
+        let mut received = Vec::new();
+        while received.len() < 100 {
+            let c = if select.ready() == 0 { &mut c1 } else { &mut c2 };
+            received.push(c.try_pop().unwrap());
+        }
+        received.sort();
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+        drop(t.join().unwrap());
+    }
+
+    #[test]
+    fn send_and_timeout() {
+        let (mut p, c) = RingBuffer::new(1).split();
+        p.try_push(1).unwrap();
+        let (_p2, c2) = RingBuffer::<u8>::new(1).split();
+        let mut select = Select::new();
+        select.send(&p);
+        select.recv(&c2);
+        assert_eq!(select.try_ready(), None);
+        assert_eq!(select.ready_timeout(Duration::from_millis(10)), None);
+        // The consumer going away leaves nothing to wait for either.
+        drop(c);
+        assert_eq!(select.ready(), 0);
+    }
+}
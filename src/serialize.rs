@@ -0,0 +1,108 @@
+use core::fmt;
+use core::marker::PhantomData;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use {Consumer, RingBuffer};
+
+const FIELDS: &[&str] = &["capacity", "items"];
+
+/// The capacity and the buffered items in pop order, which `RingBuffer` deserializes from.
+/// Nothing is popped, items pushed while serializing are left out.
+impl<T: Serialize> Serialize for Consumer<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("RingBuffer", 2)?;
+        s.serialize_field("capacity", &self.capacity())?;
+        s.serialize_field("items", &Items(self.as_slices()))?;
+        s.end()
+    }
+}
+
+struct Items<'a, T: 'a>((&'a [T], &'a [T]));
+
+impl<'a, T: Serialize> Serialize for Items<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (head, tail) = self.0;
+        serializer.collect_seq(head.iter().chain(tail))
+    }
+}
+
+/// Rebuilds a buffer serialized from a `Consumer`, with the same capacity and items.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for RingBuffer<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RingBuffer<T>, D::Error> {
+        deserializer.deserialize_struct("RingBuffer", FIELDS, SnapshotVisitor(PhantomData))
+    }
+}
+
+struct SnapshotVisitor<T>(PhantomData<T>);
+
+impl<T> SnapshotVisitor<T> {
+    fn rebuild<E: de::Error>(capacity: usize, items: Vec<T>) -> Result<RingBuffer<T>, E> {
+        let buffer = RingBuffer::try_new(capacity).map_err(E::custom)?;
+        if items.len() > buffer.capacity() {
+            return Err(E::invalid_length(items.len(), &"at most `capacity` items"));
+        }
+        for item in items {
+            buffer.try_push(item).expect("checked the buffer has room");
+        }
+        Ok(buffer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for SnapshotVisitor<T> {
+    type Value = RingBuffer<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a ring buffer snapshot")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<RingBuffer<T>, A::Error> {
+        let capacity = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let items = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        SnapshotVisitor::rebuild(capacity, items)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RingBuffer<T>, A::Error> {
+        let mut capacity = None;
+        let mut items = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match &*key {
+                "capacity" if capacity.is_none() => capacity = Some(map.next_value()?),
+                "items" if items.is_none() => items = Some(map.next_value()?),
+                "capacity" => return Err(de::Error::duplicate_field("capacity")),
+                "items" => return Err(de::Error::duplicate_field("items")),
+                _ => return Err(de::Error::unknown_field(&key, FIELDS)),
+            }
+        }
+        let capacity = capacity.ok_or_else(|| de::Error::missing_field("capacity"))?;
+        let items = items.ok_or_else(|| de::Error::missing_field("items"))?;
+        SnapshotVisitor::rebuild(capacity, items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use ::RingBuffer;
+
+    #[test]
+    fn round_trip() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        for i in 0..4 {
+            p.try_push(i).unwrap();
+        }
+        c.try_pop().unwrap();
+        p.try_push(4).unwrap();
+        let json = serde_json::to_string(&c).unwrap();
+        assert_eq!(json, r#"{"capacity":4,"items":[1,2,3,4]}"#);
+        assert_eq!(c.len(), 4);
+        let (_, mut c) = serde_json::from_str::<RingBuffer<u32>>(&json).unwrap().split();
+        assert_eq!(c.capacity(), 4);
+        assert_eq!(c.try_read(8), vec![1, 2, 3, 4]);
+        assert!(serde_json::from_str::<RingBuffer<u32>>(r#"{"capacity":2,"items":[1,2,3]}"#)
+            .is_err());
+    }
+}
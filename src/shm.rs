@@ -0,0 +1,1154 @@
+//! SPSC ring buffer living in memory shared between processes.
+//!
+//! The region starts with a `#[repr(C)]` control block holding a versioned header and both
+//! positions, each on its own cache line, followed by the slots. The header records the
+//! layout version, pointer width, element size and alignment and the capacity, attaching
+//! checks all of them so mismatched builds fail instead of corrupting each other's memory.
+//!
+//! Either supply the memory yourself with `init` and `attach`, or on unix let `anonymous` map
+//! a region which is inherited by forked children, or use `create_named` and `open_named` to
+//! go through a named object any process can open, a POSIX shared memory object on unix and
+//! a file mapping on Windows.
+//!
+//! Blocking `push` and `pop` sleep on a futex next to the position they wait for on Linux and
+//! Android, which wakes sleepers in any process. On Windows they sleep on the same word with
+//! `WaitOnAddress`, which is only woken from within the process, so sleepers check again every
+//! millisecond in case the other side lives in another one. Other platforms yield between
+//! checks instead.
+//!
+//! # Liveness
+//!
+//! Each side stamps a heartbeat into the header on every `BEAT_EVERY`th push or pop, on any
+//! which wakes the waiting other side and on `heartbeat`, and `peer_alive` tells whether the
+//! other side did so recently. A process which crashed stops beating, so `pop_while_alive` and
+//! `push_while_alive` give up waiting on it instead of blocking forever on an orphaned region.
+//! A side which is idle, or blocked in the plain `push` or `pop`, should call `heartbeat`
+//! regularly to not be taken for dead, as should one which rarely moves while the other side
+//! polls without waiting. The stamps come from the monotonic clock, which all processes on a
+//! machine share.
+//!
+//! # Recovery
+//!
+//! An item only becomes visible with the store of the write position, after it and its
+//! checksum are complete, so a process dying in the middle of a push or pop leaves the region
+//! as it was before the operation and there is nothing to roll back. `attach` checks the
+//! positions along with the rest of the header.
+//!
+//! A process takes one side for itself with `claim_producer` or `claim_consumer`, which record
+//! its pid in the header until the half is dropped. Claiming fails while another live process
+//! holds the side, but succeeds once that process is gone or hasn't beat within the given
+//! time, so a restarted peer simply attaches and claims its side again and carries on from
+//! the positions its predecessor left. `split` claims nothing.
+//!
+//! # Checksums
+//!
+//! After `enable_checksums` the producer stores a CRC-32 of every item next to the slots and
+//! `try_pop_checked` verifies it, so an item torn or scribbled over by a buggy peer is reported
+//! instead of returned. The other pops don't check.
+//!
+//! # Safety
+//!
+//! The buffer can't check what the other process does with the memory, so:
+//!
+//! - only one process may push and only one may pop,
+//! - both must use the same `T` built with the same layout,
+//! - `T` must not hold pointers or handles, they mean nothing in the other process.
+
+#[cfg(unix)]
+use std::ffi::CString;
+#[cfg(windows)]
+use std::ffi::c_void;
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::process;
+#[cfg(any(unix, windows))]
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize};
+use sync::atomic::Ordering;
+use std::sync::Arc;
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(not(any(unix, windows)))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(unix)]
+use libc;
+#[cfg(windows)]
+use windows_sys::Win32::Foundation as win;
+#[cfg(windows)]
+use windows_sys::Win32::System::Memory as memory;
+#[cfg(windows)]
+use windows_sys::Win32::System::SystemInformation as sysinfo;
+#[cfg(windows)]
+use windows_sys::Win32::System::Threading as threading;
+
+use cache_padded::CachePadded;
+use crc;
+use error::PushError;
+use index;
+
+/// Identifies a region holding a buffer.
+const MAGIC: u64 = u64::from_le_bytes(*b"ringbufr");
+
+/// Bumped whenever the layout of the region changes.
+const VERSION: u32 = 5;
+
+/// Pushes or pops between heartbeats stamped while the other side isn't waiting, reading the
+/// clock and storing the stamp on every one of them would cost more than the move itself.
+pub const BEAT_EVERY: u32 = 64;
+
+/// Control block at the start of the shared region.
+#[repr(C)]
+struct Header {
+    // Stored last, once the rest of the header is valid.
+    magic: AtomicU64,
+    version: u32,
+    word_size: u32,
+    // Set once the producer stores checksums of the items.
+    checksums: AtomicU32,
+    _reserved: u32,
+    elem_size: u64,
+    elem_align: u64,
+    capacity: u64,
+    write: CachePadded<Side>,
+    read: CachePadded<Side>,
+}
+
+/// One position, with the word the other side sleeps on while it waits for the position to
+/// move.
+#[repr(C)]
+struct Side {
+    pos: AtomicUsize,
+    // Bumped after every move of `pos`.
+    seq: AtomicU32,
+    // Set while the other side may be sleeping on `seq`.
+    waiting: AtomicU32,
+    // Milliseconds on the monotonic clock when this side last showed signs of life, zero if
+    // it never did.
+    heartbeat: AtomicU64,
+    // Pid of the process which claimed this side, zero if none did.
+    owner: AtomicU32,
+}
+
+impl Side {
+    fn new() -> Side {
+        Side {
+            pos: AtomicUsize::new(0),
+            seq: AtomicU32::new(0),
+            waiting: AtomicU32::new(0),
+            heartbeat: AtomicU64::new(0),
+            owner: AtomicU32::new(0),
+        }
+    }
+
+    fn beat(&self) {
+        self.heartbeat.store(now_millis(), Ordering::Relaxed);
+    }
+
+    /// Whether this side beat within the last `timeout`.
+    fn alive(&self, timeout: Duration) -> bool {
+        let beat = self.heartbeat.load(Ordering::Relaxed);
+        beat != 0 && now_millis().saturating_sub(beat) <= timeout.as_millis() as u64
+    }
+
+    /// Records this process as the owner of the side, taking it over from an owner which is
+    /// gone or didn't beat within `stale`.
+    fn claim(&self, stale: Duration) -> io::Result<()> {
+        let pid = process::id();
+        let mut owner = self.owner.load(Ordering::Acquire);
+        loop {
+            if owner != 0 && process_alive(owner) && self.alive(stale) {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock,
+                                          "shared buffer side is held by another process"));
+            }
+            match self.owner.compare_exchange(owner, pid, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => break,
+                Err(current) => owner = current,
+            }
+        }
+        // Counts as alive from now on, not only after the first push or pop.
+        self.beat();
+        Ok(())
+    }
+
+    fn release(&self) {
+        let _ = self.owner.compare_exchange(process::id(), 0, Ordering::AcqRel, Ordering::Relaxed);
+    }
+
+    /// Wakes the other side after `pos` moved.
+    fn moved(&self) {
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        if self.waiting.load(Ordering::SeqCst) != 0 {
+            // Once woken it may well check whether this side is alive.
+            self.beat();
+            futex_wake(&self.seq);
+        } else if seq.is_multiple_of(BEAT_EVERY) {
+            self.beat();
+        }
+    }
+
+    /// Sleeps until `pos` may have moved while `blocked` holds, returns false if the deadline
+    /// passed first.
+    fn wait<F: Fn() -> bool>(&self, deadline: Option<Instant>, blocked: F) -> bool {
+        self.waiting.store(1, Ordering::SeqCst);
+        let seq = self.seq.load(Ordering::SeqCst);
+        let mut in_time = true;
+        if blocked() {
+            let now = Instant::now();
+            let timeout = deadline.map(|deadline| deadline.saturating_duration_since(now));
+            if timeout == Some(Duration::from_secs(0)) {
+                in_time = false;
+            } else {
+                futex_wait(&self.seq, seq, timeout);
+            }
+        }
+        self.waiting.store(0, Ordering::Relaxed);
+        in_time
+    }
+}
+
+/// Whether the process `pid` still exists. Without a way to tell it is assumed to.
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let signalled = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    signalled == 0 || io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_: u32) -> bool {
+    true
+}
+
+/// Milliseconds on a clock shared by all processes, never zero.
+#[cfg(unix)]
+fn now_millis() -> u64 {
+    let mut now: libc::timespec = unsafe { mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    (now.tv_sec as u64 * 1000 + now.tv_nsec as u64 / 1_000_000).max(1)
+}
+
+#[cfg(windows)]
+fn now_millis() -> u64 {
+    unsafe { sysinfo::GetTickCount64() }.max(1)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn now_millis() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (now.as_millis() as u64).max(1)
+}
+
+/// Sleeps while `word` holds `expected`, the kernel keys shared futexes by physical page so
+/// this works across processes.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+    let timeout = timeout.map(|timeout| libc::timespec {
+        tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    });
+    let timeout = timeout.as_ref().map_or(ptr::null(), |timeout| timeout as *const _);
+    // Spurious wakeups, signals and timeouts all just send the caller back to checking.
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAIT, expected, timeout);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn futex_wake(word: &AtomicU32) {
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, libc::c_int::MAX);
+    }
+}
+
+/// Longest a sleeper on Windows goes without checking again, a wake from another process
+/// doesn't reach it.
+#[cfg(windows)]
+const WAKE_POLL: Duration = Duration::from_millis(1);
+
+/// Sleeps while `word` holds `expected`, like the futex but only woken from this process.
+#[cfg(windows)]
+fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+    let timeout = timeout.map_or(WAKE_POLL, |timeout| timeout.min(WAKE_POLL));
+    // Rounded up, a zero timeout would just spin.
+    let millis = timeout.as_micros().div_ceil(1000) as u32;
+    let expected = &expected as *const u32 as *const c_void;
+    unsafe { threading::WaitOnAddress(word.as_ptr() as *const c_void, expected, 4, millis) };
+}
+
+#[cfg(windows)]
+fn futex_wake(word: &AtomicU32) {
+    unsafe { threading::WakeByAddressAll(word.as_ptr() as *const c_void) };
+}
+
+// Without a futex the waiting side yields between checks instead.
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+fn futex_wait(word: &AtomicU32, expected: u32, _: Option<Duration>) {
+    if word.load(Ordering::SeqCst) == expected {
+        thread::yield_now();
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+fn futex_wake(_: &AtomicU32) {}
+
+/// Lockfree SPSC fixed size ring buffer in shared memory, see the module docs.
+pub struct ShmRingBuffer<T: Copy> {
+    header: *const Header,
+    slots: *mut T,
+    // Checksums of the slots, following them.
+    crcs: *mut u32,
+    size: usize,
+    // Length of the region if we mapped it ourselves.
+    mapped: Option<usize>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Copy + Send> Send for ShmRingBuffer<T> { }
+// Only the split halves push or pop.
+unsafe impl<T: Copy + Send> Sync for ShmRingBuffer<T> { }
+
+/// Writing half of a split `ShmRingBuffer`.
+pub struct Producer<T: Copy> {
+    buffer: Arc<ShmRingBuffer<T>>,
+    // Whether the side is claimed and released again on drop.
+    claimed: bool,
+}
+
+/// Reading half of a split `ShmRingBuffer`.
+pub struct Consumer<T: Copy> {
+    buffer: Arc<ShmRingBuffer<T>>,
+    claimed: bool,
+}
+
+impl<T: Copy> ShmRingBuffer<T> {
+    /// Number of bytes a region for `capacity` items needs, the capacity is rounded up to a
+    /// power of two.
+    pub fn required_size(capacity: usize) -> usize {
+        let size = index::capacity(capacity);
+        Self::crcs_offset(size) + size * mem::size_of::<u32>()
+    }
+
+    /// Alignment the region must have.
+    pub fn required_align() -> usize {
+        mem::align_of::<Header>().max(mem::align_of::<T>())
+    }
+
+    fn slots_offset() -> usize {
+        let align = mem::align_of::<T>();
+        (mem::size_of::<Header>() + align - 1) & !(align - 1)
+    }
+
+    fn crcs_offset(size: usize) -> usize {
+        let align = mem::align_of::<u32>();
+        (Self::slots_offset() + size * mem::size_of::<T>() + align - 1) & !(align - 1)
+    }
+
+    /// Sets up an empty buffer in the region at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for `required_size(capacity)` bytes, aligned to `required_align()`
+    /// and stay mapped for as long as the buffer is used. Nothing else may be using it as a
+    /// buffer yet.
+    pub unsafe fn init(ptr: *mut u8, capacity: usize) -> ShmRingBuffer<T> {
+        assert_eq!(ptr as usize % Self::required_align(), 0, "shared region is misaligned");
+        let size = index::capacity(capacity);
+        (ptr as *mut Header).write(Header {
+            magic: AtomicU64::new(0),
+            version: VERSION,
+            word_size: mem::size_of::<usize>() as u32,
+            checksums: AtomicU32::new(0),
+            _reserved: 0,
+            elem_size: mem::size_of::<T>() as u64,
+            elem_align: mem::align_of::<T>() as u64,
+            capacity: size as u64,
+            write: CachePadded::new(Side::new()),
+            read: CachePadded::new(Side::new()),
+        });
+        (*(ptr as *const Header)).magic.store(MAGIC, Ordering::Release);
+        Self::from_raw(ptr, size)
+    }
+
+    /// Opens a buffer which another process already set up with `init`, failing with
+    /// `InvalidData` if the header doesn't match this build and `T`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for at least the header, and for `required_size(capacity)` bytes if it
+    /// does hold a buffer, and stay mapped for as long as the buffer is used.
+    pub unsafe fn attach(ptr: *mut u8) -> io::Result<ShmRingBuffer<T>> {
+        if !(ptr as usize).is_multiple_of(Self::required_align()) {
+            return Err(invalid("shared region is misaligned"));
+        }
+        let header = &*(ptr as *const Header);
+        if header.magic.load(Ordering::Acquire) != MAGIC {
+            return Err(invalid("shared region holds no buffer"));
+        }
+        if header.version != VERSION {
+            return Err(invalid("shared buffer has a different layout version"));
+        }
+        if header.word_size as usize != mem::size_of::<usize>() {
+            return Err(invalid("shared buffer was created with a different pointer width"));
+        }
+        if header.elem_size != mem::size_of::<T>() as u64
+           || header.elem_align != mem::align_of::<T>() as u64 {
+            return Err(invalid("shared buffer holds a different element type"));
+        }
+        if !header.capacity.is_power_of_two() || header.capacity > index::MAX_SIZE as u64 {
+            return Err(invalid("shared buffer has an invalid capacity"));
+        }
+        let size = header.capacity as usize;
+        let read_pos = header.read.pos.load(Ordering::Acquire);
+        let write_pos = header.write.pos.load(Ordering::Acquire);
+        if read_pos >= 2 * size || write_pos >= 2 * size
+           || write_pos.wrapping_sub(read_pos) & (2 * size - 1) > size {
+            return Err(invalid("shared buffer has corrupt positions"));
+        }
+        Ok(Self::from_raw(ptr, size))
+    }
+
+    unsafe fn from_raw(ptr: *mut u8, size: usize) -> ShmRingBuffer<T> {
+        ShmRingBuffer {
+            header: ptr as *const Header,
+            slots: ptr.add(Self::slots_offset()) as *mut T,
+            crcs: ptr.add(Self::crcs_offset(size)) as *mut u32,
+            size,
+            mapped: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Maps a fresh shared region, it stays shared with child processes forked afterwards.
+    #[cfg(unix)]
+    pub fn anonymous(capacity: usize) -> io::Result<ShmRingBuffer<T>> {
+        let len = Self::required_size(capacity);
+        unsafe {
+            let ptr = map_shared(-1, len)?;
+            let mut rb = Self::init(ptr, capacity);
+            rb.mapped = Some(len);
+            Ok(rb)
+        }
+    }
+
+    /// Creates the shared memory object `name` holding an empty buffer, fails if it already
+    /// exists. `name` should look like `/some-name` on unix and `Local\\some-name` on Windows.
+    ///
+    /// On unix the object outlives the processes using it until it is removed with
+    /// `remove_named`, a Windows file mapping goes away with the last process mapping it.
+    #[cfg(any(unix, windows))]
+    pub fn create_named(name: &str, capacity: usize) -> io::Result<ShmRingBuffer<T>> {
+        let len = Self::required_size(capacity);
+        unsafe {
+            let ptr = create_mapping(name, len)?;
+            let mut rb = Self::init(ptr, capacity);
+            rb.mapped = Some(len);
+            Ok(rb)
+        }
+    }
+
+    /// Opens the buffer another process created with `create_named`, validating the header
+    /// like `attach` and that the object is large enough for the capacity it claims.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be using the object for anything but this buffer.
+    #[cfg(any(unix, windows))]
+    pub unsafe fn open_named(name: &str) -> io::Result<ShmRingBuffer<T>> {
+        let (ptr, len) = open_mapping(name)?;
+        let attached = Self::attach(ptr).and_then(|rb| {
+            if Self::required_size(rb.size) > len {
+                Err(invalid("shared memory object is too small for its buffer"))
+            } else {
+                Ok(rb)
+            }
+        });
+        match attached {
+            Ok(mut rb) => {
+                rb.mapped = Some(len);
+                Ok(rb)
+            }
+            Err(err) => {
+                unmap(ptr, len);
+                Err(err)
+            }
+        }
+    }
+
+    /// Removes the shared memory object `name`, processes which have it open keep using it.
+    /// Windows removes file mappings by itself, so there this only checks the name.
+    #[cfg(any(unix, windows))]
+    pub fn remove_named(name: &str) -> io::Result<()> {
+        remove_mapping(name)
+    }
+
+    /// Makes the producer store a checksum of every item it pushes from now on, see the module
+    /// docs. The setting is shared with the other process.
+    ///
+    /// # Safety
+    ///
+    /// `T` must not contain padding, the checksums cover every byte of an item. Nothing may
+    /// have been pushed yet.
+    pub unsafe fn enable_checksums(&mut self) {
+        (*self.header).checksums.store(1, Ordering::Release);
+    }
+
+    /// Number of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        let header = unsafe { &*self.header };
+        let read_pos = header.read.pos.load(Ordering::Acquire);
+        let write_pos = header.write.pos.load(Ordering::Acquire);
+        index::distance(write_pos, read_pos, self.size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Number of items which can be pushed before the buffer is full.
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Splits the buffer into a producer and consumer. A process usually keeps one of them and
+    /// drops the other.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let buffer = Arc::new(self);
+        (Producer { buffer: buffer.clone(), claimed: false }, Consumer { buffer, claimed: false })
+    }
+
+    /// Claims the producer side for this process, see the module docs. Fails with
+    /// `WouldBlock` while a live process which beat within `stale` holds it.
+    pub fn claim_producer(self, stale: Duration) -> io::Result<Producer<T>> {
+        unsafe { (*self.header).write.claim(stale)? };
+        Ok(Producer { buffer: Arc::new(self), claimed: true })
+    }
+
+    /// Claims the consumer side for this process, like `claim_producer`.
+    pub fn claim_consumer(self, stale: Duration) -> io::Result<Consumer<T>> {
+        let header = unsafe { &*self.header };
+        header.read.claim(stale)?;
+        // The previous consumer may have died waiting.
+        header.write.waiting.store(0, Ordering::Relaxed);
+        Ok(Consumer { buffer: Arc::new(self), claimed: true })
+    }
+}
+
+/// Checksum of the item in `slot`.
+unsafe fn item_crc<T>(slot: *const T) -> u32 {
+    crc::crc32(slice::from_raw_parts(slot as *const u8, mem::size_of::<T>()))
+}
+
+#[cfg(unix)]
+unsafe fn map_shared(fd: libc::c_int, len: usize) -> io::Result<*mut u8> {
+    let flags = if fd < 0 { libc::MAP_SHARED | libc::MAP_ANON } else { libc::MAP_SHARED };
+    let ptr = libc::mmap(ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE, flags, fd, 0);
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ptr as *mut u8)
+}
+
+/// Creates the POSIX shared memory object `name` of `len` bytes and maps it.
+#[cfg(unix)]
+unsafe fn create_mapping(name: &str, len: usize) -> io::Result<*mut u8> {
+    let name = shm_name(name)?;
+    let fd = libc::shm_open(name.as_ptr(), libc::O_RDWR | libc::O_CREAT | libc::O_EXCL, 0o600);
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mapped = if libc::ftruncate(fd, len as libc::off_t) != 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        map_shared(fd, len)
+    };
+    libc::close(fd);
+    if mapped.is_err() {
+        libc::shm_unlink(name.as_ptr());
+    }
+    mapped
+}
+
+/// Maps all of the existing object `name`, returns where and its length.
+#[cfg(unix)]
+unsafe fn open_mapping(name: &str) -> io::Result<(*mut u8, usize)> {
+    let name = shm_name(name)?;
+    let fd = libc::shm_open(name.as_ptr(), libc::O_RDWR, 0);
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut stat: libc::stat = mem::zeroed();
+    let mapped = if libc::fstat(fd, &mut stat) != 0 {
+        Err(io::Error::last_os_error())
+    } else if (stat.st_size as usize) < mem::size_of::<Header>() {
+        Err(invalid("shared memory object is too small for a buffer"))
+    } else {
+        map_shared(fd, stat.st_size as usize).map(|ptr| (ptr, stat.st_size as usize))
+    };
+    libc::close(fd);
+    mapped
+}
+
+#[cfg(unix)]
+fn remove_mapping(name: &str) -> io::Result<()> {
+    let name = shm_name(name)?;
+    if unsafe { libc::shm_unlink(name.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+unsafe fn unmap(ptr: *mut u8, len: usize) {
+    libc::munmap(ptr as *mut libc::c_void, len);
+}
+
+#[cfg(unix)]
+fn shm_name(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|_| nul_in_name())
+}
+
+/// Creates the file mapping `name` of `len` bytes backed by the paging file and maps it.
+#[cfg(windows)]
+unsafe fn create_mapping(name: &str, len: usize) -> io::Result<*mut u8> {
+    let name = wide_name(name)?;
+    let len = len as u64;
+    let mapping = memory::CreateFileMappingW(win::INVALID_HANDLE_VALUE, ptr::null(),
+                                             memory::PAGE_READWRITE, (len >> 32) as u32,
+                                             len as u32, name.as_ptr());
+    if mapping.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    // Creating succeeds for an existing mapping too, only flagging it.
+    if win::GetLastError() == win::ERROR_ALREADY_EXISTS {
+        win::CloseHandle(mapping);
+        return Err(io::Error::from_raw_os_error(win::ERROR_ALREADY_EXISTS as i32));
+    }
+    let view = memory::MapViewOfFile(mapping, memory::FILE_MAP_ALL_ACCESS, 0, 0, 0);
+    let err = io::Error::last_os_error();
+    // The view keeps the mapping alive.
+    win::CloseHandle(mapping);
+    if view.Value.is_null() {
+        return Err(err);
+    }
+    Ok(view.Value as *mut u8)
+}
+
+/// Maps all of the existing file mapping `name`, returns where and its length.
+#[cfg(windows)]
+unsafe fn open_mapping(name: &str) -> io::Result<(*mut u8, usize)> {
+    let name = wide_name(name)?;
+    let mapping = memory::OpenFileMappingW(memory::FILE_MAP_ALL_ACCESS, 0, name.as_ptr());
+    if mapping.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let view = memory::MapViewOfFile(mapping, memory::FILE_MAP_ALL_ACCESS, 0, 0, 0);
+    let err = io::Error::last_os_error();
+    win::CloseHandle(mapping);
+    if view.Value.is_null() {
+        return Err(err);
+    }
+    let ptr = view.Value as *mut u8;
+    // The view spans the whole mapping, rounded up to pages.
+    let mut info: memory::MEMORY_BASIC_INFORMATION = mem::zeroed();
+    let queried = memory::VirtualQuery(view.Value, &mut info, mem::size_of_val(&info));
+    let mapped = if queried == 0 {
+        Err(io::Error::last_os_error())
+    } else if info.RegionSize < mem::size_of::<Header>() {
+        Err(invalid("shared memory object is too small for a buffer"))
+    } else {
+        Ok((ptr, info.RegionSize))
+    };
+    if mapped.is_err() {
+        unmap(ptr, 0);
+    }
+    mapped
+}
+
+#[cfg(windows)]
+fn remove_mapping(name: &str) -> io::Result<()> {
+    wide_name(name).map(|_| ())
+}
+
+#[cfg(windows)]
+unsafe fn unmap(ptr: *mut u8, _: usize) {
+    memory::UnmapViewOfFile(memory::MEMORY_MAPPED_VIEW_ADDRESS { Value: ptr as *mut c_void });
+}
+
+#[cfg(windows)]
+fn wide_name(name: &str) -> io::Result<Vec<u16>> {
+    if name.contains('\0') {
+        return Err(nul_in_name());
+    }
+    Ok(name.encode_utf16().chain(Some(0)).collect())
+}
+
+#[cfg(any(unix, windows))]
+fn nul_in_name() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "name contains a nul byte")
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<T: Copy> Drop for ShmRingBuffer<T> {
+    fn drop(&mut self) {
+        #[cfg(any(unix, windows))]
+        {
+            if let Some(len) = self.mapped {
+                unsafe { unmap(self.header as *mut u8, len) };
+            }
+        }
+    }
+}
+
+impl<T: Copy> Drop for Producer<T> {
+    fn drop(&mut self) {
+        if self.claimed {
+            unsafe { (*self.buffer.header).write.release() };
+        }
+    }
+}
+
+impl<T: Copy> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        if self.claimed {
+            unsafe { (*self.buffer.header).read.release() };
+        }
+    }
+}
+
+impl<T: Copy> Producer<T> {
+    /// Pushes an item unless the buffer is full, in which case it is handed back.
+    pub fn try_push(&mut self, item: T) -> Result<(), PushError<T>> {
+        let buffer = &*self.buffer;
+        let header = unsafe { &*buffer.header };
+        let write_pos = header.write.pos.load(Ordering::Relaxed);
+        let read_pos = header.read.pos.load(Ordering::Acquire);
+        if index::distance(write_pos, read_pos, buffer.size) == buffer.size {
+            return Err(PushError::Full(item));
+        }
+        let i = index::index(write_pos, buffer.size);
+        unsafe {
+            buffer.slots.add(i).write(item);
+            if header.checksums.load(Ordering::Acquire) != 0 {
+                buffer.crcs.add(i).write(item_crc(buffer.slots.add(i)));
+            }
+        }
+        header.write.pos.store(index::advance(write_pos, 1, buffer.size), Ordering::Release);
+        header.write.moved();
+        Ok(())
+    }
+
+    /// Pushes an item, waiting for a free slot if the buffer is full.
+    pub fn push(&mut self, item: T) {
+        let _ = self.push_until(item, None);
+    }
+
+    /// Pushes an item, waiting at most `timeout` for a free slot. The item is handed back as
+    /// `Full` if none became free in time.
+    pub fn push_timeout(&mut self, item: T, timeout: Duration) -> Result<(), PushError<T>> {
+        self.push_until(item, Instant::now().checked_add(timeout))
+    }
+
+    /// Pushes an item, waiting for a free slot as long as the consumer beat within the last
+    /// `timeout`. The item is handed back as `Disconnected` once it didn't.
+    pub fn push_while_alive(&mut self, mut item: T, timeout: Duration)
+        -> Result<(), PushError<T>>
+    {
+        loop {
+            match self.try_push(item) {
+                Err(PushError::Full(back)) => item = back,
+                result => return result,
+            }
+            if !self.peer_alive(timeout) {
+                return self.try_push(item).map_err(|e| PushError::Disconnected(e.into_inner()));
+            }
+            self.heartbeat();
+            let buffer = &*self.buffer;
+            let header = unsafe { &*buffer.header };
+            header.read.wait(Instant::now().checked_add(timeout), || buffer.len() == buffer.size);
+        }
+    }
+
+    /// Whether the consumer pushed, popped or called `heartbeat` within the last `timeout`.
+    pub fn peer_alive(&self, timeout: Duration) -> bool {
+        unsafe { (*self.buffer.header).read.alive(timeout) }
+    }
+
+    /// Tells the consumer this side is still alive.
+    pub fn heartbeat(&self) {
+        unsafe { (*self.buffer.header).write.beat() }
+    }
+
+    fn push_until(&mut self, mut item: T, deadline: Option<Instant>) -> Result<(), PushError<T>> {
+        loop {
+            match self.try_push(item) {
+                Err(PushError::Full(back)) => item = back,
+                result => return result,
+            }
+            let buffer = &*self.buffer;
+            let header = unsafe { &*buffer.header };
+            if !header.read.wait(deadline, || buffer.len() == buffer.size) {
+                return self.try_push(item);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.size
+    }
+}
+
+impl<T: Copy> Consumer<T> {
+    pub fn try_pop(&mut self) -> Option<T> {
+        let buffer = &*self.buffer;
+        let header = unsafe { &*buffer.header };
+        let read_pos = header.read.pos.load(Ordering::Relaxed);
+        if header.write.pos.load(Ordering::Acquire) == read_pos {
+            return None;
+        }
+        let item = unsafe { buffer.slots.add(index::index(read_pos, buffer.size)).read() };
+        header.read.pos.store(index::advance(read_pos, 1, buffer.size), Ordering::Release);
+        header.read.moved();
+        Some(item)
+    }
+
+    /// Like `try_pop` but verifies the item's checksum if checksums are enabled, failing with
+    /// `InvalidData` on a mismatch. The corrupt item is popped all the same.
+    pub fn try_pop_checked(&mut self) -> io::Result<Option<T>> {
+        let buffer = &*self.buffer;
+        let header = unsafe { &*buffer.header };
+        let read_pos = header.read.pos.load(Ordering::Relaxed);
+        if header.write.pos.load(Ordering::Acquire) == read_pos {
+            return Ok(None);
+        }
+        let i = index::index(read_pos, buffer.size);
+        let (item, intact) = unsafe {
+            let slot = buffer.slots.add(i);
+            let checked = header.checksums.load(Ordering::Acquire) != 0;
+            (slot.read(), !checked || item_crc(slot) == buffer.crcs.add(i).read())
+        };
+        header.read.pos.store(index::advance(read_pos, 1, buffer.size), Ordering::Release);
+        header.read.moved();
+        if !intact {
+            return Err(invalid("shared buffer item failed its checksum"));
+        }
+        Ok(Some(item))
+    }
+
+    /// Pops an item, waiting for one if the buffer is empty.
+    pub fn pop(&mut self) -> T {
+        self.pop_until(None).expect("waiting without a deadline can't time out")
+    }
+
+    /// Pops an item, waiting at most `timeout` for one to arrive.
+    pub fn pop_timeout(&mut self, timeout: Duration) -> Option<T> {
+        self.pop_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Pops an item, waiting for one as long as the producer beat within the last `timeout`.
+    /// Returns `None` once it didn't and nothing is left.
+    pub fn pop_while_alive(&mut self, timeout: Duration) -> Option<T> {
+        loop {
+            if let Some(item) = self.try_pop() {
+                return Some(item);
+            }
+            if !self.peer_alive(timeout) {
+                return self.try_pop();
+            }
+            self.heartbeat();
+            let buffer = &*self.buffer;
+            let header = unsafe { &*buffer.header };
+            header.write.wait(Instant::now().checked_add(timeout), || buffer.is_empty());
+        }
+    }
+
+    /// Whether the producer pushed, popped or called `heartbeat` within the last `timeout`.
+    pub fn peer_alive(&self, timeout: Duration) -> bool {
+        unsafe { (*self.buffer.header).write.alive(timeout) }
+    }
+
+    /// Tells the producer this side is still alive.
+    pub fn heartbeat(&self) {
+        unsafe { (*self.buffer.header).read.beat() }
+    }
+
+    fn pop_until(&mut self, deadline: Option<Instant>) -> Option<T> {
+        loop {
+            if let Some(item) = self.try_pop() {
+                return Some(item);
+            }
+            let buffer = &*self.buffer;
+            let header = unsafe { &*buffer.header };
+            if !header.write.wait(deadline, || buffer.is_empty()) {
+                return self.try_pop();
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {PushError, ShmRingBuffer};
+    use std::io::ErrorKind;
+    #[cfg(unix)]
+    use std::mem;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[repr(align(128))]
+    struct Region([u8; 1024]);
+
+    #[test]
+    fn init_and_attach() {
+        let mut region = Region([0; 1024]);
+        assert!(ShmRingBuffer::<u32>::required_size(4) <= region.0.len());
+        let (mut p, _) = unsafe { ShmRingBuffer::<u32>::init(region.0.as_mut_ptr(), 3) }.split();
+        let (_, mut c) = unsafe { ShmRingBuffer::<u32>::attach(region.0.as_mut_ptr()) }
+            .unwrap().split();
+        assert_eq!(c.capacity(), 4);
+        for i in 0..4 {
+            p.try_push(i).unwrap();
+        }
+        assert_eq!(p.try_push(4), Err(PushError::Full(4)));
+        assert_eq!(c.try_pop(), Some(0));
+        assert_eq!(c.len(), 3);
+    }
+
+    #[test]
+    fn attach_validates_header() {
+        let mut region = Region([0; 1024]);
+        let ptr = region.0.as_mut_ptr();
+        let err = unsafe { ShmRingBuffer::<u32>::attach(ptr) }.err().unwrap();
+        assert_eq!(err.to_string(), "shared region holds no buffer");
+        unsafe { ShmRingBuffer::<u32>::init(ptr, 4) };
+        let err = unsafe { ShmRingBuffer::<u64>::attach(ptr) }.err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "shared buffer holds a different element type");
+        // The version follows the magic number.
+        region.0[8] += 1;
+        let err = unsafe { ShmRingBuffer::<u32>::attach(region.0.as_mut_ptr()) }.err().unwrap();
+        assert_eq!(err.to_string(), "shared buffer has a different layout version");
+    }
+
+    #[test]
+    fn checksums() {
+        let mut region = Region([0; 1024]);
+        let ptr = region.0.as_mut_ptr();
+        let mut rb = unsafe { ShmRingBuffer::<u64>::init(ptr, 4) };
+        unsafe { rb.enable_checksums() };
+        let (mut p, mut c) = rb.split();
+        p.try_push(1).unwrap();
+        p.try_push(2).unwrap();
+        assert_eq!(c.try_pop_checked().unwrap(), Some(1));
+        // A peer scribbling over the buffered item.
+        let offset = ShmRingBuffer::<u64>::slots_offset() + 8;
+        unsafe { *ptr.add(offset) ^= 1 };
+        let err = c.try_pop_checked().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(c.try_pop_checked().unwrap(), None);
+    }
+
+    #[test]
+    fn liveness() {
+        let mut region = Region([0; 1024]);
+        let rb = unsafe { ShmRingBuffer::<u32>::init(region.0.as_mut_ptr(), 2) };
+        let (mut p, mut c) = rb.split();
+        let timeout = Duration::from_millis(50);
+        // Neither side beat yet.
+        assert!(!c.peer_alive(timeout));
+        assert_eq!(p.push_while_alive(1, timeout), Ok(()));
+        assert!(c.peer_alive(timeout) && !p.peer_alive(timeout));
+        assert_eq!(c.pop_while_alive(timeout), Some(1));
+        assert!(p.peer_alive(timeout));
+        // The producer stops beating, as if it crashed.
+        let start = Instant::now();
+        assert_eq!(c.pop_while_alive(timeout), None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        p.heartbeat();
+        assert!(c.peer_alive(timeout));
+        p.try_push(2).unwrap();
+        p.try_push(3).unwrap();
+        thread::sleep(timeout * 2);
+        assert_eq!(p.push_while_alive(4, timeout), Err(PushError::Disconnected(4)));
+    }
+
+    #[test]
+    fn heartbeat_throttled() {
+        let mut region = Region([0; 1024]);
+        let rb = unsafe { ShmRingBuffer::<u32>::init(region.0.as_mut_ptr(), 2) };
+        let header = unsafe { &*rb.header };
+        let (mut p, mut c) = rb.split();
+        p.try_push(1).unwrap();
+        header.write.heartbeat.store(1, Ordering::Relaxed);
+        for i in 1..super::BEAT_EVERY {
+            assert_eq!(c.try_pop(), Some(i));
+            p.try_push(i + 1).unwrap();
+        }
+        assert_eq!(header.write.heartbeat.load(Ordering::Relaxed), 1);
+        c.try_pop().unwrap();
+        p.try_push(0).unwrap();
+        assert!(header.write.heartbeat.load(Ordering::Relaxed) > 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reclaim() {
+        let mut region = Region([0; 1024]);
+        let ptr = region.0.as_mut_ptr();
+        let stale = Duration::from_secs(60);
+        let attach = || unsafe { ShmRingBuffer::<u32>::attach(ptr) }.unwrap();
+        let mut p = unsafe { ShmRingBuffer::<u32>::init(ptr, 4) }.claim_producer(stale).unwrap();
+        let mut c = attach().claim_consumer(stale).unwrap();
+        p.try_push(1).unwrap();
+        let err = attach().claim_producer(stale).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        drop(p);
+        let mut p = attach().claim_producer(stale).unwrap();
+        p.try_push(2).unwrap();
+        assert_eq!(c.try_pop(), Some(1));
+
+        // The consumer hangs, its heartbeat goes stale.
+        let header = unsafe { &*attach().header };
+        header.read.heartbeat.store(1, Ordering::Relaxed);
+        mem::forget(c);
+        let mut c = attach().claim_consumer(stale).unwrap();
+        assert_eq!(c.try_pop(), Some(2));
+
+        // The consumer's process exits, the pid goes away.
+        let mut child = ::std::process::Command::new("true").spawn().unwrap();
+        header.read.owner.store(child.id(), Ordering::Relaxed);
+        child.wait().unwrap();
+        mem::forget(c);
+        let c = attach().claim_consumer(stale).unwrap();
+        drop((p, c));
+        assert_eq!(header.write.owner.load(Ordering::Relaxed), 0);
+        assert_eq!(header.read.owner.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn attach_validates_positions() {
+        let mut region = Region([0; 1024]);
+        let ptr = region.0.as_mut_ptr();
+        let rb = unsafe { ShmRingBuffer::<u32>::init(ptr, 4) };
+        let header = unsafe { &*rb.header };
+        header.write.pos.store(5, Ordering::Relaxed);
+        let err = unsafe { ShmRingBuffer::<u32>::attach(ptr) }.err().unwrap();
+        assert_eq!(err.to_string(), "shared buffer has corrupt positions");
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn named() {
+        let name = format!("/ring-buffer-test-{}", ::std::process::id());
+        let (mut p, _) = ShmRingBuffer::<u16>::create_named(&name, 8).unwrap().split();
+        assert!(ShmRingBuffer::<u16>::create_named(&name, 8).is_err());
+        let (_, mut c) = unsafe { ShmRingBuffer::<u16>::open_named(&name) }.unwrap().split();
+        ShmRingBuffer::<u16>::remove_named(&name).unwrap();
+        assert_eq!(c.capacity(), 8);
+        p.try_push(7).unwrap();
+        assert_eq!(c.try_pop(), Some(7));
+        // Windows keeps the mapping around while it is mapped.
+        #[cfg(unix)]
+        assert!(unsafe { ShmRingBuffer::<u16>::open_named(&name) }.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn anonymous_threads() {
+        let (mut p, mut c) = ShmRingBuffer::<u64>::anonymous(16).unwrap().split();
+        let t = thread::spawn(move || {
+            for i in 0..1000 {
+                while p.try_push(i).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+        for i in 0..1000 {
+            loop {
+                if let Some(item) = c.try_pop() {
+                    assert_eq!(item, i);
+                    break;
+                }
+                thread::yield_now();
+            }
+        }
+        t.join().unwrap();
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn blocking() {
+        #[cfg(unix)]
+        let rb = ShmRingBuffer::<u32>::anonymous(2).unwrap();
+        #[cfg(windows)]
+        let rb = {
+            let name = format!("ring-buffer-blocking-{}", ::std::process::id());
+            ShmRingBuffer::<u32>::create_named(&name, 2).unwrap()
+        };
+        let (mut p, mut c) = rb.split();
+        assert_eq!(c.pop_timeout(Duration::from_millis(10)), None);
+        let t = thread::spawn(move || {
+            for i in 0..1000 {
+                p.push(i);
+            }
+            p
+        });
+        for i in 0..1000 {
+            assert_eq!(c.pop(), i);
+        }
+        let mut p = t.join().unwrap();
+        p.push(1);
+        p.push(2);
+        assert_eq!(p.push_timeout(3, Duration::from_millis(10)), Err(PushError::Full(3)));
+    }
+}
@@ -0,0 +1,44 @@
+use sync::atomic::{AtomicUsize, Ordering};
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+
+/// Storage slot whose stamp tracks which lap of the buffer it currently belongs to.
+///
+/// A slot at position `pos` is free for writing when `stamp == pos` and holds a published item
+/// when `stamp == pos + 1`, after the item is taken the stamp is advanced by the buffer size.
+pub struct Slot<T> {
+    pub stamp: AtomicUsize,
+    item: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    pub fn new(pos: usize) -> Slot<T> {
+        Slot { stamp: AtomicUsize::new(pos), item: UnsafeCell::new(MaybeUninit::uninit()) }
+    }
+
+    /// Caller must have claimed the slot for writing, any item left in it is overwritten.
+    pub unsafe fn write(&self, item: T) {
+        (*self.item.get()).write(item);
+    }
+
+    /// Caller must have claimed the slot for reading, it is uninitialized afterwards.
+    pub unsafe fn take(&self) -> T {
+        (*self.item.get()).assume_init_read()
+    }
+}
+
+/// Drops the published items between `read_pos` and `write_pos`.
+///
+/// The slot index alone is ambiguous with a single slot, so positions are walked and each slot
+/// is checked against the stamp it has when holding the item of that position.
+pub fn drop_published<T>(slots: &mut [Slot<T>], read_pos: usize, write_pos: usize) {
+    let size = slots.len();
+    let mut pos = read_pos;
+    while pos != write_pos {
+        let slot = &slots[pos & (size - 1)];
+        if slot.stamp.load(Ordering::Relaxed) == pos.wrapping_add(1) {
+            unsafe { drop(slot.take()) };
+        }
+        pos = pos.wrapping_add(1);
+    }
+}
@@ -0,0 +1,368 @@
+use sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use sync::Arc;
+use alloc::vec::Vec;
+
+use cache_padded::CachePadded;
+#[cfg(feature = "std")]
+use error::Disconnected;
+use error::PushError;
+use index;
+use slot::{self, Slot};
+#[cfg(feature = "std")]
+use wait::WaitStrategy;
+use waiter::Waiter;
+
+/// Lockfree SPMC fixed size ring buffer.
+///
+/// Consumers compete for items by CAS on the read position, so every item is popped by exactly
+/// one consumer.
+pub struct SpmcRingBuffer<T> {
+    size: usize,
+    slots: Vec<Slot<T>>,
+    write_pos: CachePadded<AtomicUsize>,
+    read_pos: CachePadded<AtomicUsize>,
+    push_waiter: Waiter,
+    pop_waiter: Waiter,
+    /// Live `Consumer` handles, the last one to go abandons the buffer.
+    consumers: AtomicUsize,
+    abandoned: AtomicBool,
+}
+
+unsafe impl<T: Send> Send for SpmcRingBuffer<T>{ }
+
+/// Writing half of a split `SpmcRingBuffer`.
+pub struct Producer<T> {
+    buffer: Arc<SpmcRingBuffer<T>>,
+}
+
+unsafe impl<T: Send> Send for Producer<T>{ }
+
+/// Reading half of a split `SpmcRingBuffer`, clone it to get more consumers.
+pub struct Consumer<T> {
+    buffer: Arc<SpmcRingBuffer<T>>,
+}
+
+unsafe impl<T: Send> Send for Consumer<T>{ }
+
+impl<T> SpmcRingBuffer<T> {
+    /// Creates a buffer holding at least `size` items, the capacity is rounded up to a power of
+    /// two.
+    pub fn new(size: usize) -> SpmcRingBuffer<T> {
+        let size = index::capacity(size);
+        let slots = (0..size).map(Slot::new).collect();
+        SpmcRingBuffer {
+            size,
+            slots,
+            write_pos: CachePadded::new(AtomicUsize::new(0)),
+            read_pos: CachePadded::new(AtomicUsize::new(0)),
+            push_waiter: Waiter::new(),
+            pop_waiter: Waiter::new(),
+            consumers: AtomicUsize::new(1),
+            abandoned: AtomicBool::new(false),
+        }
+    }
+
+    /// Like `new` but blocked threads wait according to `strategy` instead of parking.
+    #[cfg(feature = "std")]
+    pub fn with_strategy<S: WaitStrategy + 'static>(size: usize, strategy: S) -> SpmcRingBuffer<T> {
+        let strategy: ::std::sync::Arc<dyn WaitStrategy> = ::std::sync::Arc::new(strategy);
+        let mut rb = SpmcRingBuffer::new(size);
+        rb.push_waiter = Waiter::with_strategy(strategy.clone());
+        rb.pop_waiter = Waiter::with_strategy(strategy);
+        rb
+    }
+
+    #[cfg(feature = "std")]
+    pub fn push(&self, item: T) {
+        let write_pos = self.write_pos.load(Ordering::Relaxed);
+        let slot = &self.slots[write_pos & (self.size - 1)];
+        self.push_waiter.wait(|| slot.stamp.load(Ordering::Acquire) == write_pos);
+        self.put(write_pos, item);
+    }
+
+    /// Pushes an item unless the buffer is full, in which case it is handed back.
+    pub fn try_push(&self, item: T) -> Result<(), PushError<T>> {
+        let write_pos = self.write_pos.load(Ordering::Relaxed);
+        let slot = &self.slots[write_pos & (self.size - 1)];
+        if slot.stamp.load(Ordering::Acquire) != write_pos {
+            return Err(PushError::Full(item));
+        }
+        self.put(write_pos, item);
+        Ok(())
+    }
+
+    fn put(&self, write_pos: usize, item: T) {
+        let slot = &self.slots[write_pos & (self.size - 1)];
+        unsafe { slot.write(item); }
+        slot.stamp.store(write_pos.wrapping_add(1), Ordering::Release);
+        self.write_pos.store(write_pos.wrapping_add(1), Ordering::Release);
+        self.pop_waiter.notify();
+    }
+
+    #[cfg(feature = "std")]
+    pub fn pop(&self) -> T {
+        loop {
+            if let Some(item) = self.try_pop() {
+                return item;
+            }
+            let read_pos = self.read_pos.load(Ordering::Relaxed);
+            self.pop_waiter.wait(|| self.readable(read_pos));
+        }
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        let mut read_pos = self.read_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[read_pos & (self.size - 1)];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            let diff = stamp.wrapping_sub(read_pos.wrapping_add(1)) as isize;
+            if diff == 0 {
+                match self.read_pos.compare_exchange_weak(read_pos, read_pos.wrapping_add(1),
+                                                          Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        let item = unsafe { slot.take() };
+                        slot.stamp.store(read_pos.wrapping_add(self.size), Ordering::Release);
+                        self.push_waiter.notify();
+                        return Some(item);
+                    }
+                    Err(pos) => read_pos = pos,
+                }
+            } else if diff < 0 {
+                return None;
+            } else {
+                read_pos = self.read_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Whether waiting on the slot of `read_pos` is over, it either holds its item or another
+    /// consumer took it. A claimed slot only counts once it is stamped, so there is no spinning
+    /// on a claim whose write or take is still in flight.
+    #[cfg(feature = "std")]
+    fn readable(&self, read_pos: usize) -> bool {
+        let stamp = self.slots[read_pos & (self.size - 1)].stamp.load(Ordering::Acquire);
+        stamp.wrapping_sub(read_pos.wrapping_add(1)) as isize >= 0
+    }
+
+    fn is_abandoned(&self) -> bool {
+        self.abandoned.load(Ordering::Acquire)
+    }
+
+    /// Number of published items which have not been claimed by a consumer yet.
+    pub fn len(&self) -> usize {
+        let read_pos = self.read_pos.load(Ordering::Acquire);
+        let write_pos = self.write_pos.load(Ordering::Acquire);
+        write_pos.wrapping_sub(read_pos).min(self.size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Number of items which can be pushed before the buffer is full.
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Number of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Splits the buffer into a single producer and a cloneable consumer.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let buffer = Arc::new(self);
+        (Producer { buffer: buffer.clone() }, Consumer { buffer })
+    }
+}
+
+impl<T> Drop for SpmcRingBuffer<T> {
+    fn drop(&mut self) {
+        let read_pos = self.read_pos.load(Ordering::Relaxed);
+        let write_pos = self.write_pos.load(Ordering::Relaxed);
+        slot::drop_published(&mut self.slots, read_pos, write_pos);
+    }
+}
+
+impl<T> Producer<T> {
+    /// Blocks until the item is pushed, failing once every consumer is gone.
+    #[cfg(feature = "std")]
+    pub fn push(&mut self, item: T) -> Result<(), PushError<T>> {
+        let buffer = &*self.buffer;
+        let write_pos = buffer.write_pos.load(Ordering::Relaxed);
+        let slot = &buffer.slots[write_pos & (buffer.size - 1)];
+        buffer.push_waiter.wait(|| {
+            slot.stamp.load(Ordering::Acquire) == write_pos || buffer.is_abandoned()
+        });
+        if buffer.is_abandoned() {
+            return Err(PushError::Disconnected(item));
+        }
+        buffer.put(write_pos, item);
+        Ok(())
+    }
+
+    pub fn try_push(&mut self, item: T) -> Result<(), PushError<T>> {
+        if self.is_abandoned() {
+            return Err(PushError::Disconnected(item));
+        }
+        self.buffer.try_push(item)
+    }
+
+    /// Whether every consumer has been dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.buffer.is_abandoned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.buffer.abandoned.store(true, Ordering::Release);
+        self.buffer.pop_waiter.notify();
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Blocks until an item is available, failing once the producer is gone and the buffer has
+    /// been drained.
+    #[cfg(feature = "std")]
+    pub fn pop(&mut self) -> Result<T, Disconnected> {
+        let buffer = &*self.buffer;
+        loop {
+            if let Some(item) = buffer.try_pop() {
+                return Ok(item);
+            }
+            if buffer.is_abandoned() {
+                return buffer.try_pop().ok_or(Disconnected);
+            }
+            let read_pos = buffer.read_pos.load(Ordering::Relaxed);
+            buffer.pop_waiter.wait(|| buffer.readable(read_pos) || buffer.is_abandoned());
+        }
+    }
+
+    pub fn try_pop(&mut self) -> Option<T> {
+        self.buffer.try_pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Whether the producer has been dropped, there may still be items left to pop.
+    pub fn is_abandoned(&self) -> bool {
+        self.buffer.is_abandoned()
+    }
+}
+
+impl<T> Clone for Consumer<T> {
+    fn clone(&self) -> Consumer<T> {
+        self.buffer.consumers.fetch_add(1, Ordering::Relaxed);
+        Consumer { buffer: self.buffer.clone() }
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        if self.buffer.consumers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.buffer.abandoned.store(true, Ordering::Release);
+            self.buffer.push_waiter.notify();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use {Disconnected, SpmcRingBuffer, PushError};
+    use std::thread;
+
+    #[test]
+    fn try_push_pop() {
+        let rb = SpmcRingBuffer::new(2);
+        assert_eq!(Ok(()), rb.try_push(1));
+        assert_eq!(Ok(()), rb.try_push(2));
+        assert_eq!(Err(PushError::Full(3)), rb.try_push(3));
+        assert_eq!(Some(1), rb.try_pop());
+        assert_eq!(Some(2), rb.try_pop());
+        assert_eq!(None, rb.try_pop());
+    }
+
+    #[test]
+    fn many_consumers() {
+        let (mut p, c) = SpmcRingBuffer::new(4).split();
+        let threads: Vec<_> = (0..4).map(|_| {
+            let mut c = c.clone();
+            thread::spawn(move || (0..250).map(|_| c.pop().unwrap()).collect::<Vec<_>>())
+        }).collect();
+        for i in 0..1000 {
+            p.push(i).unwrap();
+        }
+        let mut seen: Vec<_> = threads.into_iter().flat_map(|t| t.join().unwrap()).collect();
+        seen.sort();
+        assert_eq!(seen, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn dropped_producer_disconnects() {
+        let (mut p, c) = SpmcRingBuffer::new(2).split();
+        let threads: Vec<_> = (0..2).map(|_| {
+            let mut c = c.clone();
+            thread::spawn(move || c.pop().err())
+        }).collect();
+        p.push(1).unwrap();
+        drop(p);
+        let errors: Vec<_> = threads.into_iter().filter_map(|t| t.join().unwrap()).collect();
+        assert_eq!(errors, [Disconnected]);
+        assert!(c.is_abandoned());
+    }
+
+    #[test]
+    fn dropped_consumers_disconnect() {
+        let (mut p, c) = SpmcRingBuffer::new(1).split();
+        let d = c.clone();
+        p.push(1).unwrap();
+        drop(c);
+        assert!(!p.is_abandoned());
+        let t = thread::spawn(move || p.push(2));
+        drop(d);
+        assert_eq!(t.join().unwrap(), Err(PushError::Disconnected(2)));
+    }
+}
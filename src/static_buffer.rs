@@ -0,0 +1,314 @@
+use sync::atomic::{AtomicBool, Ordering};
+use core::cell::{Cell, UnsafeCell};
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::slice;
+
+use cache_padded::CachePadded;
+use error::PushError;
+use index::{self, Cursor, Index};
+
+/// Lockfree SPSC ring buffer storing `N` items inline.
+///
+/// `new` is a `const fn`, so the buffer can be placed in a `static` and handed out once through
+/// `split`. Only the non-blocking operations are provided. `N` must be a power of two.
+///
+/// The positions are stored as `I`, which can be narrowed to `u16` or `u32` to shrink the buffer
+/// on small targets as long as `N` is at most `I::MAX_SIZE`. They wrap around within the chosen
+/// width, however many items pass through.
+pub struct StaticRingBuffer<T, const N: usize, I: Index = usize> {
+    // Only the slots in `read_pos..write_pos` are initialized.
+    items: UnsafeCell<[MaybeUninit<T>; N]>,
+    write: CachePadded<Cursor<I>>,
+    read: CachePadded<Cursor<I>>,
+    split: AtomicBool,
+}
+
+unsafe impl<T: Send, const N: usize, I: Index> Sync for StaticRingBuffer<T, N, I>{ }
+
+/// Writing half of a split `StaticRingBuffer`.
+pub struct Producer<'a, T: 'a, const N: usize, I: Index + 'a = usize> {
+    buffer: &'a StaticRingBuffer<T, N, I>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+/// Reading half of a split `StaticRingBuffer`.
+pub struct Consumer<'a, T: 'a, const N: usize, I: Index + 'a = usize> {
+    buffer: &'a StaticRingBuffer<T, N, I>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T, const N: usize, I: Index> StaticRingBuffer<T, N, I> {
+    pub const fn new() -> StaticRingBuffer<T, N, I> {
+        const {
+            assert!(N.is_power_of_two(), "StaticRingBuffer capacity must be a power of two");
+            assert!(N <= I::MAX_SIZE, "StaticRingBuffer capacity too large for its index type")
+        };
+        StaticRingBuffer {
+            items: UnsafeCell::new([const { MaybeUninit::uninit() }; N]),
+            write: CachePadded::new(Cursor::new()),
+            read: CachePadded::new(Cursor::new()),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    /// Hands out the producer and consumer, this succeeds only once per buffer.
+    pub fn split(&self) -> Option<(Producer<'_, T, N, I>, Consumer<'_, T, N, I>)> {
+        if self.split.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        Some((Producer { buffer: self, _not_sync: PhantomData },
+              Consumer { buffer: self, _not_sync: PhantomData }))
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn len(&self) -> usize {
+        let read_pos = self.read.load(Ordering::Acquire);
+        let write_pos = self.write.load(Ordering::Acquire);
+        index::distance(write_pos, read_pos, N)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Number of items which can be pushed before the buffer is full.
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    fn slot(&self, pos: usize) -> *mut MaybeUninit<T> {
+        unsafe { (self.items.get() as *mut MaybeUninit<T>).add(index::index(pos, N)) }
+    }
+}
+
+impl<T, const N: usize, I: Index> Default for StaticRingBuffer<T, N, I> {
+    fn default() -> StaticRingBuffer<T, N, I> {
+        StaticRingBuffer::new()
+    }
+}
+
+impl<T, const N: usize, I: Index> Drop for StaticRingBuffer<T, N, I> {
+    fn drop(&mut self) {
+        let read_pos = self.read.load(Ordering::Relaxed);
+        let len = index::distance(self.write.load(Ordering::Relaxed), read_pos, N);
+        for i in 0..len {
+            unsafe { (*self.slot(index::advance(read_pos, i, N))).assume_init_drop(); }
+        }
+    }
+}
+
+impl<'a, T, const N: usize, I: Index> Producer<'a, T, N, I> {
+    /// Pushes an item unless the buffer is full, in which case it is handed back.
+    pub fn try_push(&mut self, item: T) -> Result<(), PushError<T>> {
+        let buffer = self.buffer;
+        let write = &buffer.write;
+        let write_pos = write.load(Ordering::Relaxed);
+        if index::distance(write_pos, write.cached(), N) == N {
+            write.set_cached(buffer.read.load(Ordering::Acquire));
+            if index::distance(write_pos, write.cached(), N) == N {
+                return Err(PushError::Full(item));
+            }
+        }
+        unsafe { (*buffer.slot(write_pos)).write(item); }
+        write.store(index::advance(write_pos, 1, N), Ordering::Release);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<'a, T, const N: usize, I: Index> Consumer<'a, T, N, I> {
+    pub fn try_pop(&mut self) -> Option<T> {
+        let buffer = self.buffer;
+        let read_pos = self.next_pos()?;
+        let item = unsafe { (*buffer.slot(read_pos)).assume_init_read() };
+        buffer.read.store(index::advance(read_pos, 1, N), Ordering::Release);
+        Some(item)
+    }
+
+    /// Borrows the next item without popping it.
+    pub fn peek(&self) -> Option<&T> {
+        let read_pos = self.next_pos()?;
+        Some(unsafe { (*self.buffer.slot(read_pos)).assume_init_ref() })
+    }
+
+    /// Mutably borrows the next item without popping it.
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        let read_pos = self.next_pos()?;
+        Some(unsafe { (*self.buffer.slot(read_pos)).assume_init_mut() })
+    }
+
+    /// Borrows the buffered items in pop order, the second slice is only non-empty when they
+    /// wrap around the end of the storage.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let buffer = self.buffer;
+        let read_pos = buffer.read.load(Ordering::Relaxed);
+        let write_pos = buffer.write.load(Ordering::Acquire);
+        buffer.read.set_cached(write_pos);
+        let len = index::distance(write_pos, read_pos, N);
+        let start = index::index(read_pos, N);
+        let first = len.min(N - start);
+        unsafe {
+            (slice::from_raw_parts(buffer.slot(read_pos) as *const T, first),
+             slice::from_raw_parts(buffer.slot(0) as *const T, len - first))
+        }
+    }
+
+    /// Position of the next item, if one is available.
+    fn next_pos(&self) -> Option<usize> {
+        let buffer = self.buffer;
+        let read = &buffer.read;
+        let read_pos = read.load(Ordering::Relaxed);
+        if read.cached() == read_pos {
+            read.set_cached(buffer.write.load(Ordering::Acquire));
+            if read.cached() == read_pos {
+                return None;
+            }
+        }
+        Some(read_pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    pub fn slots_free(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Index, StaticRingBuffer, PushError};
+    use std::thread;
+
+    static BUFFER: StaticRingBuffer<u32, 4> = StaticRingBuffer::new();
+
+    #[test]
+    fn try_push_pop() {
+        let rb: StaticRingBuffer<_, 2> = StaticRingBuffer::new();
+        let (mut p, mut c) = rb.split().unwrap();
+        assert_eq!(Ok(()), p.try_push(1));
+        assert_eq!(Ok(()), p.try_push(2));
+        assert_eq!(Err(PushError::Full(3)), p.try_push(3));
+        assert!(p.is_full());
+        assert_eq!(Some(1), c.try_pop());
+        assert_eq!(c.slots_free(), 1);
+        assert_eq!(Some(2), c.try_pop());
+        assert_eq!(None, c.try_pop());
+    }
+
+    #[test]
+    fn peek() {
+        let rb: StaticRingBuffer<_, 2> = StaticRingBuffer::new();
+        let (mut p, mut c) = rb.split().unwrap();
+        assert_eq!(c.peek(), None);
+        p.try_push(1).unwrap();
+        assert_eq!(c.peek(), Some(&1));
+        *c.peek_mut().unwrap() = 2;
+        assert_eq!(c.try_pop(), Some(2));
+        assert_eq!(c.peek(), None);
+    }
+
+    #[test]
+    fn as_slices() {
+        let rb: StaticRingBuffer<_, 4> = StaticRingBuffer::new();
+        let (mut p, mut c) = rb.split().unwrap();
+        for i in 0..4 {
+            p.try_push(i).unwrap();
+        }
+        c.try_pop();
+        p.try_push(4).unwrap();
+        assert_eq!(c.as_slices(), (&[1, 2, 3][..], &[4][..]));
+    }
+
+    #[test]
+    fn narrow_index() {
+        use std::mem::size_of;
+
+        let rb: StaticRingBuffer<u8, 4, u16> = StaticRingBuffer::new();
+        assert!(size_of::<StaticRingBuffer<u8, 4, u16>>() <= size_of::<StaticRingBuffer<u8, 4>>());
+        let (mut p, mut c) = rb.split().unwrap();
+        // Enough laps for the positions to wrap many times over.
+        for i in 0..100_000u32 {
+            p.try_push(i as u8).unwrap();
+            if i % 3 == 0 {
+                p.try_push(0).unwrap();
+                assert_eq!(c.try_pop(), Some(i as u8));
+                assert_eq!(c.try_pop(), Some(0));
+            } else {
+                assert_eq!(c.try_pop(), Some(i as u8));
+            }
+        }
+        assert!(c.is_empty());
+        assert_eq!(<u16 as Index>::MAX_SIZE, 1 << 14);
+        assert_eq!(<usize as Index>::MAX_SIZE, ::index::MAX_SIZE);
+    }
+
+    #[test]
+    fn split_once() {
+        let rb: StaticRingBuffer<u8, 1> = StaticRingBuffer::new();
+        assert!(rb.split().is_some());
+        assert!(rb.split().is_none());
+    }
+
+    #[test]
+    fn static_threads() {
+        let (mut p, mut c) = BUFFER.split().unwrap();
+        let t = thread::spawn(move || {
+            for i in 0..1000 {
+                while p.try_push(i).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+        for i in 0..1000 {
+            loop {
+                if let Some(item) = c.try_pop() {
+                    assert_eq!(item, i);
+                    break;
+                }
+                thread::yield_now();
+            }
+        }
+        t.join().unwrap();
+    }
+}
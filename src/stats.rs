@@ -0,0 +1,318 @@
+//! Opt-in counters of the SPSC `RingBuffer`, compiled out entirely without the `stats`,
+//! `metrics` and `tracing` features.
+
+#[cfg(feature = "tracing")]
+use sync::atomic::AtomicBool;
+#[cfg(feature = "stats")]
+use sync::atomic::AtomicUsize;
+#[cfg(any(feature = "stats", feature = "tracing"))]
+use sync::atomic::Ordering;
+
+#[cfg(feature = "metrics")]
+use metrics::{Counter, Gauge};
+
+#[cfg(feature = "stats")]
+use cache_padded::CachePadded;
+
+/// Snapshot of a `RingBuffer`'s counters, taken with `stats`.
+///
+/// Counts wrap around on overflow.
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Items pushed.
+    pub pushes: usize,
+    /// Items popped, drained or skipped.
+    pub pops: usize,
+    /// Pushes which found the buffer full, whether they then waited or failed.
+    pub full: usize,
+    /// Pops which found the buffer empty, whether they then waited or failed.
+    pub empty: usize,
+    /// Most items buffered at once.
+    pub high_water: usize,
+}
+
+/// The counters of one buffer, each side only updates its own half.
+#[derive(Default)]
+pub struct Counters {
+    #[cfg(feature = "stats")]
+    producer: CachePadded<ProducerCounters>,
+    #[cfg(feature = "stats")]
+    consumer: CachePadded<ConsumerCounters>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+    // Whether the buffer was last seen full by the producer or empty by the consumer, so only
+    // the transitions are traced.
+    #[cfg(feature = "tracing")]
+    was_full: AtomicBool,
+    #[cfg(feature = "tracing")]
+    was_empty: AtomicBool,
+}
+
+#[cfg(feature = "stats")]
+#[derive(Default)]
+struct ProducerCounters {
+    pushes: AtomicUsize,
+    full: AtomicUsize,
+    high_water: AtomicUsize,
+}
+
+#[cfg(feature = "stats")]
+#[derive(Default)]
+struct ConsumerCounters {
+    pops: AtomicUsize,
+    empty: AtomicUsize,
+}
+
+/// Handles registered with the `metrics` recorder which every count is mirrored to.
+#[cfg(feature = "metrics")]
+struct Metrics {
+    pushes: Counter,
+    pops: Counter,
+    full: Counter,
+    empty: Counter,
+    occupancy: Gauge,
+}
+
+/// Adds `n` to a counter only its owning side writes, so no read-modify-write is needed.
+#[cfg(feature = "stats")]
+fn bump(counter: &AtomicUsize, n: usize) {
+    counter.store(counter.load(Ordering::Relaxed).wrapping_add(n), Ordering::Relaxed);
+}
+
+impl Counters {
+    /// Records `n` items pushed, `len` tells how many are buffered now and is only called with
+    /// the feature enabled.
+    #[inline]
+    pub fn pushed<F: FnOnce() -> usize>(&self, n: usize, len: F) {
+        #[cfg(feature = "stats")]
+        {
+            bump(&self.producer.pushes, n);
+            let len = len();
+            if len > self.producer.high_water.load(Ordering::Relaxed) {
+                self.producer.high_water.store(len, Ordering::Relaxed);
+            }
+        }
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(ref metrics) = self.metrics {
+                metrics.pushes.increment(n as u64);
+                metrics.occupancy.increment(n as f64);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        self.was_full.store(false, Ordering::Relaxed);
+        #[cfg(not(feature = "stats"))]
+        let _ = (n, len);
+    }
+
+    #[inline]
+    pub fn popped(&self, n: usize) {
+        #[cfg(feature = "stats")]
+        bump(&self.consumer.pops, n);
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(ref metrics) = self.metrics {
+                metrics.pops.increment(n as u64);
+                metrics.occupancy.decrement(n as f64);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        self.was_empty.store(false, Ordering::Relaxed);
+        let _ = n;
+    }
+
+    #[inline]
+    pub fn full(&self) {
+        #[cfg(feature = "stats")]
+        bump(&self.producer.full, 1);
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(ref metrics) = self.metrics {
+                metrics.full.increment(1);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        {
+            if !self.was_full.swap(true, Ordering::Relaxed) {
+                ::tracing::trace!("buffer full");
+            }
+        }
+    }
+
+    #[inline]
+    pub fn empty(&self) {
+        #[cfg(feature = "stats")]
+        bump(&self.consumer.empty, 1);
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(ref metrics) = self.metrics {
+                metrics.empty.increment(1);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        {
+            if !self.was_empty.swap(true, Ordering::Relaxed) {
+                ::tracing::trace!("buffer empty");
+            }
+        }
+    }
+
+    /// Registers the metrics named after `prefix`, `len` items are buffered already.
+    #[cfg(feature = "metrics")]
+    pub fn register_metrics(&mut self, prefix: &str, len: usize) {
+        let metrics = Metrics {
+            pushes: ::metrics::counter!(format!("{}.pushes", prefix)),
+            pops: ::metrics::counter!(format!("{}.pops", prefix)),
+            full: ::metrics::counter!(format!("{}.full", prefix)),
+            empty: ::metrics::counter!(format!("{}.empty", prefix)),
+            occupancy: ::metrics::gauge!(format!("{}.occupancy", prefix)),
+        };
+        metrics.occupancy.set(len as f64);
+        self.metrics = Some(metrics);
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn snapshot(&self) -> Stats {
+        Stats {
+            pushes: self.producer.pushes.load(Ordering::Relaxed),
+            pops: self.consumer.pops.load(Ordering::Relaxed),
+            full: self.producer.full.load(Ordering::Relaxed),
+            empty: self.consumer.empty.load(Ordering::Relaxed),
+            high_water: self.producer.high_water.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "stats", feature = "metrics", feature = "tracing"))]
+    use ::RingBuffer;
+    #[cfg(feature = "stats")]
+    use super::Stats;
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn counts() {
+        let (mut p, mut c) = RingBuffer::new(2).split();
+        assert_eq!(c.try_pop(), None);
+        p.try_push(1).unwrap();
+        p.try_push(2).unwrap();
+        assert!(p.try_push(3).is_err());
+        assert_eq!(c.try_read(4), vec![1, 2]);
+        assert_eq!(p.try_write(&[3]), 1);
+        assert_eq!(c.stats(), Stats { pushes: 3, pops: 2, full: 1, empty: 1, high_water: 2 });
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn metrics() {
+        use metrics::{self, Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder};
+        use metrics::{SharedString, Unit};
+        use std::collections::HashMap;
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Values(Mutex<HashMap<String, Arc<AtomicU64>>>);
+
+        impl Values {
+            fn get(&self, name: &str) -> u64 {
+                self.0.lock().unwrap()[name].load(Ordering::Relaxed)
+            }
+
+            fn handle(&self, key: &Key) -> Arc<AtomicU64> {
+                self.0.lock().unwrap().entry(key.name().to_string()).or_default().clone()
+            }
+        }
+
+        impl Recorder for Values {
+            fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) { }
+            fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) { }
+            fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) { }
+
+            fn register_counter(&self, key: &Key, _: &Metadata) -> Counter {
+                Counter::from_arc(self.handle(key))
+            }
+
+            fn register_gauge(&self, key: &Key, _: &Metadata) -> Gauge {
+                Gauge::from_arc(self.handle(key))
+            }
+
+            fn register_histogram(&self, _: &Key, _: &Metadata) -> Histogram {
+                Histogram::noop()
+            }
+        }
+
+        let values = Values::default();
+        let rb = metrics::with_local_recorder(&values, || RingBuffer::new(2).metrics("rb"));
+        let (mut p, mut c) = rb.split();
+        p.try_push(1).unwrap();
+        p.try_push(2).unwrap();
+        assert!(p.try_push(3).is_err());
+        assert_eq!(c.try_pop(), Some(1));
+        assert_eq!(values.get("rb.pushes"), 2);
+        assert_eq!(values.get("rb.pops"), 1);
+        assert_eq!(values.get("rb.full"), 1);
+        // Gauges are stored as the bits of an `f64`.
+        assert_eq!(f64::from_bits(values.get("rb.occupancy")), 1.0);
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn traces_transitions() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{self, Event, Metadata, Subscriber};
+
+        #[derive(Clone, Default)]
+        struct Count {
+            events: Arc<AtomicUsize>,
+            spans: Arc<AtomicUsize>,
+        }
+
+        impl Subscriber for Count {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn new_span(&self, _: &Attributes) -> Id {
+                Id::from_u64(self.spans.fetch_add(1, Ordering::Relaxed) as u64 + 1)
+            }
+
+            fn record(&self, _: &Id, _: &Record) { }
+            fn record_follows_from(&self, _: &Id, _: &Id) { }
+
+            fn event(&self, _: &Event) {
+                self.events.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn enter(&self, _: &Id) { }
+            fn exit(&self, _: &Id) { }
+        }
+
+        let count = Count::default();
+        tracing::subscriber::with_default(count.clone(), || {
+            let (mut p, mut c) = RingBuffer::new(1).split();
+            p.try_push(1).unwrap();
+            // Only the first of these fails into a full buffer.
+            for _ in 0..3 {
+                assert!(p.try_push(2).is_err());
+            }
+            assert_eq!(count.events.load(Ordering::Relaxed), 1);
+            let t = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                assert_eq!(c.try_pop(), Some(1));
+                c
+            });
+            // Waits for the pop, the wait gets a span and an event once it is over.
+            p.push(3).unwrap();
+            drop(t.join().unwrap());
+            assert_eq!(count.spans.load(Ordering::Relaxed), 1);
+        });
+        assert_eq!(count.events.load(Ordering::Relaxed), 2);
+    }
+}
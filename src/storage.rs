@@ -0,0 +1,226 @@
+use core::mem::MaybeUninit;
+use core::ptr::{self, NonNull};
+use alloc::boxed::Box;
+use alloc::collections::TryReserveError;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use alloc::alloc::{self as global, handle_alloc_error};
+#[cfg(feature = "allocator_api")]
+use alloc::alloc::{AllocError, Allocator};
+#[cfg(any(all(feature = "hugepages", unix), all(feature = "numa", target_os = "linux")))]
+use std::io;
+
+#[cfg(any(all(feature = "hugepages", unix), all(feature = "numa", target_os = "linux")))]
+use libc;
+
+#[cfg(feature = "loom")]
+use loom::cell::UnsafeCell;
+#[cfg(all(feature = "numa", target_os = "linux"))]
+use numa::{self, NumaNode};
+
+/// Frees slots which didn't come from a boxed slice.
+type Dealloc = Box<dyn FnOnce(NonNull<u8>) + Send>;
+
+/// Size of the huge pages `huge_pages` rounds up to.
+#[cfg(all(feature = "hugepages", unix))]
+const HUGE_PAGE: usize = 2 << 20;
+/// Storage at least this large is tried on 1GB pages first.
+#[cfg(all(feature = "hugepages", any(target_os = "linux", target_os = "android")))]
+const GIGANTIC_PAGE: usize = 1 << 30;
+
+/// The slots of a `RingBuffer`, from the global allocator, the one passed to `new_in` or mapped
+/// onto huge pages or a NUMA node.
+///
+/// Where the slots came from is erased into the function freeing them, so it doesn't show up in
+/// the type of the buffer and its halves.
+pub struct Storage<T> {
+    ptr: NonNull<MaybeUninit<T>>,
+    len: usize,
+    dealloc: Option<Dealloc>,
+    // Stands in for the slots in loom's model, which can't see accesses through `ptr`.
+    #[cfg(feature = "loom")]
+    access: Box<[UnsafeCell<()>]>,
+}
+
+impl<T> Storage<T> {
+    /// Allocates `len` slots, nothing for zero sized items.
+    pub fn new(len: usize) -> Storage<T> {
+        let mut items = Vec::with_capacity(len);
+        unsafe { items.set_len(len) };
+        Storage::from_box(items.into_boxed_slice())
+    }
+
+    pub fn try_new(len: usize) -> Result<Storage<T>, TryReserveError> {
+        let mut items = Vec::new();
+        items.try_reserve_exact(len)?;
+        // Uninitialized slots are all there is to fill in, whatever the item size.
+        unsafe { items.set_len(len) };
+        Ok(Storage::from_box(items.into_boxed_slice()))
+    }
+
+    pub fn from_box(items: Box<[MaybeUninit<T>]>) -> Storage<T> {
+        let len = items.len();
+        let ptr = unsafe { NonNull::new_unchecked(Box::into_raw(items) as *mut MaybeUninit<T>) };
+        Storage::raw(ptr, len, None)
+    }
+
+    /// Allocates `len` slots from the global allocator, the first one aligned to at least
+    /// `align` bytes.
+    pub fn aligned(len: usize, align: usize) -> Storage<T> {
+        let layout = Layout::array::<T>(len)
+            .and_then(|layout| layout.align_to(align))
+            .expect("capacity too large");
+        if layout.size() == 0 {
+            // Nothing to allocate, any pointer with the alignment will do.
+            let ptr = unsafe { NonNull::new_unchecked(layout.align() as *mut MaybeUninit<T>) };
+            return Storage::raw(ptr, len, Some(Box::new(|_| { })));
+        }
+        let ptr = match NonNull::new(unsafe { global::alloc(layout) }) {
+            Some(ptr) => ptr,
+            None => handle_alloc_error(layout),
+        };
+        let dealloc = move |ptr: NonNull<u8>| unsafe { global::dealloc(ptr.as_ptr(), layout) };
+        Storage::raw(ptr.cast(), len, Some(Box::new(dealloc)))
+    }
+
+    /// Allocates `len` slots from `alloc`, which is kept until the slots are freed.
+    #[cfg(feature = "allocator_api")]
+    pub fn new_in<A>(len: usize, alloc: A) -> Result<Storage<T>, AllocError>
+        where A: Allocator + Send + 'static
+    {
+        let layout = Layout::array::<T>(len).map_err(|_| AllocError)?;
+        let ptr = alloc.allocate(layout)?.cast::<MaybeUninit<T>>();
+        let dealloc = move |ptr| unsafe { alloc.deallocate(ptr, layout) };
+        Ok(Storage::raw(ptr, len, Some(Box::new(dealloc))))
+    }
+
+    /// Maps `len` slots onto huge pages: explicit 1GB or 2MB pages where Linux has them
+    /// reserved, otherwise a 2MB aligned mapping advised to use transparent huge pages.
+    #[cfg(all(feature = "hugepages", unix))]
+    pub fn huge_pages(len: usize) -> io::Result<Storage<T>> {
+        let layout = Layout::array::<T>(len)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "capacity too large"))?;
+        assert!(layout.align() <= HUGE_PAGE, "items aligned beyond a huge page");
+        let size = layout.size().max(1).next_multiple_of(HUGE_PAGE);
+        let (ptr, mapped) = unsafe { map_huge(size)? };
+        Ok(Storage::mapped(ptr, mapped, len))
+    }
+
+    /// Maps `len` slots whose pages are bound to `node` when first touched.
+    #[cfg(all(feature = "numa", target_os = "linux"))]
+    pub fn on_node(len: usize, node: NumaNode) -> io::Result<Storage<T>> {
+        let node = node.id()?;
+        let layout = Layout::array::<T>(len)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "capacity too large"))?;
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        assert!(layout.align() <= page, "items aligned beyond a page");
+        let size = layout.size().max(1).next_multiple_of(page);
+        unsafe {
+            let prot = libc::PROT_READ | libc::PROT_WRITE;
+            let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+            let addr = libc::mmap(ptr::null_mut(), size, prot, flags, -1, 0);
+            if addr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            let storage = Storage::mapped(NonNull::new_unchecked(addr as *mut u8), size, len);
+            numa::bind(addr as *mut u8, size, node)?;
+            Ok(storage)
+        }
+    }
+
+    /// Takes over the `mapped` bytes at `ptr`, unmapping them once dropped.
+    #[cfg(any(all(feature = "hugepages", unix), all(feature = "numa", target_os = "linux")))]
+    fn mapped(ptr: NonNull<u8>, mapped: usize, len: usize) -> Storage<T> {
+        let dealloc = move |ptr: NonNull<u8>| unsafe {
+            libc::munmap(ptr.as_ptr() as *mut libc::c_void, mapped);
+        };
+        Storage::raw(ptr.cast(), len, Some(Box::new(dealloc)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Pointer to slot `i`, or one past the end for `i == len`.
+    ///
+    /// The producer and consumer own different slots at the same time, so they may only ever
+    /// borrow the slots they own, never the storage as a whole.
+    #[inline]
+    pub fn slot(&self, i: usize) -> *mut MaybeUninit<T> {
+        debug_assert!(i <= self.len);
+        unsafe { self.ptr.as_ptr().add(i) }
+    }
+
+    fn raw(ptr: NonNull<MaybeUninit<T>>, len: usize, dealloc: Option<Dealloc>) -> Storage<T> {
+        Storage {
+            ptr,
+            len,
+            dealloc,
+            #[cfg(feature = "loom")]
+            access: (0..len).map(|_| UnsafeCell::new(())).collect(),
+        }
+    }
+
+    /// Tells loom slot `i` is written now, so it can check nothing else touches the slot
+    /// concurrently.
+    #[inline]
+    pub fn track_write(&self, i: usize) {
+        #[cfg(feature = "loom")]
+        self.access[i].with_mut(|_| ());
+        let _ = i;
+    }
+
+    /// Tells loom slot `i` is read now.
+    #[inline]
+    pub fn track_read(&self, i: usize) {
+        #[cfg(feature = "loom")]
+        self.access[i].with(|_| ());
+        let _ = i;
+    }
+}
+
+/// Maps `size` bytes, a multiple of `HUGE_PAGE`, returning the mapping and its length.
+#[cfg(all(feature = "hugepages", unix))]
+unsafe fn map_huge(size: usize) -> io::Result<(NonNull<u8>, usize)> {
+    let prot = libc::PROT_READ | libc::PROT_WRITE;
+    let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let pages = [(libc::MAP_HUGE_1GB, GIGANTIC_PAGE), (libc::MAP_HUGE_2MB, HUGE_PAGE)];
+        for &(page, page_size) in pages.iter().filter(|&&(_, page_size)| size >= page_size) {
+            let len = size.next_multiple_of(page_size);
+            let addr = libc::mmap(ptr::null_mut(), len, prot, flags | libc::MAP_HUGETLB | page,
+                                  -1, 0);
+            if addr != libc::MAP_FAILED {
+                return Ok((NonNull::new_unchecked(addr as *mut u8), len));
+            }
+        }
+    }
+    // No pages reserved, over-map to cut out an aligned range transparent huge pages can back.
+    let addr = libc::mmap(ptr::null_mut(), size + HUGE_PAGE, prot, flags, -1, 0);
+    if addr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    let start = addr as usize;
+    let aligned = start.next_multiple_of(HUGE_PAGE);
+    if aligned != start {
+        libc::munmap(addr, aligned - start);
+    }
+    let tail = HUGE_PAGE - (aligned - start);
+    if tail != 0 {
+        libc::munmap((aligned + size) as *mut libc::c_void, tail);
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    libc::madvise(aligned as *mut libc::c_void, size, libc::MADV_HUGEPAGE);
+    Ok((NonNull::new_unchecked(aligned as *mut u8), size))
+}
+
+impl<T> Drop for Storage<T> {
+    fn drop(&mut self) {
+        if let Some(dealloc) = self.dealloc.take() {
+            dealloc(self.ptr.cast());
+            return;
+        }
+        unsafe { drop(Box::from_raw(ptr::slice_from_raw_parts_mut(self.ptr.as_ptr(), self.len))) };
+    }
+}
@@ -0,0 +1,79 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{Sink, Stream};
+
+use {Consumer, Disconnected, Producer, PushError};
+
+/// Sending fails with `Disconnected` once the consumer is gone, closing the sink closes the
+/// buffer.
+impl<T> Sink<T> for Producer<T> {
+    type Error = Disconnected;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Disconnected>> {
+        match self.poll_push_ready(cx) {
+            Poll::Ready(()) if self.is_abandoned() => Poll::Ready(Err(Disconnected)),
+            Poll::Ready(()) => Poll::Ready(Ok(())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Disconnected> {
+        match self.get_mut().try_push(item) {
+            Ok(()) => Ok(()),
+            Err(PushError::Disconnected(_)) => Err(Disconnected),
+            Err(PushError::Full(_)) => panic!("start_send called without poll_ready"),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), Disconnected>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), Disconnected>> {
+        self.get_mut().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The stream ends once the producer is gone or closed the buffer and it is drained.
+impl<T> Stream for Consumer<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.get_mut().poll_pop(cx).map(Result::ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::RingBuffer;
+    use futures::executor::block_on;
+    use futures::{stream, SinkExt, StreamExt};
+    use std::thread;
+
+    #[test]
+    fn sink_stream() {
+        let (mut p, c) = RingBuffer::new(2).split();
+        let t = thread::spawn(move || {
+            block_on(p.send_all(&mut stream::iter((0..100).map(Ok)))).unwrap();
+        });
+        let items: Vec<_> = block_on(c.collect());
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn close_ends_stream() {
+        let (mut p, c) = RingBuffer::new(2).split();
+        let t = thread::spawn(move || {
+            block_on(p.send(1)).unwrap();
+            block_on(p.send(2)).unwrap();
+            block_on(SinkExt::close(&mut p)).unwrap();
+            p
+        });
+        let items: Vec<_> = block_on(c.collect());
+        assert_eq!(items, [1, 2]);
+        drop(t.join().unwrap());
+    }
+}
@@ -0,0 +1,44 @@
+//! The atomics and `Arc` the buffers are built on.
+//!
+//! With the `portable-atomic` feature the atomics come from the `portable-atomic` crate, which
+//! emulates the operations a target lacks, and so does `Arc` on targets without native pointer
+//! sized compare-and-swap. The `loom` feature swaps in loom's versions instead, so the model
+//! tests in `model.rs` can explore every interleaving of their operations.
+//!
+//! With the `strict-ordering` feature `Ordering` is replaced by constants which all stand for
+//! `SeqCst`, so every operation written as `Ordering::Acquire` and so on becomes sequentially
+//! consistent without touching it.
+
+pub mod atomic {
+    #[cfg(feature = "loom")]
+    pub use loom::sync::atomic::*;
+    #[cfg(all(not(feature = "loom"), not(feature = "portable-atomic")))]
+    pub use core::sync::atomic::*;
+    #[cfg(all(not(feature = "loom"), feature = "portable-atomic"))]
+    pub use portable_atomic::*;
+    #[cfg(feature = "strict-ordering")]
+    pub use super::strict::Ordering;
+}
+
+#[cfg(feature = "strict-ordering")]
+mod strict {
+    #[allow(non_snake_case, non_upper_case_globals)]
+    pub mod Ordering {
+        use core::sync::atomic::Ordering;
+
+        pub const Relaxed: Ordering = Ordering::SeqCst;
+        pub const Release: Ordering = Ordering::SeqCst;
+        pub const Acquire: Ordering = Ordering::SeqCst;
+        pub const AcqRel: Ordering = Ordering::SeqCst;
+        pub const SeqCst: Ordering = Ordering::SeqCst;
+    }
+}
+
+#[cfg(feature = "loom")]
+pub use loom::sync::Arc;
+#[cfg(all(not(feature = "loom"), feature = "alloc",
+          any(not(feature = "portable-atomic"), target_has_atomic = "ptr")))]
+pub use alloc::sync::Arc;
+#[cfg(all(not(feature = "loom"), feature = "alloc", feature = "portable-atomic",
+          not(target_has_atomic = "ptr")))]
+pub use portable_atomic_util::Arc;
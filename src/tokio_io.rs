@@ -0,0 +1,160 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use {byte, Consumer, Producer};
+
+impl AsyncWrite for Producer<u8> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        match self.poll_push_ready(cx) {
+            Poll::Ready(()) if self.is_abandoned() => {
+                Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+            }
+            Poll::Ready(()) => Poll::Ready(Ok(self.buffer.try_write(buf))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        self.get_mut().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for Consumer<u8> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        if self.poll_pop_ready(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let read = self.get_mut().read_into(buf.initialize_unfilled());
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Writes straight into the free bytes before the wrap point.
+impl AsyncWrite for byte::Producer {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        match this.as_inner().poll_push_ready(cx) {
+            Poll::Ready(()) if this.is_abandoned() => {
+                Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+            }
+            Poll::Ready(()) => {
+                let free = this.as_mut_slice();
+                let n = free.len().min(buf.len());
+                free[..n].copy_from_slice(&buf[..n]);
+                this.commit(n);
+                Poll::Ready(Ok(n))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        self.get_mut().as_inner().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for byte::Consumer {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        if this.as_inner().poll_pop_ready(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let read = this.read(buf.initialize_unfilled());
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::{ByteRingBuffer, RingBuffer};
+    use std::future::Future;
+    use std::thread;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::runtime::Builder;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn async_read_write() {
+        let (mut p, mut c) = RingBuffer::new(16).split();
+        let data: Vec<u8> = (0..255).collect();
+        let expected = data.clone();
+        let t = thread::spawn(move || {
+            block_on(p.write_all(&data)).unwrap();
+        });
+        let mut out = Vec::new();
+        block_on(c.read_to_end(&mut out)).unwrap();
+        assert_eq!(out, expected);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn shutdown_ends_read() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        let t = thread::spawn(move || {
+            block_on(p.write_all(b"abcdefgh")).unwrap();
+            block_on(p.shutdown()).unwrap();
+            // Kept alive until the reader saw the end.
+            p
+        });
+        let mut out = Vec::new();
+        block_on(c.read_to_end(&mut out)).unwrap();
+        assert_eq!(out, b"abcdefgh");
+        assert!(c.is_closed());
+        drop(t.join().unwrap());
+    }
+
+    #[test]
+    fn reads_across_wrap() {
+        let (mut p, mut c) = RingBuffer::new(4).split();
+        p.try_write(&[1, 2, 3]);
+        let mut out = [0; 4];
+        assert_eq!(block_on(AsyncReadExt::read(&mut c, &mut out[..2])).unwrap(), 2);
+        p.try_write(&[4, 5, 6]);
+        assert_eq!(block_on(AsyncReadExt::read(&mut c, &mut out)).unwrap(), 4);
+        assert_eq!(out, [3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn byte_halves() {
+        let (mut p, mut c) = ByteRingBuffer::new(4).split();
+        let t = thread::spawn(move || {
+            block_on(p.write_all(b"one two three")).unwrap();
+            block_on(p.shutdown()).unwrap();
+            p
+        });
+        let mut out = Vec::new();
+        block_on(c.read_to_end(&mut out)).unwrap();
+        assert_eq!(out, b"one two three");
+        drop(t.join().unwrap());
+    }
+}
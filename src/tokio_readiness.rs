@@ -0,0 +1,112 @@
+use std::future::Future;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::unix::AsyncFd;
+
+use Consumer;
+
+/// Consumer whose readiness descriptor is registered with the tokio reactor, so a task can
+/// await items with `readable` and then drain them with `try_pop`.
+pub struct AsyncConsumer<T> {
+    // Dropped first, so the descriptor is deregistered before the consumer may close it.
+    fd: AsyncFd<RawFd>,
+    consumer: Consumer<T>,
+}
+
+/// Future returned by `AsyncConsumer::readable`.
+pub struct Readable<'a, T: 'a> {
+    consumer: &'a AsyncConsumer<T>,
+}
+
+impl<T> AsyncConsumer<T> {
+    /// Registers the consumer's readiness descriptor, must be called within a tokio runtime
+    /// with IO enabled.
+    pub fn new(consumer: Consumer<T>) -> io::Result<AsyncConsumer<T>> {
+        let fd = AsyncFd::new(consumer.readiness_fd()?)?;
+        Ok(AsyncConsumer { fd, consumer })
+    }
+
+    /// Waits until items are buffered or the producer is gone.
+    pub fn readable(&self) -> Readable<'_, T> {
+        Readable { consumer: self }
+    }
+
+    /// Polls for buffered items or a dropped producer, registering the task to be woken once
+    /// either happens.
+    pub fn poll_readable(&self, cx: &mut Context) -> Poll<io::Result<()>> {
+        loop {
+            if !self.consumer.is_empty() || self.consumer.is_abandoned() {
+                return Poll::Ready(Ok(()));
+            }
+            let mut guard = match self.fd.poll_read_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            };
+            // Reset both before checking again so a push right after is signalled again.
+            guard.clear_ready();
+            self.consumer.clear_readiness();
+        }
+    }
+
+    pub fn get_ref(&self) -> &Consumer<T> {
+        &self.consumer
+    }
+
+    pub fn get_mut(&mut self) -> &mut Consumer<T> {
+        &mut self.consumer
+    }
+
+    /// Deregisters the descriptor and hands back the consumer.
+    pub fn into_inner(self) -> Consumer<T> {
+        self.fd.into_inner();
+        self.consumer
+    }
+}
+
+impl<'a, T> Future for Readable<'a, T> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.consumer.poll_readable(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {AsyncConsumer, RingBuffer};
+    use std::thread;
+    use tokio::runtime::Builder;
+
+    #[test]
+    fn await_items() {
+        let rt = Builder::new_current_thread().enable_io().build().unwrap();
+        let (mut p, c) = RingBuffer::new(8).split();
+        let mut c = {
+            let _runtime = rt.enter();
+            AsyncConsumer::new(c).unwrap()
+        };
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                p.push(i).unwrap();
+            }
+        });
+        let mut next = 0;
+        loop {
+            rt.block_on(c.readable()).unwrap();
+            while let Some(item) = c.get_mut().try_pop() {
+                assert_eq!(item, next);
+                next += 1;
+            }
+            if c.get_ref().is_abandoned() && c.get_ref().is_empty() {
+                break;
+            }
+        }
+        assert_eq!(next, 100);
+        t.join().unwrap();
+        c.into_inner();
+    }
+}
@@ -0,0 +1,320 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ptr;
+use sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use alloc::boxed::Box;
+use sync::Arc;
+
+use cache_padded::CachePadded;
+use error::PushError;
+#[cfg(feature = "std")]
+use error::Disconnected;
+use waiter::Waiter;
+
+/// Slots per segment used by `new`.
+const SEGMENT_SIZE: usize = 64;
+
+/// SPSC queue without a capacity limit, a linked list of fixed size segments the producer
+/// appends to whenever the last one is full.
+///
+/// Pushing never blocks and never drops an item, it only allocates when it moves on to a new
+/// segment. The consumer hands the segment it finished back to the producer to be reused, so a
+/// queue whose length stays bounded stops allocating after warming up.
+pub struct UnboundedRingBuffer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Writing half of a split `UnboundedRingBuffer`.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+    tail: *mut Segment<T>,
+    // Next slot to write in `tail`.
+    write: usize,
+}
+
+unsafe impl<T: Send> Send for Producer<T> { }
+
+/// Reading half of a split `UnboundedRingBuffer`.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+unsafe impl<T: Send> Send for Consumer<T> { }
+
+struct Segment<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // Number of slots the producer published, only the slots below it are initialized.
+    written: AtomicUsize,
+    next: AtomicPtr<Segment<T>>,
+}
+
+struct Shared<T> {
+    segment_size: usize,
+    // The segment the consumer reads from and its next slot there, only the consumer touches
+    // them while split.
+    head: UnsafeCell<*mut Segment<T>>,
+    read: UnsafeCell<usize>,
+    // A drained segment waiting to be reused by the producer.
+    spare: AtomicPtr<Segment<T>>,
+    pushed: CachePadded<AtomicUsize>,
+    popped: CachePadded<AtomicUsize>,
+    pop_waiter: Waiter,
+    // Set once either half is dropped.
+    abandoned: AtomicBool,
+}
+
+impl<T> Segment<T> {
+    fn alloc(size: usize) -> *mut Segment<T> {
+        Box::into_raw(Box::new(Segment {
+            slots: (0..size).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+            written: AtomicUsize::new(0),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+impl<T> Shared<T> {
+    /// Reuses the spare segment or allocates a new one.
+    fn segment(&self) -> *mut Segment<T> {
+        let spare = self.spare.swap(ptr::null_mut(), Ordering::Acquire);
+        if spare.is_null() {
+            Segment::alloc(self.segment_size)
+        } else {
+            spare
+        }
+    }
+
+    /// Hands a drained segment to the producer, freeing the previous spare if it wasn't used.
+    unsafe fn recycle(&self, segment: *mut Segment<T>) {
+        (*segment).written.store(0, Ordering::Relaxed);
+        (*segment).next.store(ptr::null_mut(), Ordering::Relaxed);
+        let old = self.spare.swap(segment, Ordering::AcqRel);
+        if !old.is_null() {
+            drop(Box::from_raw(old));
+        }
+    }
+
+    fn len(&self) -> usize {
+        let popped = self.popped.load(Ordering::Acquire);
+        self.pushed.load(Ordering::Acquire).wrapping_sub(popped)
+    }
+}
+
+impl<T> UnboundedRingBuffer<T> {
+    pub fn new() -> UnboundedRingBuffer<T> {
+        UnboundedRingBuffer::with_segment_size(SEGMENT_SIZE)
+    }
+
+    /// Like `new` but with `size` slots per segment, the unit memory is allocated in.
+    pub fn with_segment_size(size: usize) -> UnboundedRingBuffer<T> {
+        assert!(size > 0, "segment size must not be zero");
+        UnboundedRingBuffer {
+            shared: Arc::new(Shared {
+                segment_size: size,
+                head: UnsafeCell::new(Segment::alloc(size)),
+                read: UnsafeCell::new(0),
+                spare: AtomicPtr::new(ptr::null_mut()),
+                pushed: CachePadded::new(AtomicUsize::new(0)),
+                popped: CachePadded::new(AtomicUsize::new(0)),
+                pop_waiter: Waiter::new(),
+                abandoned: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Splits the queue into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let tail = unsafe { *self.shared.head.get() };
+        let producer = Producer { shared: self.shared.clone(), tail, write: 0 };
+        (producer, Consumer { shared: self.shared })
+    }
+}
+
+impl<T> Default for UnboundedRingBuffer<T> {
+    fn default() -> UnboundedRingBuffer<T> {
+        UnboundedRingBuffer::new()
+    }
+}
+
+impl<T> Producer<T> {
+    /// Pushes an item, only failing if the consumer is gone.
+    pub fn push(&mut self, item: T) -> Result<(), PushError<T>> {
+        let shared = &*self.shared;
+        if shared.abandoned.load(Ordering::Relaxed) {
+            return Err(PushError::Disconnected(item));
+        }
+        unsafe {
+            if self.write == shared.segment_size {
+                let next = shared.segment();
+                (*self.tail).next.store(next, Ordering::Release);
+                self.tail = next;
+                self.write = 0;
+            }
+            let tail = &*self.tail;
+            (*tail.slots[self.write].get()).write(item);
+            self.write += 1;
+            tail.written.store(self.write, Ordering::Release);
+        }
+        shared.pushed.store(shared.pushed.load(Ordering::Relaxed).wrapping_add(1),
+                            Ordering::Release);
+        shared.pop_waiter.notify();
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the consumer has been dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.shared.abandoned.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.shared.abandoned.store(true, Ordering::Release);
+        self.shared.pop_waiter.notify();
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Blocks until an item is available, failing once the producer is gone and the queue is
+    /// drained.
+    #[cfg(feature = "std")]
+    pub fn pop(&mut self) -> Result<T, Disconnected> {
+        loop {
+            if let Some(item) = self.try_pop() {
+                return Ok(item);
+            }
+            if self.is_abandoned() {
+                // The producer may have pushed right before it was dropped.
+                return self.try_pop().ok_or(Disconnected);
+            }
+            let shared = &*self.shared;
+            shared.pop_waiter.wait(|| shared.len() != 0 || self.is_abandoned());
+        }
+    }
+
+    pub fn try_pop(&mut self) -> Option<T> {
+        let shared = &*self.shared;
+        unsafe {
+            let head = &mut *shared.head.get();
+            let read = &mut *shared.read.get();
+            if *read == shared.segment_size {
+                let next = (**head).next.load(Ordering::Acquire);
+                if next.is_null() {
+                    return None;
+                }
+                // The producer moved on for good, the segment is ours to hand back.
+                shared.recycle(*head);
+                *head = next;
+                *read = 0;
+            }
+            if *read == (**head).written.load(Ordering::Acquire) {
+                return None;
+            }
+            let item = (*(**head).slots[*read].get()).assume_init_read();
+            *read += 1;
+            shared.popped.store(shared.popped.load(Ordering::Relaxed).wrapping_add(1),
+                                Ordering::Release);
+            Some(item)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the producer has been dropped, there may still be items left to pop.
+    pub fn is_abandoned(&self) -> bool {
+        self.shared.abandoned.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.shared.abandoned.store(true, Ordering::Release);
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let mut segment = *self.head.get();
+            let mut read = *self.read.get();
+            while !segment.is_null() {
+                let boxed = Box::from_raw(segment);
+                let written = boxed.written.load(Ordering::Relaxed);
+                for slot in &boxed.slots[read.min(written)..written] {
+                    (*slot.get()).assume_init_drop();
+                }
+                segment = boxed.next.load(Ordering::Relaxed);
+                read = 0;
+            }
+            let spare = self.spare.load(Ordering::Relaxed);
+            if !spare.is_null() {
+                drop(Box::from_raw(spare));
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use {Disconnected, PushError, UnboundedRingBuffer};
+    use std::rc::Rc;
+    use std::thread;
+
+    #[test]
+    fn never_full() {
+        let (mut p, mut c) = UnboundedRingBuffer::with_segment_size(2).split();
+        for i in 0..5 {
+            p.push(i).unwrap();
+        }
+        assert_eq!(c.len(), 5);
+        assert_eq!((0..5).map(|_| c.try_pop().unwrap()).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(c.try_pop(), None);
+        p.push(5).unwrap();
+        assert_eq!(c.try_pop(), Some(5));
+        drop(p);
+        assert_eq!(c.pop(), Err(Disconnected));
+    }
+
+    #[test]
+    fn drops_unread() {
+        let item = Rc::new(());
+        {
+            let (mut p, mut c) = UnboundedRingBuffer::with_segment_size(2).split();
+            for _ in 0..5 {
+                p.push(item.clone()).unwrap();
+            }
+            c.try_pop().unwrap();
+            drop(c);
+            assert!(matches!(p.push(item.clone()), Err(PushError::Disconnected(_))));
+        }
+        assert_eq!(Rc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn threads() {
+        let (mut p, mut c) = UnboundedRingBuffer::with_segment_size(8).split();
+        let t = thread::spawn(move || {
+            for i in 0..100_000 {
+                p.push(i).unwrap();
+            }
+        });
+        for i in 0..100_000 {
+            assert_eq!(c.pop(), Ok(i));
+        }
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+//! How blocked threads wait for the other side of a buffer.
+//!
+//! Buffers park blocked threads by default, pass another strategy to `with_strategy` to trade
+//! CPU time for latency. Async operations always register their task, whatever the strategy.
+//!
+//! How often pushes wake a blocked consumer is set separately with a `NotifyPolicy`.
+
+use std::hint;
+use std::thread;
+use std::time::Duration;
+
+/// Decides what a thread does while it is blocked on a buffer.
+pub trait WaitStrategy: Send + Sync {
+    /// Whether blocked threads park until the other side wakes them. If not, `idle` is called
+    /// between checks of the buffer instead.
+    fn parks(&self) -> bool {
+        false
+    }
+
+    /// Passes some time before the buffer is checked again, `step` counts the checks so far.
+    fn idle(&self, step: u32);
+}
+
+/// Parks the thread until it is woken, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Park;
+
+impl WaitStrategy for Park {
+    fn parks(&self) -> bool {
+        true
+    }
+
+    fn idle(&self, _: u32) {}
+}
+
+/// Busy-spins, lowest latency but burns a core for as long as it waits.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Spin;
+
+impl WaitStrategy for Spin {
+    fn idle(&self, _: u32) {
+        hint::spin_loop();
+    }
+}
+
+/// Yields to the scheduler between checks.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Yield;
+
+impl WaitStrategy for Yield {
+    fn idle(&self, _: u32) {
+        thread::yield_now();
+    }
+}
+
+/// Sleeps for a fixed interval between checks.
+#[derive(Clone, Copy, Debug)]
+pub struct Sleep(pub Duration);
+
+impl WaitStrategy for Sleep {
+    fn idle(&self, _: u32) {
+        thread::sleep(self.0);
+    }
+}
+
+/// When the halves of a `RingBuffer` wake the other one blocked on it, see
+/// `RingBuffer::with_notify_policy`. Dropping either half always wakes the other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotifyPolicy {
+    /// On every push, the default.
+    Every,
+    /// Once `items` were pushed since the last wake-up, or on the first push at least
+    /// `latency` after the oldest one still waiting to be announced. `items` is capped at the
+    /// capacity of the buffer.
+    ///
+    /// A blocked consumer thread also checks again every `latency` by itself, so an item is
+    /// seen within about that long even if nothing follows it. Async consumers only ever hear
+    /// of pushes, call `Producer::notify_consumer` after the last one of a burst so they aren't
+    /// left waiting.
+    Batch { items: usize, latency: Duration },
+    /// Pops only wake a producer blocked on a full buffer once fewer than `low` items are
+    /// left, and pushes only wake a consumer blocked on an empty one once more than `high` are
+    /// buffered, so neither is woken again and again for a single slot near the boundary.
+    /// `low` is at least one and `high` below the capacity, so a full buffer always wakes the
+    /// consumer and an empty one the producer.
+    ///
+    /// A producer trickling items which never pass `high` leaves the consumer asleep until it
+    /// calls `Producer::notify_consumer` or is dropped.
+    Watermarks { low: usize, high: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NotifyPolicy, Sleep, Spin, Yield, WaitStrategy};
+    use ::RingBuffer;
+    use std::thread;
+    use std::time::Duration;
+
+    fn transfer<S: WaitStrategy + 'static>(strategy: S) {
+        let (mut p, mut c) = RingBuffer::with_strategy(2, strategy).split();
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                p.push(i).unwrap();
+            }
+        });
+        for i in 0..100 {
+            assert_eq!(c.pop(), Ok(i));
+        }
+        t.join().unwrap();
+        assert!(c.pop().is_err());
+    }
+
+    #[test]
+    fn spin() {
+        transfer(Spin);
+    }
+
+    #[test]
+    fn yield_now() {
+        transfer(Yield);
+    }
+
+    #[test]
+    fn sleep() {
+        transfer(Sleep(Duration::from_micros(10)));
+    }
+
+    #[test]
+    fn batched_notify() {
+        use std::time::Instant;
+
+        let policy = NotifyPolicy::Batch { items: 8, latency: Duration::from_millis(20) };
+        let (mut p, mut c) = RingBuffer::with_notify_policy(16, policy).split();
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                p.push(i).unwrap();
+            }
+            thread::sleep(Duration::from_millis(50));
+            // Held back but picked up once the consumer checks again by itself.
+            p.push(100).unwrap();
+            thread::sleep(Duration::from_secs(1));
+        });
+        for i in 0..100 {
+            assert_eq!(c.pop(), Ok(i));
+        }
+        let start = Instant::now();
+        assert_eq!(c.pop(), Ok(100));
+        assert!(start.elapsed() < Duration::from_millis(500));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn watermarks() {
+        use std::time::Instant;
+
+        let policy = NotifyPolicy::Watermarks { low: 2, high: 4 };
+        let (mut p, mut c) = RingBuffer::with_notify_policy(8, policy).split();
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            p.push(0).unwrap();
+            thread::sleep(Duration::from_millis(500));
+            for i in 1..1000 {
+                p.push(i).unwrap();
+            }
+        });
+        // A single item doesn't pass the high watermark, only the deadline ends the wait.
+        let start = Instant::now();
+        assert_eq!(c.pop_timeout(Duration::from_millis(300)), Ok(0));
+        assert!(start.elapsed() >= Duration::from_millis(250));
+        for i in 1..1000 {
+            assert_eq!(c.pop(), Ok(i));
+        }
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn timeout_without_parking() {
+        let (_p, mut c) = RingBuffer::<u8>::with_strategy(1, Yield).split();
+        assert!(c.pop_timeout(Duration::from_millis(10)).is_err());
+    }
+}
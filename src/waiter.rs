@@ -0,0 +1,350 @@
+#[cfg(feature = "std")]
+use std::sync::atomic::{self, AtomicUsize};
+#[cfg(feature = "std")]
+use sync::atomic::Ordering;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex, MutexGuard};
+#[cfg(all(feature = "std", any(target_family = "wasm", feature = "rt-safe")))]
+use std::hint;
+#[cfg(all(feature = "std", any(target_family = "wasm", feature = "rt-safe")))]
+use std::sync::TryLockError;
+#[cfg(feature = "std")]
+use std::task::Waker;
+#[cfg(feature = "std")]
+use std::thread::{self, Thread};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+#[cfg(all(feature = "readiness", unix))]
+use readiness::Readiness;
+#[cfg(feature = "std")]
+use rt;
+#[cfg(feature = "std")]
+use wait::{Park, WaitStrategy};
+
+/// Parking spot for the threads and tasks blocked on one side of a buffer.
+#[cfg(feature = "std")]
+pub struct Waiter {
+    waiting: AtomicUsize,
+    entries: Mutex<Vec<Entry>>,
+    strategy: Arc<dyn WaitStrategy>,
+    batch: Option<Batch>,
+    threshold: Option<Threshold>,
+}
+
+/// Occupancy of the buffer a `NotifyPolicy::Watermarks` lets wake-ups through at.
+#[cfg(feature = "std")]
+pub enum Threshold {
+    Below(usize),
+    Above(usize),
+}
+
+/// Wake-ups held back by a `NotifyPolicy::Batch`, only touched by the notifying side.
+#[cfg(feature = "std")]
+struct Batch {
+    items: usize,
+    latency: Duration,
+    epoch: Instant,
+    pending: AtomicUsize,
+    // Microseconds after `epoch` of the oldest push held back, wrapping, plus one so zero can
+    // mean none. An item would have to be held back for over an hour for the wrap to matter.
+    since: AtomicUsize,
+}
+
+#[cfg(feature = "std")]
+impl Batch {
+    /// Counts `n` pushed items, returns whether they should be announced now.
+    fn push(&self, n: usize) -> bool {
+        let pending = self.pending.load(Ordering::Relaxed) + n;
+        if pending >= self.items {
+            return true;
+        }
+        self.pending.store(pending, Ordering::Relaxed);
+        let now = (self.epoch.elapsed().as_micros() as usize).wrapping_add(1);
+        let since = self.since.load(Ordering::Relaxed);
+        if since == 0 {
+            self.since.store(now, Ordering::Relaxed);
+            return false;
+        }
+        now.wrapping_sub(since) as u128 >= self.latency.as_micros()
+    }
+
+    fn reset(&self) {
+        self.pending.store(0, Ordering::Relaxed);
+        self.since.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "std")]
+enum Entry {
+    Thread(Thread),
+    Task(Waker),
+    #[cfg(all(feature = "readiness", unix))]
+    Readiness(Arc<Readiness>),
+}
+
+#[cfg(feature = "std")]
+impl Entry {
+    fn wake(self) {
+        match self {
+            Entry::Thread(thread) => thread.unpark(),
+            Entry::Task(waker) => rt::outside(|| waker.wake()),
+            #[cfg(all(feature = "readiness", unix))]
+            Entry::Readiness(readiness) => readiness.signal(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Waiter {
+    pub fn new() -> Waiter {
+        Waiter::with_strategy(Arc::new(Park))
+    }
+
+    pub fn with_strategy(strategy: Arc<dyn WaitStrategy>) -> Waiter {
+        Waiter {
+            waiting: AtomicUsize::new(0),
+            entries: Mutex::new(Vec::new()),
+            strategy,
+            batch: None,
+            threshold: None,
+        }
+    }
+
+    /// Holds back `notify_pushed` until `items` were pushed or `latency` passed.
+    pub fn set_batch(&mut self, items: usize, latency: Duration) {
+        self.batch = Some(Batch {
+            items,
+            latency,
+            epoch: Instant::now(),
+            pending: AtomicUsize::new(0),
+            since: AtomicUsize::new(0),
+        });
+    }
+
+    /// Holds back `notify_pushed` and `notify_popped` unless they leave the buffer's occupancy
+    /// past `threshold`.
+    pub fn set_threshold(&mut self, threshold: Threshold) {
+        self.threshold = Some(threshold);
+    }
+
+    /// Parks the current thread until `ready` returns true.
+    pub fn wait<F: Fn() -> bool>(&self, ready: F) {
+        self.wait_until(ready, None);
+    }
+
+    /// Parks the current thread until `ready` returns true or `deadline` passes, returns whether
+    /// `ready` was satisfied.
+    pub fn wait_until<F: Fn() -> bool>(&self, ready: F, deadline: Option<Instant>) -> bool {
+        if ready() {
+            return true;
+        }
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::trace_span!("wait", deadline = ?deadline).entered();
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+        let satisfied = self.block(ready, deadline);
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!(waited = ?start.elapsed(), satisfied, "blocking wait finished");
+        satisfied
+    }
+
+    /// The slow path of `wait_until`, once `ready` returned false.
+    fn block<F: Fn() -> bool>(&self, ready: F, deadline: Option<Instant>) -> bool {
+        if !self.strategy.parks() {
+            let mut step = 0;
+            loop {
+                if ready() {
+                    return true;
+                }
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return false;
+                }
+                self.strategy.idle(step);
+                step = step.saturating_add(1);
+            }
+        }
+        let current = thread::current();
+        let satisfied = loop {
+            self.register_thread(&current);
+            if ready() {
+                break true;
+            }
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break false;
+                    }
+                    thread::park_timeout(self.interval().map_or(deadline - now, |interval| {
+                        interval.min(deadline - now)
+                    }));
+                }
+                None => match self.interval() {
+                    Some(interval) => thread::park_timeout(interval),
+                    None => thread::park(),
+                },
+            }
+        };
+        self.unregister_thread(&current);
+        satisfied
+    }
+
+    /// Registers `thread` to be unparked on the next notify, the caller must check its
+    /// condition again afterwards.
+    pub fn register_thread(&self, thread: &Thread) {
+        self.add(|entry| match *entry {
+            Entry::Thread(ref t) => t.id() == thread.id(),
+            _ => false,
+        }, || Entry::Thread(thread.clone()));
+    }
+
+    /// Drops the registration of `thread` once it stops waiting.
+    pub fn unregister_thread(&self, thread: &Thread) {
+        let mut entries = self.entries();
+        entries.retain(|entry| match *entry {
+            Entry::Thread(ref t) => t.id() != thread.id(),
+            _ => true,
+        });
+        self.waiting.store(entries.len(), Ordering::Relaxed);
+    }
+
+    /// Registers a task to be woken on the next notify, the caller must check its condition
+    /// again afterwards.
+    pub fn register(&self, waker: &Waker) {
+        self.add(|entry| match *entry {
+            Entry::Task(ref w) => w.will_wake(waker),
+            _ => false,
+        }, || Entry::Task(waker.clone()));
+    }
+
+    /// Registers a readiness descriptor to be signalled on the next notify.
+    #[cfg(all(feature = "readiness", unix))]
+    pub fn register_readiness(&self, readiness: &Arc<Readiness>) {
+        self.add(|entry| match *entry {
+            Entry::Readiness(ref r) => Arc::ptr_eq(r, readiness),
+            _ => false,
+        }, || Entry::Readiness(readiness.clone()));
+    }
+
+    /// Locks the registered entries.
+    #[cfg(not(any(target_family = "wasm", feature = "rt-safe")))]
+    fn entries(&self) -> MutexGuard<'_, Vec<Entry>> {
+        self.entries.lock().unwrap()
+    }
+
+    /// Locks the registered entries by spinning, a contended `lock` would fall back to
+    /// `Atomics.wait`, which traps on a browser's main thread, and is a syscall which real time
+    /// threads can't afford. Only ever held briefly, so it only yields after spinning for a
+    /// while.
+    #[cfg(any(target_family = "wasm", feature = "rt-safe"))]
+    fn entries(&self) -> MutexGuard<'_, Vec<Entry>> {
+        let mut spins = 0u32;
+        loop {
+            match self.entries.try_lock() {
+                Ok(entries) => return entries,
+                // Only a holder preempted on the same core takes this long.
+                Err(TryLockError::WouldBlock) if spins >= 100 => thread::yield_now(),
+                Err(TryLockError::WouldBlock) => hint::spin_loop(),
+                // A waker panicked while notify held it, the entries are left consistent.
+                Err(TryLockError::Poisoned(e)) => return e.into_inner(),
+            }
+            spins = spins.saturating_add(1);
+        }
+    }
+
+    fn add<F: Fn(&Entry) -> bool, N: FnOnce() -> Entry>(&self, present: F, entry: N) {
+        {
+            let mut entries = self.entries();
+            if !entries.iter().any(present) {
+                entries.push(entry());
+            }
+            self.waiting.store(entries.len(), Ordering::SeqCst);
+        }
+        // Pairs with the fence in notify, either the caller's next check sees the other side's
+        // progress or notify sees our registration.
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    /// Longest a parked thread goes without checking again, pushes may not wake it sooner.
+    fn interval(&self) -> Option<Duration> {
+        self.batch.as_ref().map(|batch| batch.latency)
+    }
+
+    /// Wakes the waiting threads and tasks after `n` items were pushed, unless the policy
+    /// holds the wake-up back for more. `len` is the occupancy, only asked for by a threshold.
+    pub fn notify_pushed<F: FnOnce() -> usize>(&self, n: usize, len: F) {
+        if !self.passes(len) {
+            return;
+        }
+        if let Some(ref batch) = self.batch {
+            if !batch.push(n) {
+                return;
+            }
+        }
+        self.notify();
+    }
+
+    /// Wakes the waiting threads and tasks after items were popped, unless the threshold holds
+    /// the wake-up back.
+    pub fn notify_popped<F: FnOnce() -> usize>(&self, len: F) {
+        if self.passes(len) {
+            self.notify();
+        }
+    }
+
+    /// Whether an occupancy of `len` lets a wake-up through.
+    #[inline]
+    fn passes<F: FnOnce() -> usize>(&self, len: F) -> bool {
+        match self.threshold {
+            None => true,
+            Some(Threshold::Below(low)) => len() < low,
+            Some(Threshold::Above(high)) => len() > high,
+        }
+    }
+
+    /// Wakes all waiting threads and tasks, if any.
+    pub fn notify(&self) {
+        if let Some(ref batch) = self.batch {
+            batch.reset();
+        }
+        atomic::fence(Ordering::SeqCst);
+        #[cfg(feature = "rt-safe")]
+        if self.waiting.load(Ordering::Relaxed) != 0 {
+            // Woken under the lock rather than collected first, so waking never allocates.
+            let mut entries = self.entries();
+            self.waiting.store(0, Ordering::Relaxed);
+            for entry in entries.drain(..) {
+                entry.wake();
+            }
+        }
+        #[cfg(not(feature = "rt-safe"))]
+        if self.waiting.load(Ordering::Relaxed) != 0 {
+            let entries: Vec<_> = {
+                let mut entries = self.entries();
+                self.waiting.store(0, Ordering::Relaxed);
+                entries.drain(..).collect()
+            };
+            for entry in entries {
+                entry.wake();
+            }
+        }
+    }
+}
+
+/// Without `std` nothing can block, so there is never anyone to wake.
+#[cfg(not(feature = "std"))]
+pub struct Waiter;
+
+#[cfg(not(feature = "std"))]
+impl Waiter {
+    pub fn new() -> Waiter {
+        Waiter
+    }
+
+    pub fn notify(&self) {}
+
+    pub fn notify_pushed<F: FnOnce() -> usize>(&self, _: usize, _: F) {}
+
+    pub fn notify_popped<F: FnOnce() -> usize>(&self, _: F) {}
+}
@@ -0,0 +1,160 @@
+use core::cell::UnsafeCell;
+use sync::atomic::{AtomicUsize, Ordering};
+use sync::Arc;
+
+use cache_padded::CachePadded;
+
+/// Set in `middle` while it holds a value the consumer hasn't picked up yet.
+const FRESH: usize = 4;
+
+/// Lockfree single value cell, the producer overwrites the value and the consumer reads the
+/// latest one.
+///
+/// This is a triple buffer: the producer writes into a spare slot and swaps it with the middle
+/// one, the consumer swaps the middle slot with the one it reads from when it is fresh. Neither
+/// side ever waits for the other and values are never copied. Every published value carries a
+/// version counting the values published so far, the initial one has version 0.
+pub struct Watch<T> {
+    shared: Shared<T>,
+}
+
+struct Shared<T> {
+    slots: [UnsafeCell<Slot<T>>; 3],
+    // Index of the middle slot, or'd with `FRESH`.
+    middle: CachePadded<AtomicUsize>,
+}
+
+struct Slot<T> {
+    value: Option<T>,
+    version: u64,
+}
+
+/// Writing half of a split `Watch`.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+    back: usize,
+    version: u64,
+}
+
+unsafe impl<T: Send> Send for Producer<T> { }
+
+/// Reading half of a split `Watch`.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+    front: usize,
+}
+
+unsafe impl<T: Send> Send for Consumer<T> { }
+
+impl<T> Watch<T> {
+    pub fn new(initial: T) -> Watch<T> {
+        let slot = |value| UnsafeCell::new(Slot { value, version: 0 });
+        Watch {
+            shared: Shared {
+                slots: [slot(Some(initial)), slot(None), slot(None)],
+                middle: CachePadded::new(AtomicUsize::new(1)),
+            },
+        }
+    }
+
+    /// Splits the cell into a producer and consumer which can be used from separate threads.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let shared = Arc::new(self.shared);
+        (Producer { shared: shared.clone(), back: 2, version: 0 }, Consumer { shared, front: 0 })
+    }
+}
+
+impl<T> Producer<T> {
+    /// Replaces the value, a value the consumer hasn't picked up yet is dropped.
+    pub fn publish(&mut self, value: T) {
+        self.version += 1;
+        let slot = Slot { value: Some(value), version: self.version };
+        unsafe { *self.shared.slots[self.back].get() = slot; }
+        let old = self.shared.middle.swap(self.back | FRESH, Ordering::AcqRel);
+        self.back = old & !FRESH;
+    }
+
+    /// Version of the last published value.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Whether a newer value than the one `get` returns has been published.
+    pub fn has_update(&self) -> bool {
+        self.shared.middle.load(Ordering::Relaxed) & FRESH != 0
+    }
+
+    /// Picks up the latest published value, returns false if there was none newer.
+    pub fn update(&mut self) -> bool {
+        if !self.has_update() {
+            return false;
+        }
+        let old = self.shared.middle.swap(self.front, Ordering::AcqRel);
+        self.front = old & !FRESH;
+        true
+    }
+
+    /// The value picked up last.
+    pub fn get(&self) -> &T {
+        let slot = unsafe { &*self.shared.slots[self.front].get() };
+        slot.value.as_ref().expect("watch slot without a value")
+    }
+
+    /// Picks up the latest published value and returns it.
+    pub fn latest(&mut self) -> &T {
+        self.update();
+        self.get()
+    }
+
+    /// Version of the value `get` returns.
+    pub fn version(&self) -> u64 {
+        unsafe { (*self.shared.slots[self.front].get()).version }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::Watch;
+    use std::thread;
+
+    #[test]
+    fn latest_value() {
+        let (mut p, mut c) = Watch::new("initial".to_string()).split();
+        assert_eq!(c.get(), "initial");
+        assert_eq!(c.version(), 0);
+        assert!(!c.update());
+        p.publish("one".to_string());
+        p.publish("two".to_string());
+        assert!(c.has_update());
+        assert_eq!(c.latest(), "two");
+        assert_eq!(c.version(), 2);
+        assert!(!c.has_update());
+        p.publish("three".to_string());
+        assert_eq!(c.get(), "two");
+        assert!(c.update());
+        assert_eq!(c.get(), "three");
+        assert_eq!(p.version(), 3);
+    }
+
+    #[test]
+    fn threads() {
+        let (mut p, mut c) = Watch::new((0u64, 0u64)).split();
+        let t = thread::spawn(move || {
+            for i in 1..=10_000 {
+                p.publish((i, i * 2));
+            }
+        });
+        let mut last = 0;
+        while last < 10_000 {
+            let &(a, b) = c.latest();
+            // Values are never torn and never go back in time.
+            assert_eq!(b, a * 2);
+            assert!(a >= last);
+            assert_eq!(c.version(), a);
+            last = a;
+        }
+        t.join().unwrap();
+    }
+}